@@ -0,0 +1,309 @@
+use crate::btree::BTree;
+use crate::error::Error;
+use crate::header::Header;
+use crate::node::Node;
+use crate::node_type::{NodeType, Offset};
+use crate::page::Page;
+use crate::page_layout::{header_slots, FORMAT_VERSION, PAGE_SIZE};
+use crate::pager::Pager;
+use crate::wal::{Record, Wal};
+use std::collections::HashMap;
+use std::convert::TryFrom;
+use std::path::Path;
+
+/// Frame is a node being copied along with the number of its children copied so far,
+/// for leaves it tells whether the data page was copied.
+struct Frame {
+    node: Node,
+    offset: Offset,
+    next: usize,
+}
+
+/// Backup is a compact copy of a tree, and its indexes, in a file of its own.
+/// The backup pins the header of the last commit and copies the pages reachable from its roots,
+/// since committed pages are never overwritten the tree can keep committing while the copy is in progress.
+/// Pages are written using the newest layout following their children so the backup is only made of reachable pages,
+/// its header is written once every page is copied. The change log is not copied.
+pub struct Backup {
+    pager: Pager,
+    wal: Wal,
+    /// The header of the commit being copied.
+    pinned: Header,
+    /// The offset in the backup of every page copied from the tree, keyed by its offset in the tree.
+    copied: HashMap<usize, Offset>,
+    /// The copied roots, the root of the tree followed by the roots of its indexes.
+    roots: Vec<Offset>,
+    stack: Vec<Frame>,
+    complete: bool,
+    /// The slot sequence number of the next header written, see Header::slot.
+    slot_seq: u64,
+}
+
+impl Backup {
+    /// new creates the backup file at the given path pinning the commit described by the given header,
+    /// the file must not exist or be empty.
+    pub(crate) fn new(btree: &mut BTree, path: &Path, pinned: Header) -> Result<Backup, Error> {
+        let backend = btree.backend();
+        let mut pager = Pager::new(backend.open(path)?)?;
+        if !pager.is_empty() {
+            return Err(Error::UnexpectedError);
+        }
+        let cipher = btree.cipher();
+        if let Some(cipher) = &cipher {
+            pager.set_cipher(cipher.clone())?;
+        }
+        // Reserve the header slots, the header is written once the copy completes.
+        for _ in 0..header_slots(FORMAT_VERSION) {
+            pager.write_page(Page::new([0x00; PAGE_SIZE]))?;
+        }
+        let mut wal_path = path.as_os_str().to_owned();
+        wal_path.push(".wal");
+        let mut wal = Wal::new(backend.open(Path::new(&wal_path))?, cipher)?;
+        wal.truncate(0)?;
+        Ok(Backup {
+            pager,
+            wal,
+            pinned,
+            copied: HashMap::new(),
+            roots: vec![],
+            stack: vec![],
+            complete: false,
+            slot_seq: 0,
+        })
+    }
+
+    /// pin pins the last commit of the tree, an incremental backup then only copies
+    /// the pages appended since the commit previously copied as the pages already copied are reused.
+    pub fn pin(&mut self, btree: &BTree) {
+        self.pinned = btree.header().clone();
+        self.roots.clear();
+        self.stack.clear();
+        self.complete = false;
+    }
+
+    /// update brings a complete backup up to date with the last commit of the tree.
+    pub fn update(&mut self, btree: &mut BTree) -> Result<(), Error> {
+        self.pin(btree);
+        self.step(btree, usize::MAX)?;
+        Ok(())
+    }
+
+    /// step copies up to num_pages pages of the pinned commit, the tree may be modified between steps.
+    /// Returns true once the backup is complete and its header is written.
+    pub fn step(&mut self, btree: &mut BTree, num_pages: usize) -> Result<bool, Error> {
+        let mut written = 0;
+        while !self.complete && written < num_pages {
+            let frame = match self.stack.last_mut() {
+                Some(frame) => frame,
+                None => {
+                    let root = match self.pinned_roots().get(self.roots.len()) {
+                        Some(root) => root.clone(),
+                        None => {
+                            self.commit(btree.now())?;
+                            break;
+                        }
+                    };
+                    match self.copied.get(&root.0) {
+                        Some(copy) => self.roots.push(copy.clone()),
+                        None => self.stack.push(Frame {
+                            node: btree.read_node(&root)?,
+                            offset: root,
+                            next: 0,
+                        }),
+                    }
+                    continue;
+                }
+            };
+            match &mut frame.node.node_type {
+                NodeType::Internal(children, _) if frame.next < children.len() => {
+                    let child = children[frame.next].clone();
+                    match self.copied.get(&child.0) {
+                        Some(copy) => {
+                            children[frame.next] = copy.clone();
+                            frame.next += 1;
+                        }
+                        None => {
+                            let node = btree.read_node(&child)?;
+                            self.stack.push(Frame {
+                                node,
+                                offset: child,
+                                next: 0,
+                            });
+                        }
+                    }
+                    continue;
+                }
+                NodeType::Leaf(data_offset, _) if frame.next == 0 => {
+                    // A data page may be shared by several versions of a leaf, it is only copied once.
+                    *data_offset = match self.copied.get(&data_offset.0) {
+                        Some(copy) => copy.clone(),
+                        None => {
+                            let data_page = btree.read_data_page(data_offset)?;
+                            let copy = self.pager.write_page(Page::try_from(&data_page)?)?;
+                            self.copied.insert(data_offset.0, copy.clone());
+                            written += 1;
+                            copy
+                        }
+                    };
+                    frame.next = 1;
+                    continue;
+                }
+                NodeType::Unexpected => return Err(Error::UnexpectedError),
+                _ => {}
+            }
+            // Every page the node points at was copied, copy the node itself.
+            let frame = self.stack.pop().ok_or(Error::UnexpectedError)?;
+            let copy = self.pager.write_page(Page::try_from(&frame.node)?)?;
+            written += 1;
+            self.copied.insert(frame.offset.0, copy.clone());
+            match self.stack.last_mut() {
+                Some(parent) => match &mut parent.node.node_type {
+                    NodeType::Internal(children, _) => {
+                        children[parent.next] = copy;
+                        parent.next += 1;
+                    }
+                    _ => return Err(Error::UnexpectedError),
+                },
+                None => self.roots.push(copy),
+            }
+        }
+        Ok(self.complete)
+    }
+
+    /// pinned_roots returns the roots of the pinned commit, the root of the tree followed by the roots of its indexes.
+    fn pinned_roots(&self) -> Vec<Offset> {
+        let mut roots = vec![self.pinned.root.clone()];
+        roots.extend(self.pinned.indexes.iter().map(|(_, root)| root.clone()));
+        roots
+    }
+
+    /// commit commits the copied roots by appending the root to the wal and writing the header of the backup
+    /// once the copied pages are durable.
+    fn commit(&mut self, now: u64) -> Result<(), Error> {
+        let mut header = self.pinned.clone();
+        header.version = FORMAT_VERSION;
+        header.free_list_head = None;
+        header.slot_seq = self.slot_seq;
+        header.root = self.roots.first().ok_or(Error::UnexpectedError)?.clone();
+        header.indexes = self
+            .pinned
+            .indexes
+            .iter()
+            .zip(self.roots.iter().skip(1))
+            .map(|((name, _), root)| (name.clone(), root.clone()))
+            .collect();
+        // The copied pages have to be durable before the header points at them, see BTree::write_commit.
+        self.pager.sync()?;
+        self.wal.append(&Record {
+            root: header.root.clone(),
+            seq: header.last_seq,
+            timestamp: now,
+        })?;
+        self.pager.write_header(&header)?;
+        self.pager.sync()?;
+        self.slot_seq += 1;
+        self.complete = true;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::error::Error;
+
+    #[test]
+    fn backup_works() -> Result<(), Error> {
+        use crate::btree::BTreeBuilder;
+        use crate::node_type::Key;
+        use std::path::Path;
+
+        let path = Path::new("/tmp/backup_works/db");
+        let backup_path = Path::new("/tmp/backup_works/backup");
+        std::fs::create_dir_all(path.parent().unwrap())?;
+        let _ = std::fs::remove_file(path);
+        let _ = std::fs::remove_file(backup_path);
+        let upper = |value: &str| Some(value.to_uppercase());
+        let builder = BTreeBuilder::new()
+            .path(path)
+            .b_parameter(2)
+            .index("upper", upper);
+        let backup_builder = BTreeBuilder::new()
+            .path(backup_path)
+            .b_parameter(2)
+            .index("upper", upper);
+
+        let mut btree = builder.build()?;
+        for key in ["a", "b", "c", "d", "e", "f", "g"] {
+            btree.insert(key.to_string(), format!("value {}", key))?;
+        }
+        btree.delete(Key("c".to_string()))?;
+
+        // The tree keeps committing while the backup is in progress.
+        let mut backup = btree.start_backup(backup_path)?;
+        assert!(!backup.step(&mut btree, 2)?);
+        btree.insert("h".to_string(), "value h".to_string())?;
+        btree.delete(Key("a".to_string()))?;
+        while !backup.step(&mut btree, 2)? {}
+
+        let mut copy = backup_builder.build()?;
+        assert_eq!(copy.search("a".to_string())?, "value a");
+        assert_eq!(
+            copy.search_by_index("upper", "VALUE G".to_string())?,
+            "value g"
+        );
+        for key in ["c", "h"] {
+            let res = copy.search(key.to_string());
+            assert!(matches!(res, Err(Error::KeyNotFound)));
+        }
+        drop(copy);
+        // Only the pages reachable from the pinned roots were copied.
+        let len = std::fs::metadata(backup_path)?.len();
+        assert!(len < std::fs::metadata(path)?.len());
+
+        btree.insert("i".to_string(), "value i".to_string())?;
+        backup.update(&mut btree)?;
+        let mut copy = backup_builder.build()?;
+        assert_eq!(copy.search("h".to_string())?, "value h");
+        assert_eq!(
+            copy.search_by_index("upper", "VALUE I".to_string())?,
+            "value i"
+        );
+        let res = copy.search("a".to_string());
+        assert!(matches!(res, Err(Error::KeyNotFound)));
+        Ok(())
+    }
+
+    #[test]
+    fn backup_survives_power_loss() -> Result<(), Error> {
+        use crate::btree::BTreeBuilder;
+        use crate::fault::FaultyBackend;
+        use crate::storage::MemoryBackend;
+        use std::path::Path;
+
+        let backend = FaultyBackend::new(MemoryBackend::new());
+        let mut btree = BTreeBuilder::new()
+            .path(Path::new("/db"))
+            .b_parameter(2)
+            .backend(backend.clone())
+            .build()?;
+        for key in ["a", "b", "c", "d", "e"] {
+            btree.insert(key.to_string(), format!("value {}", key))?;
+        }
+        let mut backup = btree.backup_to(Path::new("/backup"))?;
+        btree.insert("f".to_string(), "value f".to_string())?;
+        backup.update(&mut btree)?;
+        drop(btree);
+        // A completed backup is durable.
+        backend.power_loss()?;
+
+        let mut copy = BTreeBuilder::new()
+            .path(Path::new("/backup"))
+            .b_parameter(2)
+            .backend(backend)
+            .build()?;
+        for key in ["a", "b", "c", "d", "e", "f"] {
+            assert_eq!(copy.search(key.to_string())?, format!("value {}", key));
+        }
+        Ok(())
+    }
+}
@@ -1,17 +1,46 @@
-use crate::data_page::{self, DataPage};
+use crate::backup::Backup;
+use crate::catalog::Catalog;
+use crate::change_log::{Change, ChangeLog, ChangeOp, Changes, Subscriber};
+use crate::comparator::{Bytewise, Comparator};
+use crate::compression::Compression;
+use crate::data_page::DataPage;
+use crate::decoder::Decoder;
+use crate::dirty::{DirtyPage, DirtyPages};
+use crate::encryption::Cipher;
 use crate::error::Error;
+use crate::header::Header;
 use crate::node::Node;
 use crate::node_type::{Key, KeyValuePair, NodeType, Offset};
+use crate::node_view::Lookup;
 use crate::page::Page;
+use crate::page_layout::{header_slots, FORMAT_VERSION, MAX_KEY_PART_SIZE, PAGE_SIZE};
 use crate::pager::Pager;
-use crate::wal::Wal;
+use crate::replication::{read_batch, Replica};
+use crate::stats::{Counters, Stats};
+use crate::storage::{Backend, FileBackend};
+use crate::wal::{Record, Wal};
 use std::cmp;
 use std::convert::TryFrom;
-use std::path::Path;
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+use std::sync::Arc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 
 /// B+Tree properties.
 pub const MAX_BRANCHING_FACTOR: usize = 200;
 pub const NODE_KEYS_LIMIT: usize = MAX_BRANCHING_FACTOR - 1;
+/// Keys, index keys included, are at most MAX_KEY_SIZE bytes long so a key can be stored next to any other,
+/// longer keys are refused with KeyOverflowError when they are inserted.
+pub const MAX_KEY_SIZE: usize = MAX_KEY_PART_SIZE;
+
+/// check_key_size refuses keys longer than MAX_KEY_SIZE.
+pub(crate) fn check_key_size(key: &str) -> Result<(), Error> {
+    if key.len() > MAX_KEY_SIZE {
+        return Err(Error::KeyOverflowError);
+    }
+    Ok(())
+}
 
 /// BTree struct represents an on-disk B+tree.
 /// Each node is persisted in the table file, the leaf nodes contain the values.
@@ -19,6 +48,30 @@ pub struct BTree {
     pager: Pager,
     b: usize,
     wal: Wal,
+    header: Header,
+    decoder: Decoder,
+    compression: Compression,
+    indexes: Vec<Index>,
+    clock: Clock,
+    comparator: Rc<dyn Comparator>,
+    change_log: ChangeLog,
+    subscribers: Vec<Subscriber>,
+    replicas: Vec<Replica>,
+    backend: Rc<dyn Backend>,
+    /// Whether the tree refuses modifications, followers are always read-only.
+    read_only: bool,
+    /// Whether the tree is a follower of another tree, see BTree::apply_replication.
+    follower: bool,
+    counters: Arc<Counters>,
+    /// The pages of the pending commit, written once the commit completes.
+    dirty: DirtyPages,
+}
+
+/// Index is a secondary index, a tree stored in the same file mapping index keys to primary keys.
+struct Index {
+    name: String,
+    extractor: IndexExtractor,
+    root: Offset,
 }
 
 /// BtreeBuilder is a Builder for the BTree struct.
@@ -28,13 +81,116 @@ pub struct BTreeBuilder {
     /// The BTree parameter, an inner node contains no more than 2*b-1 keys and no less than b-1 keys
     /// and no more than 2*b children and no less than b children.
     b: usize,
+    /// The codec used to compress values written to data pages.
+    compression: Compression,
+    /// Provides the cipher encrypting the tree, the key is never written to disk.
+    cipher: Option<CipherProvider>,
+    /// The secondary indexes maintained alongside the tree.
+    indexes: Vec<(String, IndexExtractor)>,
+    /// The clock deciding when pairs expire.
+    clock: Clock,
+    /// The ordering of the keys, recorded in the file when the tree is created.
+    comparator: Rc<dyn Comparator>,
+    /// Whether the tree is a read-only follower of another tree, see BTree::apply_replication.
+    follower: bool,
+    /// Whether the files of the tree are opened without write permission.
+    read_only: bool,
+    /// Opens the storage of the tree file, its wal and its change log.
+    backend: Rc<dyn Backend>,
+}
+
+/// CipherProvider is a callback providing the cipher, and thus the key material, when the tree is opened.
+pub type CipherProvider = Box<dyn Fn() -> Result<Box<dyn Cipher>, Error>>;
+
+/// Clock returns the current time, it is replaceable for testing expiry.
+pub type Clock = Rc<dyn Fn() -> SystemTime>;
+
+/// millis returns the given time in milliseconds since the unix epoch.
+fn millis(time: SystemTime) -> u64 {
+    time.duration_since(UNIX_EPOCH)
+        .map(|elapsed| elapsed.as_millis() as u64)
+        .unwrap_or(0)
+}
+
+/// rollback_changes returns the changes turning the current pairs into the past ones,
+/// both given in the key order of the comparator.
+fn rollback_changes(
+    current: Vec<(KeyValuePair, String)>,
+    past: Vec<(KeyValuePair, String)>,
+    comparator: &dyn Comparator,
+) -> Result<Vec<Change>, Error> {
+    let mut changes = vec![];
+    let mut current = current.into_iter().peekable();
+    let mut past = past.into_iter().peekable();
+    loop {
+        let order = match (current.peek(), past.peek()) {
+            (Some((current_pair, _)), Some((past_pair, _))) => {
+                comparator.compare(&current_pair.key, &past_pair.key)
+            }
+            (Some(_), None) => cmp::Ordering::Less,
+            (None, Some(_)) => cmp::Ordering::Greater,
+            (None, None) => return Ok(changes),
+        };
+        match order {
+            // The key was inserted since.
+            cmp::Ordering::Less => {
+                let (pair, _) = current.next().ok_or(Error::UnexpectedError)?;
+                changes.push(Change::new(ChangeOp::Delete, pair.key, None));
+            }
+            // The key was deleted since.
+            cmp::Ordering::Greater => {
+                let (pair, value) = past.next().ok_or(Error::UnexpectedError)?;
+                changes.push(Change::new(ChangeOp::Insert, pair.key, Some(value)));
+            }
+            cmp::Ordering::Equal => {
+                let (current_pair, current_value) = current.next().ok_or(Error::UnexpectedError)?;
+                let (pair, value) = past.next().ok_or(Error::UnexpectedError)?;
+                if current_value != value || current_pair.expiry != pair.expiry {
+                    changes.push(Change::new(ChangeOp::Insert, pair.key, Some(value)));
+                }
+            }
+        }
+    }
+}
+
+/// Commit is a root committed to the tree, see BTree::history.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Commit {
+    /// The sequence number of the last change committed up until the root, see BTree::changes_since.
+    pub seq: u64,
+    pub root: Offset,
+    pub timestamp: SystemTime,
 }
 
+/// WriteOp is a write applied along with others in a single commit, see BTree::write_batch.
+#[derive(Clone, Debug)]
+pub enum WriteOp {
+    Insert(String, String),
+    /// Inserts a pair which expires once the given duration elapses, see BTree::insert_with_ttl.
+    InsertWithTtl(String, String, Duration),
+    Delete(Key),
+}
+
+/// Descent is the path of a root to leaf descent, each ancestor is stored along with
+/// its offset and the index of the child the descent continued to.
+type Descent = Vec<(Node, Offset, usize)>;
+
+/// IndexExtractor extracts the index key of a value, values without an index key return None.
+pub type IndexExtractor = Rc<dyn Fn(&str) -> Option<String>>;
+
 impl BTreeBuilder {
     pub fn new() -> BTreeBuilder {
         BTreeBuilder {
             path: Path::new(""),
             b: 0,
+            compression: Compression::None,
+            cipher: None,
+            indexes: vec![],
+            clock: Rc::new(SystemTime::now),
+            comparator: Rc::new(Bytewise),
+            follower: false,
+            read_only: false,
+            backend: Rc::new(FileBackend),
         }
     }
 
@@ -48,31 +204,227 @@ impl BTreeBuilder {
         self
     }
 
+    /// compression sets the codec used for newly written data pages,
+    /// pages already written using another codec remain readable.
+    pub fn compression(mut self, compression: Compression) -> BTreeBuilder {
+        self.compression = compression;
+        self
+    }
+
+    /// cipher sets a callback providing the cipher used to encrypt the tree and its wal at rest.
+    /// A new tree is encrypted if a callback is set, an existing tree has to be opened
+    /// with a callback if and only if it was created with one.
+    pub fn cipher(
+        mut self,
+        provider: impl Fn() -> Result<Box<dyn Cipher>, Error> + 'static,
+    ) -> BTreeBuilder {
+        self.cipher = Some(Box::new(provider));
+        self
+    }
+
+    /// index registers a unique secondary index under the given name, its entries map the key extracted
+    /// from each value to the value's key. Indexes are not remembered by the file, an index which is not
+    /// registered when the tree is opened is dropped while a newly registered one is built from the existing pairs.
+    pub fn index(
+        mut self,
+        name: &str,
+        extractor: impl Fn(&str) -> Option<String> + 'static,
+    ) -> BTreeBuilder {
+        self.indexes.push((name.to_string(), Rc::new(extractor)));
+        self
+    }
+
+    /// clock replaces the system clock used to decide when pairs expire.
+    pub fn clock(mut self, clock: impl Fn() -> SystemTime + 'static) -> BTreeBuilder {
+        self.clock = Rc::new(clock);
+        self
+    }
+
+    /// comparator sets the ordering of the keys, keys are ordered byte-wise by default.
+    /// The name of the comparator is recorded in the file when the tree is created,
+    /// opening the file with a comparator of another name fails with Error::ComparatorMismatch.
+    /// Secondary indexes and the trees of a catalog are ordered by the same comparator,
+    /// an existing tree is reordered by migrating it to a tree built using another comparator, see BTree::migrate.
+    pub fn comparator(mut self, comparator: impl Comparator + 'static) -> BTreeBuilder {
+        self.comparator = Rc::new(comparator);
+        self
+    }
+
+    /// follower opens the tree as a read-only follower of another tree, it is kept up to date
+    /// by applying the stream shipped by the other tree, see BTree::add_replica.
+    /// Secondary indexes cannot be registered on a follower.
+    pub fn follower(mut self, follower: bool) -> BTreeBuilder {
+        self.follower = follower;
+        self
+    }
+
+    /// read_only opens the files of an existing tree without write permission, every modification
+    /// fails with Error::ReadOnly. The tree file is locked by every open tree, a writer holds an exclusive lock
+    /// while readers hold a shared one, so any number of readers may open the file as long as no writer has it open.
+    /// Secondary indexes which are not registered are left in place, a registered index has to exist.
+    pub fn read_only(mut self, read_only: bool) -> BTreeBuilder {
+        self.read_only = read_only;
+        self
+    }
+
+    /// backend sets the backend storing the files of the tree, files are stored on disk by default.
+    /// The path names the files within the backend.
+    pub fn backend(mut self, backend: impl Backend + 'static) -> BTreeBuilder {
+        self.backend = Rc::new(backend);
+        self
+    }
+
+    /// build opens the tree file at path, an empty file is initialized with an empty tree
+    /// while an existing file has its header validated against the builder.
     pub fn build(&self) -> Result<BTree, Error> {
+        self.open(false)
+    }
+
+    /// build_catalog opens the file at path as a catalog of named trees, see the catalog module.
+    /// Secondary indexes cannot be registered on a catalog.
+    pub fn build_catalog(&self) -> Result<Catalog, Error> {
+        if !self.indexes.is_empty() || self.follower {
+            return Err(Error::UnexpectedError);
+        }
+        Ok(Catalog::new(self.open(true)?))
+    }
+
+    /// open opens the file at path, catalog tells whether the root is expected to be the root of a catalog.
+    fn open(&self, catalog: bool) -> Result<BTree, Error> {
         if self.path.to_string_lossy() == "" {
             return Err(Error::UnexpectedError);
         }
-        if self.b == 0 {
+        if self.b == 0 || self.comparator.name().is_empty() {
+            return Err(Error::UnexpectedError);
+        }
+        if self.follower && (!self.indexes.is_empty() || self.read_only) {
             return Err(Error::UnexpectedError);
         }
 
-        let mut pager = Pager::new(self.path)?;
+        let counters = Arc::new(Counters::default());
+        let mut storage = if self.read_only {
+            self.backend.open_read_only(self.path)?
+        } else {
+            self.backend.open(self.path)?
+        };
+        // A second writer would append pages over the pages of the first one, so writers exclude each other
+        // while readers attach to the file alongside a writer.
+        storage.lock(!self.read_only)?;
+        let mut pager = Pager::new(storage)?;
+        pager.set_counters(counters.clone());
+        let cipher: Option<Arc<dyn Cipher>> = match &self.cipher {
+            Some(provider) => Some(Arc::from(provider()?)),
+            None => None,
+        };
+
+        let created = pager.is_empty();
+        if created && self.read_only {
+            return Err(Error::ReadOnly);
+        }
+        if let Some(cipher) = &cipher {
+            pager.set_cipher(cipher.clone())?;
+        }
+        let header = if created {
+            // Reserve the header slots, the header is written once the root is known.
+            for _ in 0..header_slots(FORMAT_VERSION) {
+                pager.write_page(Page::new([0x00; PAGE_SIZE]))?;
+            }
+
+            let mut data_page = DataPage::new();
+            data_page.set_compression(self.compression);
+            let root_page_offset = pager.write_page(Page::try_from(&data_page)?)?;
 
-        let data_page = DataPage::new();
-        let root_page_offset = pager.write_page(Page::try_from(&data_page)?)?;
+            let root = Node::new(NodeType::Leaf(root_page_offset, vec![]), true);
+            let root_offset = pager.write_page(Page::try_from(&root)?)?;
 
-        let root = Node::new(NodeType::Leaf(root_page_offset, vec![]), true, None);
-        let root_offset = pager.write_page(Page::try_from(&root)?)?;
+            let mut header = Header::new(self.b, root_offset);
+            header.encrypted = cipher.is_some();
+            header.catalog = catalog;
+            header.comparator = self.comparator.name().to_string();
+            pager.write_header(&header)?;
+            header
+        } else {
+            // The header is stored in plain text, it tells whether the rest of the file is encrypted.
+            let header = pager.read_header(false)?;
+            let header = match &cipher {
+                // Authenticate the header now that the key is known.
+                Some(_) if header.encrypted => pager.read_header(true)?,
+                None if !header.encrypted => header,
+                _ => return Err(Error::EncryptionMismatch),
+            };
+            if header.b != self.b {
+                return Err(Error::BParameterMismatch);
+            }
+            if header.catalog != catalog {
+                return Err(Error::CatalogMismatch);
+            }
+            if header.comparator != self.comparator.name() {
+                return Err(Error::ComparatorMismatch);
+            }
+            header
+        };
 
-        let parent_directory = self.path.parent().unwrap_or_else(|| Path::new("/tmp"));
-        let mut wal = Wal::new(parent_directory.to_path_buf())?;
-        wal.set_root(root_offset)?;
+        // The wal is named after the tree file so trees sharing a directory keep their own wal.
+        let mut wal_path = self.path.as_os_str().to_owned();
+        wal_path.push(".wal");
+        let mut change_log_path = self.path.as_os_str().to_owned();
+        change_log_path.push(".changes");
+        let change_log = ChangeLog::new(
+            self.backend.clone(),
+            PathBuf::from(change_log_path),
+            cipher.clone(),
+            header.last_seq,
+            self.read_only,
+        )?;
+        let mut wal = if self.read_only {
+            Wal::read_only(self.backend.open_read_only(Path::new(&wal_path))?, cipher)?
+        } else {
+            Wal::new(self.backend.open(Path::new(&wal_path))?, cipher)?
+        };
+        if created {
+            // The wal of a file which was removed holds the history of another tree.
+            wal.truncate(0)?;
+        }
+        // Records following the last committed root belong to commits which never completed.
+        match wal
+            .records()?
+            .iter()
+            .rposition(|record| record.root == header.root)
+        {
+            Some(idx) => wal.truncate(idx + 1)?,
+            None => {
+                let written = wal.append(&Record {
+                    root: header.root.clone(),
+                    seq: header.last_seq,
+                    timestamp: millis((self.clock)()),
+                })?;
+                counters.wal_record(written);
+            }
+        }
 
-        Ok(BTree {
+        let mut btree = BTree {
+            dirty: DirtyPages::new(pager.len()),
             pager,
             b: self.b,
             wal,
-        })
+            decoder: Decoder::new(header.version)?,
+            header,
+            compression: self.compression,
+            indexes: vec![],
+            clock: self.clock.clone(),
+            comparator: self.comparator.clone(),
+            change_log,
+            subscribers: vec![],
+            replicas: vec![],
+            backend: self.backend.clone(),
+            read_only: self.follower || self.read_only,
+            follower: self.follower,
+            counters,
+        };
+        if !self.follower {
+            btree.open_indexes(&self.indexes)?;
+        }
+        Ok(btree)
     }
 }
 
@@ -88,6 +440,487 @@ impl Default for BTreeBuilder {
 }
 
 impl BTree {
+    /// commit commits a new root along with the new roots of the indexes, given in registration order,
+    /// by appending the root to the wal and recording all of them in the file header.
+    /// The given changes are numbered and appended to the change log before the header is written,
+    /// subscribers are notified once the commit completes.
+    pub(crate) fn commit(
+        &mut self,
+        offset: Offset,
+        index_roots: Vec<Offset>,
+        mut changes: Vec<Change>,
+    ) -> Result<(), Error> {
+        let mut last_seq = self.header.last_seq;
+        for change in changes.iter_mut() {
+            last_seq += 1;
+            change.seq = last_seq;
+        }
+        let mut header = self.header.clone();
+        header.root = offset;
+        header.indexes = self
+            .indexes
+            .iter()
+            .zip(index_roots.iter())
+            .map(|(index, root)| (index.name.clone(), root.clone()))
+            .collect();
+        header.last_seq = last_seq;
+        header.slot_seq += 1;
+        let num_records = self.wal.num_records()?;
+        if let Err(e) = self.write_commit(&header, &changes) {
+            // Drop the record of the failed commit so the history only lists committed roots.
+            let _ = self.wal.truncate(num_records);
+            self.dirty.clear(self.pager.len());
+            return Err(e);
+        }
+        // The in-memory state only moves on once the header is durable.
+        self.header = header;
+        for (index, root) in self.indexes.iter_mut().zip(index_roots) {
+            index.root = root;
+        }
+        self.change_log.commit();
+        for change in changes.iter() {
+            for subscriber in self.subscribers.iter_mut() {
+                subscriber(change);
+            }
+        }
+        // The commit is complete, a replica which cannot keep up is dropped rather than failing it,
+        // dropped replicas are counted so they can be noticed and added again.
+        let mut replicas = std::mem::take(&mut self.replicas);
+        replicas.retain_mut(|replica| {
+            let shipped = self.ship(replica).is_ok();
+            if !shipped {
+                self.counters.replica_dropped();
+            }
+            shipped
+        });
+        self.replicas = replicas;
+        Ok(())
+    }
+
+    /// write_commit appends the changes to the change log, the pages of the commit to the file and the root to the wal,
+    /// then writes the header once every page it points at is durable.
+    fn write_commit(&mut self, header: &Header, changes: &[Change]) -> Result<(), Error> {
+        if !changes.is_empty() {
+            let written = self.change_log.append(changes)?;
+            self.counters.written(written);
+            self.change_log.sync()?;
+        }
+        self.flush()?;
+        // The pages of the commit have to be durable before the header points at them.
+        self.pager.sync()?;
+        let written = self.wal.append(&Record {
+            root: header.root.clone(),
+            seq: header.last_seq,
+            timestamp: self.now(),
+        })?;
+        self.counters.wal_record(written);
+        self.pager.write_header(header)?;
+        self.pager.sync()
+    }
+
+    /// flush writes every page of the pending commit, each page is serialized and appended once.
+    fn flush(&mut self) -> Result<(), Error> {
+        for (offset, page) in self.dirty.take() {
+            let page = match page {
+                DirtyPage::Node(node) => {
+                    self.counters.node_written();
+                    Page::try_from(&node)?
+                }
+                DirtyPage::DataPage(data_page) => {
+                    self.counters.data_page_written();
+                    Page::try_from(&data_page)?
+                }
+            };
+            self.pager.write_page_at_offset(page, &offset)?;
+        }
+        Ok(())
+    }
+
+    /// add_replica streams the tree to a follower through the given writer, all of the pages are shipped at once
+    /// followed by the pages appended by every commit, see BTree::apply_replication.
+    /// A replica the pages of a commit cannot be shipped to is dropped, see Stats::replicas_dropped.
+    pub fn add_replica(&mut self, writer: impl Write + 'static) -> Result<(), Error> {
+        let mut replica = Replica::new(Box::new(writer), header_slots(self.header.version));
+        self.ship(&mut replica)?;
+        self.replicas.push(replica);
+        Ok(())
+    }
+
+    /// ship sends the pages appended since the last batch shipped to the replica along with the header.
+    /// Committed pages are never overwritten so the appended pages are all a replica needs to catch up.
+    fn ship(&mut self, replica: &mut Replica) -> Result<(), Error> {
+        let end = self.pager.len();
+        replica.begin_batch((end - replica.len) / PAGE_SIZE)?;
+        for offset in (replica.len..end).step_by(PAGE_SIZE) {
+            let offset = Offset(offset);
+            let page = self.pager.get_page(&offset)?;
+            replica.write_page(&offset, &page)?;
+        }
+        replica.end_batch(&Page::try_from(&self.header)?)?;
+        replica.len = end;
+        Ok(())
+    }
+
+    /// apply_replication applies the next batch of the stream shipped by the tree this tree follows,
+    /// the new root is used once every page of the batch is written.
+    /// Returns false once the stream ends.
+    pub fn apply_replication(&mut self, reader: &mut impl Read) -> Result<bool, Error> {
+        if !self.follower {
+            return Err(Error::UnexpectedError);
+        }
+        let (pages, header_page) = match read_batch(reader)? {
+            Some(batch) => batch,
+            None => return Ok(false),
+        };
+        let mut header = Header::try_from(header_page)?;
+        if header.b != self.b {
+            return Err(Error::BParameterMismatch);
+        }
+        if header.comparator != self.comparator.name() {
+            return Err(Error::ComparatorMismatch);
+        }
+        // The pages of the tree follow the header slots of the file it is stored in.
+        let header_len = header_slots(header.version) * PAGE_SIZE;
+        if pages.iter().any(|(offset, _)| offset.0 < header_len) {
+            return Err(Error::UnexpectedError);
+        }
+        for (offset, page) in pages {
+            self.pager.write_page_at_offset(page, &offset)?;
+        }
+        // The pages of the batch have to be durable before the header points at them, see BTree::write_commit.
+        self.pager.sync()?;
+        // The follower encrypts its pages using its own key, if any.
+        header.encrypted = self.header.encrypted;
+        header.slot_seq = self.header.slot_seq + 1;
+        self.decoder = Decoder::new(header.version)?;
+        let written = self.wal.append(&Record {
+            root: header.root.clone(),
+            seq: header.last_seq,
+            timestamp: self.now(),
+        })?;
+        self.counters.wal_record(written);
+        self.pager.write_header(&header)?;
+        self.pager.sync()?;
+        self.header = header;
+        Ok(true)
+    }
+
+    /// backup_to copies the last commit of the tree to a compact new file at the given path,
+    /// the returned backup can later be brought up to date incrementally, see Backup::update.
+    pub fn backup_to(&mut self, path: &Path) -> Result<Backup, Error> {
+        let mut backup = self.start_backup(path)?;
+        backup.step(self, usize::MAX)?;
+        Ok(backup)
+    }
+
+    /// start_backup pins the last commit of the tree without copying any page,
+    /// the copy is made in steps between which the tree may be modified, see Backup::step.
+    pub fn start_backup(&mut self, path: &Path) -> Result<Backup, Error> {
+        let header = self.header.clone();
+        Backup::new(self, path, header)
+    }
+
+    /// history lists the committed roots from the oldest to the newest,
+    /// it starts with the first commit made by a version keeping the history in the wal.
+    pub fn history(&mut self) -> Result<Vec<Commit>, Error> {
+        Ok(self
+            .wal
+            .records()?
+            .into_iter()
+            .map(|record| Commit {
+                seq: record.seq,
+                root: record.root,
+                timestamp: UNIX_EPOCH + Duration::from_millis(record.timestamp),
+            })
+            .collect())
+    }
+
+    /// find_commit returns the last commit made up until the change of the given sequence number was committed.
+    fn find_commit(&mut self, seq: u64) -> Result<Record, Error> {
+        self.wal
+            .records()?
+            .into_iter()
+            .rev()
+            .find(|record| record.seq <= seq)
+            .ok_or(Error::CommitNotFound)
+    }
+
+    /// rollback_to brings the tree back to the way it was once the change of the given sequence number was committed
+    /// by appending the root committed back then as the new root, its pages are still in place as they are never overwritten.
+    /// The indexes are rebuilt and the changes undone are recorded so the change feed follows the rollback.
+    pub fn rollback_to(&mut self, seq: u64) -> Result<(), Error> {
+        self.begin()?;
+        let past_root = self.find_commit(seq)?.root;
+        let root_offset = self.header.root.clone();
+        let current = self.sub_tree_entries(root_offset)?;
+        let past = self.sub_tree_entries(past_root.clone())?;
+        let changes = rollback_changes(current, past, self.comparator.as_ref())?;
+        let extractors: Vec<IndexExtractor> = self
+            .indexes
+            .iter()
+            .map(|index| index.extractor.clone())
+            .collect();
+        let mut index_roots = vec![];
+        for extractor in extractors.iter() {
+            index_roots.push(self.build_index(past_root.clone(), extractor)?);
+        }
+        self.commit(past_root, index_roots, changes)
+    }
+
+    /// copy_version_to copies the tree as it was once the change of the given sequence number was committed
+    /// to a compact new file at the given path. The indexes of the copy are built once it is opened with them registered.
+    pub fn copy_version_to(&mut self, seq: u64, path: &Path) -> Result<(), Error> {
+        let record = self.find_commit(seq)?;
+        let mut header = self.header.clone();
+        header.root = record.root;
+        header.last_seq = record.seq;
+        header.indexes = vec![];
+        let mut backup = Backup::new(self, path, header)?;
+        backup.step(self, usize::MAX)?;
+        Ok(())
+    }
+
+    /// changes_since returns the committed changes following the given sequence number in order,
+    /// changes to the trees of a catalog are not recorded.
+    pub fn changes_since(&self, seq: u64) -> Result<Changes, Error> {
+        self.change_log.changes_since(seq)
+    }
+
+    /// subscribe registers a callback called with every change once it is committed.
+    pub fn subscribe(&mut self, callback: impl FnMut(&Change) + 'static) {
+        self.subscribers.push(Box::new(callback));
+    }
+
+    /// index_roots returns the current roots of the indexes in registration order.
+    pub(crate) fn index_roots(&self) -> Vec<Offset> {
+        self.indexes
+            .iter()
+            .map(|index| index.root.clone())
+            .collect()
+    }
+
+    /// open_indexes matches the registered indexes against the ones recorded in the header,
+    /// missing indexes are built from the existing pairs and unregistered ones are dropped.
+    fn open_indexes(&mut self, registered: &[(String, IndexExtractor)]) -> Result<(), Error> {
+        let mut changed = registered.len() != self.header.indexes.len();
+        for (name, extractor) in registered {
+            let recorded = self
+                .header
+                .indexes
+                .iter()
+                .find(|(recorded_name, _)| recorded_name == name)
+                .map(|(_, root)| root.clone());
+            let root = match recorded {
+                Some(root) => root,
+                None => {
+                    self.check_writable()?;
+                    changed = true;
+                    let root_offset = self.header.root.clone();
+                    self.build_index(root_offset, extractor)?
+                }
+            };
+            self.indexes.push(Index {
+                name: name.clone(),
+                extractor: extractor.clone(),
+                root,
+            });
+        }
+        // A read-only tree leaves the indexes which are not registered in place.
+        if changed && !self.read_only {
+            self.check_writable()?;
+            let root = self.header.root.clone();
+            let index_roots = self.index_roots();
+            self.commit(root, index_roots, vec![])?;
+        }
+        Ok(())
+    }
+
+    /// build_index writes a new index tree holding the index entries of the existing pairs and returns its root.
+    fn build_index(
+        &mut self,
+        root_offset: Offset,
+        extractor: &IndexExtractor,
+    ) -> Result<Offset, Error> {
+        let mut index_root = self.write_empty_tree()?;
+        for (key, value) in self.sub_tree_pairs(root_offset)? {
+            if let Some(index_key) = extractor(&value) {
+                index_root = self.insert_index_entry(index_root, index_key, key)?;
+            }
+        }
+        Ok(index_root)
+    }
+
+    /// header returns the header of the last commit.
+    pub(crate) fn header(&self) -> &Header {
+        &self.header
+    }
+
+    /// decoder returns the decoder of the pages of the file.
+    pub(crate) fn decoder(&self) -> Decoder {
+        self.decoder
+    }
+
+    /// comparator returns the ordering of the keys.
+    pub(crate) fn comparator(&self) -> Rc<dyn Comparator> {
+        self.comparator.clone()
+    }
+
+    /// backend returns the backend storing the files of the tree.
+    pub(crate) fn backend(&self) -> Rc<dyn Backend> {
+        self.backend.clone()
+    }
+
+    /// cipher returns the cipher encrypting the file, if any.
+    pub(crate) fn cipher(&self) -> Option<Arc<dyn Cipher>> {
+        self.pager.cipher()
+    }
+
+    /// stats returns a snapshot of the counters of the tree.
+    pub fn stats(&self) -> Stats {
+        self.counters.snapshot()
+    }
+
+    /// counters returns the counters of the tree, they keep being updated as the tree is used
+    /// and may be shared with a thread exporting them, see Counters::snapshot.
+    pub fn counters(&self) -> Arc<Counters> {
+        self.counters.clone()
+    }
+
+    /// read_node reads the node at the given offset, the nodes of the pending commit are read from memory.
+    pub(crate) fn read_node(&mut self, offset: &Offset) -> Result<Node, Error> {
+        match self.dirty.get(offset) {
+            Some(DirtyPage::Node(node)) => Ok(node.clone()),
+            Some(DirtyPage::DataPage(_)) => Err(Error::UnexpectedError),
+            None => {
+                let page = self.pager.get_page(offset)?;
+                self.counters.node_read();
+                self.decoder.node(page)
+            }
+        }
+    }
+
+    /// read_data_page reads the data page at the given offset, the data pages of the pending commit are read from memory.
+    pub(crate) fn read_data_page(&mut self, offset: &Offset) -> Result<DataPage, Error> {
+        match self.dirty.get(offset) {
+            Some(DirtyPage::DataPage(data_page)) => Ok(data_page.clone()),
+            Some(DirtyPage::Node(_)) => Err(Error::UnexpectedError),
+            None => {
+                let page = self.pager.get_page(offset)?;
+                self.counters.data_page_read();
+                self.decoder.data_page(page)
+            }
+        }
+    }
+
+    /// write_node adds a node to the pending commit and returns the offset it is going to be written at.
+    fn write_node(&mut self, node: &Node) -> Result<Offset, Error> {
+        Ok(self.dirty.allocate(DirtyPage::Node(node.clone())))
+    }
+
+    /// write_node_at replaces a node of the pending commit, committed nodes are never overwritten.
+    fn write_node_at(&mut self, node: &Node, offset: &Offset) -> Result<(), Error> {
+        self.dirty.replace(offset, DirtyPage::Node(node.clone()))
+    }
+
+    /// copy_node reads the node at the given offset and returns it along with the offset of its copy in the pending commit.
+    /// A node which is already part of the pending commit is modified in place rather than copied again.
+    fn copy_node(&mut self, offset: &Offset) -> Result<(Node, Offset), Error> {
+        let node = self.read_node(offset)?;
+        if self.dirty.contains(offset) {
+            return Ok((node, offset.clone()));
+        }
+        let copy = self.write_node(&node)?;
+        Ok((node, copy))
+    }
+
+    /// split splits a full node, see Node::split, the data page of a leaf is split between the two halves.
+    fn split(&mut self, node: &mut Node) -> Result<(Key, Node), Error> {
+        self.counters.split();
+        let (median, mut sibling) = node.split(self.b, self.comparator.as_ref())?;
+        if let (
+            NodeType::Leaf(data_offset, pairs),
+            NodeType::Leaf(sibling_data_offset, sibling_pairs),
+        ) = (&mut node.node_type, &mut sibling.node_type)
+        {
+            // Move each value along with the pair referring to it.
+            let data_page = self.read_data_page(data_offset)?;
+            let left = data_page.extract(pairs)?;
+            let right = data_page.extract(sibling_pairs)?;
+            self.update_data_page(data_offset, left)?;
+            *sibling_data_offset = self.write_data_page(right)?;
+        }
+        Ok((median, sibling))
+    }
+
+    /// root returns the offset of the last committed root.
+    pub(crate) fn root(&mut self) -> Result<Offset, Error> {
+        Ok(self.header.root.clone())
+    }
+
+    /// write_empty_tree writes a new tree made of a single empty leaf and returns its root.
+    pub(crate) fn write_empty_tree(&mut self) -> Result<Offset, Error> {
+        let data_page_offset = self.write_data_page(DataPage::new())?;
+        let root = Node::new(NodeType::Leaf(data_page_offset, vec![]), true);
+        self.write_node(&root)
+    }
+
+    /// insert_index_entry inserts an entry to an index tree refusing index keys already in use,
+    /// returns the new root of the index tree.
+    fn insert_index_entry(
+        &mut self,
+        index_root: Offset,
+        index_key: String,
+        key: String,
+    ) -> Result<Offset, Error> {
+        match self.search_in(&index_root, &index_key) {
+            Ok(_) => Err(Error::KeyAlreadyExists),
+            Err(Error::KeyNotFound) => self.insert_into(index_root, index_key, key, None),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// check_writable refuses modifications of files written using an older page layout,
+    /// as new pages are always written using the newest one.
+    fn check_writable(&self) -> Result<(), Error> {
+        if self.read_only {
+            return Err(Error::ReadOnly);
+        }
+        if !self.decoder.is_current() {
+            return Err(Error::MigrationRequired);
+        }
+        Ok(())
+    }
+
+    /// write_data_page adds a data page to the pending commit compressing its values using the tree's codec,
+    /// returns the offset it is going to be written at.
+    fn write_data_page(&mut self, mut data_page: DataPage) -> Result<Offset, Error> {
+        data_page.set_compression(self.compression);
+        Ok(self.dirty.allocate(DirtyPage::DataPage(data_page)))
+    }
+
+    /// update_data_page stores a modified data page, a data page of the pending commit is replaced in place
+    /// while a committed one is copied to a new offset which the given offset is set to.
+    fn update_data_page(
+        &mut self,
+        offset: &mut Offset,
+        mut data_page: DataPage,
+    ) -> Result<(), Error> {
+        if self.dirty.contains(offset) {
+            data_page.set_compression(self.compression);
+            return self.dirty.replace(offset, DirtyPage::DataPage(data_page));
+        }
+        *offset = self.write_data_page(data_page)?;
+        Ok(())
+    }
+
+    /// begin starts the modifications of a new commit refusing them if the tree is not writable,
+    /// the pages left behind by an operation which failed before committing are dropped.
+    pub(crate) fn begin(&mut self) -> Result<(), Error> {
+        self.check_writable()?;
+        self.dirty.clear(self.pager.len());
+        Ok(())
+    }
+
     fn is_node_full(&self, node: &Node) -> Result<bool, Error> {
         match &node.node_type {
             NodeType::Leaf(_, pairs) => Ok(pairs.len() == (2 * self.b - 1)),
@@ -105,42 +938,248 @@ impl BTree {
         }
     }
 
-    /// insert a key value pair possibly splitting nodes along the way.
+    /// now returns the current time of the tree's clock in milliseconds since the unix epoch.
+    pub(crate) fn now(&self) -> u64 {
+        millis((self.clock)())
+    }
+
+    /// insert a key value pair possibly splitting nodes along the way,
+    /// the value of an existing key is replaced. The indexes are updated in the same commit.
     pub fn insert(&mut self, key: String, value: String) -> Result<(), Error> {
-        let root_offset = self.wal.get_root()?;
-        let root_page = self.pager.get_page(&root_offset)?;
+        self.insert_entry(key, value, None)
+    }
+
+    /// insert_with_ttl inserts a key value pair which expires once the given duration elapses,
+    /// expired pairs are hidden from searches until they are removed by purge_expired.
+    pub fn insert_with_ttl(
+        &mut self,
+        key: String,
+        value: String,
+        ttl: Duration,
+    ) -> Result<(), Error> {
+        let expiry = self.now().saturating_add(ttl.as_millis() as u64);
+        self.insert_entry(key, value, Some(expiry))
+    }
+
+    /// insert_entry inserts a key value pair expiring at the given time along with its index entries.
+    fn insert_entry(
+        &mut self,
+        key: String,
+        value: String,
+        expiry: Option<u64>,
+    ) -> Result<(), Error> {
+        self.begin()?;
+        self.counters.user_data(key.len() + value.len());
+        let root_offset = self.header.root.clone();
+        let mut index_roots = self.index_roots();
+        let change = Change::new(ChangeOp::Insert, key.clone(), Some(value.clone()));
+        let new_root_offset = self.add_entry(root_offset, &mut index_roots, key, value, expiry)?;
+        self.commit(new_root_offset, index_roots, vec![change])
+    }
+
+    /// write_batch applies the writes in order and commits them at once, with a single sync of the tree file
+    /// and a single wal record, see the group_commit module. Each write is checked before it is applied,
+    /// a write which would fail, for example the deletion of a missing key or a value which does not fit
+    /// into its data page, is left out of the commit and its error is returned in its place.
+    /// Returns an error, in which case none of the writes is applied, if reading the tree or the commit fails.
+    pub fn write_batch(&mut self, writes: &[WriteOp]) -> Result<Vec<Result<(), Error>>, Error> {
+        let now = self.now();
+        // A write may only fail once it is applied, such as a value which does not fit next to the values of its leaf,
+        // the batch is then applied again from the last commit leaving the failed write out.
+        let mut failed: Vec<Option<Error>> = vec![None; writes.len()];
+        let results = 'apply: loop {
+            self.begin()?;
+            let mut root_offset = self.header.root.clone();
+            let mut index_roots = self.index_roots();
+            let mut changes = vec![];
+            let mut results = vec![];
+            for (write, failed) in writes.iter().zip(failed.iter_mut()) {
+                if let Some(e) = failed {
+                    results.push(Err(e.clone()));
+                    continue;
+                }
+                if let Some(e) = self.check_write(&root_offset, &index_roots, write)? {
+                    results.push(Err(e));
+                    continue;
+                }
+                match self.apply_write(root_offset.clone(), &mut index_roots, write, now) {
+                    Ok((new_root_offset, change)) => {
+                        root_offset = new_root_offset;
+                        changes.push(change);
+                        results.push(Ok(()));
+                    }
+                    Err(e @ (Error::KeyOverflowError | Error::ValueOverflowError)) => {
+                        *failed = Some(e);
+                        continue 'apply;
+                    }
+                    Err(e) => return Err(e),
+                }
+            }
+            if !changes.is_empty() {
+                self.commit(root_offset, index_roots, changes)?;
+            }
+            break results;
+        };
+        for (write, result) in writes.iter().zip(results.iter()) {
+            if let (WriteOp::Insert(key, value) | WriteOp::InsertWithTtl(key, value, _), Ok(())) =
+                (write, result)
+            {
+                self.counters.user_data(key.len() + value.len());
+            }
+        }
+        Ok(results)
+    }
+
+    /// check_write checks a write against the tree rooted at the given offset and the index trees rooted
+    /// at the given offsets without modifying them, returns the error the write would fail with, if any.
+    fn check_write(
+        &mut self,
+        root_offset: &Offset,
+        index_roots: &[Offset],
+        write: &WriteOp,
+    ) -> Result<Option<Error>, Error> {
+        match write {
+            WriteOp::Delete(key) => match self.lookup_in(root_offset, &key.0) {
+                Ok(_) => Ok(None),
+                Err(Error::KeyNotFound) => Ok(Some(Error::KeyNotFound)),
+                Err(e) => Err(e),
+            },
+            WriteOp::Insert(key, value) | WriteOp::InsertWithTtl(key, value, _) => {
+                if let Err(e) = check_key_size(key) {
+                    return Ok(Some(e));
+                }
+                let mut data_page = DataPage::new();
+                data_page.set_compression(self.compression);
+                if !data_page.fits(value) {
+                    return Ok(Some(Error::ValueOverflowError));
+                }
+                let extractors: Vec<IndexExtractor> = self
+                    .indexes
+                    .iter()
+                    .map(|index| index.extractor.clone())
+                    .collect();
+                for (extractor, index_root) in extractors.iter().zip(index_roots) {
+                    let index_key = match extractor(value) {
+                        Some(index_key) => index_key,
+                        None => continue,
+                    };
+                    if let Err(e) = check_key_size(&index_key) {
+                        return Ok(Some(e));
+                    }
+                    // The index key of the replaced value is removed before the new one is inserted.
+                    match self.search_in(index_root, &index_key) {
+                        Ok(owner) if owner != *key => return Ok(Some(Error::KeyAlreadyExists)),
+                        Ok(_) | Err(Error::KeyNotFound) => {}
+                        Err(e) => return Err(e),
+                    }
+                }
+                Ok(None)
+            }
+        }
+    }
+
+    /// apply_write applies a write to the tree rooted at the given offset and to the index trees rooted at the given offsets,
+    /// returns the new root along with the change made. The roots are replaced without being committed.
+    fn apply_write(
+        &mut self,
+        root_offset: Offset,
+        index_roots: &mut [Offset],
+        write: &WriteOp,
+        now: u64,
+    ) -> Result<(Offset, Change), Error> {
+        match write {
+            WriteOp::Insert(key, value) => Ok((
+                self.add_entry(root_offset, index_roots, key.clone(), value.clone(), None)?,
+                Change::new(ChangeOp::Insert, key.clone(), Some(value.clone())),
+            )),
+            WriteOp::InsertWithTtl(key, value, ttl) => {
+                let expiry = now.saturating_add(ttl.as_millis() as u64);
+                Ok((
+                    self.add_entry(
+                        root_offset,
+                        index_roots,
+                        key.clone(),
+                        value.clone(),
+                        Some(expiry),
+                    )?,
+                    Change::new(ChangeOp::Insert, key.clone(), Some(value.clone())),
+                ))
+            }
+            WriteOp::Delete(key) => Ok((
+                self.remove_entry(root_offset, index_roots, key.clone())?,
+                Change::new(ChangeOp::Delete, key.0.clone(), None),
+            )),
+        }
+    }
+
+    /// add_entry inserts a key value pair expiring at the given time to the tree rooted at the given offset and its entries to
+    /// the index trees rooted at the given offsets, the roots are replaced by the new roots without being committed.
+    fn add_entry(
+        &mut self,
+        root_offset: Offset,
+        index_roots: &mut [Offset],
+        key: String,
+        value: String,
+        expiry: Option<u64>,
+    ) -> Result<Offset, Error> {
+        if !self.indexes.is_empty() {
+            // The index entries of the replaced value are removed even if it has expired.
+            let previous = match self.lookup_in(&root_offset, &key) {
+                Ok(previous) => Some(previous),
+                Err(Error::KeyNotFound) => None,
+                Err(e) => return Err(e),
+            };
+            for (idx, index_root) in index_roots.iter_mut().enumerate() {
+                let extractor = self.indexes[idx].extractor.clone();
+                if let Some(index_key) =
+                    previous.as_deref().and_then(|previous| extractor(previous))
+                {
+                    *index_root = self.delete_from(index_root.clone(), Key(index_key))?;
+                }
+                if let Some(index_key) = extractor(&value) {
+                    *index_root =
+                        self.insert_index_entry(index_root.clone(), index_key, key.clone())?;
+                }
+            }
+        }
+        self.insert_into(root_offset, key, value, expiry)
+    }
+
+    /// insert_into inserts a key value pair expiring at the given time to the tree rooted at the given offset
+    /// and returns the offset of the new root without committing it.
+    pub(crate) fn insert_into(
+        &mut self,
+        root_offset: Offset,
+        key: String,
+        value: String,
+        expiry: Option<u64>,
+    ) -> Result<Offset, Error> {
+        check_key_size(&key)?;
         let new_root_offset: Offset;
         let mut new_root: Node;
-        let mut root = Node::try_from(root_page)?;
+        let (mut root, root_copy_offset) = self.copy_node(&root_offset)?;
         if self.is_node_full(&root)? {
-            // split the root creating a new root and child nodes along the way.
-            new_root = Node::new(NodeType::Internal(vec![], vec![]), true, None);
-            // write the new root to disk to aquire an offset for the new root.
-            new_root_offset = self.pager.write_page(Page::try_from(&new_root)?)?;
-            // set the old roots parent to the new root.
-            root.parent_offset = Some(new_root_offset.clone());
+            self.counters.root_flip();
             root.is_root = false;
             // split the old root.
-            let (median, sibling) = root.split(self.b, &mut self.pager)?;
-
-            // write the old root with its new data to disk in a *new* location.
-            let old_root_offset = self.pager.write_page(Page::try_from(&root)?)?;
-            // write the newly created sibling to disk.
-            let sibling_offset = self.pager.write_page(Page::try_from(&sibling)?)?;
-            // update the new root with its children and key.
-            new_root.node_type =
-                NodeType::Internal(vec![old_root_offset, sibling_offset], vec![median]);
-            // write the new_root to disk.
-            self.pager
-                .write_page_at_offset(Page::try_from(&new_root)?, &new_root_offset)?;
+            let (median, sibling) = self.split(&mut root)?;
+
+            // the old root keeps its copy while the newly created sibling is added to the commit.
+            self.write_node_at(&root, &root_copy_offset)?;
+            let sibling_offset = self.write_node(&sibling)?;
+            // split the root creating a new root pointing at the two halves.
+            new_root = Node::new(
+                NodeType::Internal(vec![root_copy_offset, sibling_offset], vec![median]),
+                true,
+            );
+            new_root_offset = self.write_node(&new_root)?;
         } else {
-            new_root = root.clone();
-            new_root_offset = self.pager.write_page(Page::try_from(&new_root)?)?;
+            new_root = root;
+            new_root_offset = root_copy_offset;
         }
         // continue recursively.
-        self.insert_non_full(&mut new_root, new_root_offset.clone(), key, value)?;
-        // finish by setting the root to its new copy.
-        self.wal.set_root(new_root_offset)
+        self.insert_non_full(&mut new_root, new_root_offset.clone(), key, value, expiry)?;
+        Ok(new_root_offset)
     }
 
     /// insert_non_full (recursively) finds a node rooted at a given non-full node.
@@ -152,61 +1191,65 @@ impl BTree {
         node_offset: Offset,
         key: String,
         value: String,
+        expiry: Option<u64>,
     ) -> Result<(), Error> {
         match &mut node.node_type {
             NodeType::Leaf(ref mut data_offset, ref mut pairs) => {
-                let mut kv = KeyValuePair { key, idx: 0 };
-                let idx = pairs.binary_search(&kv).unwrap_or_else(|x| x);
+                let mut kv = KeyValuePair::new(key, 0);
+                kv.expiry = expiry;
 
-                let page = self.pager.get_page(&data_offset)?;
-                let mut data_page = DataPage::try_from(page)?;
-                let data_idx = data_page.insert(value);
-                kv.idx = data_idx;
+                let mut data_page = self.read_data_page(data_offset)?;
+                let idx = match pairs
+                    .binary_search_by(|pair| self.comparator.compare(&pair.key, &kv.key))
+                {
+                    // Replace the pair of an existing key dropping its value from the data page.
+                    Ok(idx) => {
+                        data_page.remove(pairs.remove(idx).idx);
+                        idx
+                    }
+                    Err(idx) => idx,
+                };
+                kv.idx = data_page.insert(value)?;
 
                 pairs.insert(idx, kv);
 
-                let offset = self.pager.write_page(Page::try_from(&data_page)?)?;
-                *data_offset = offset;
-                self.pager
-                    .write_page_at_offset(Page::try_from(&*node)?, &node_offset)
+                self.update_data_page(data_offset, data_page)?;
+                self.write_node_at(node, &node_offset)
             }
             NodeType::Internal(ref mut children, ref mut keys) => {
-                let idx = keys.binary_search(&Key(key.clone())).unwrap_or_else(|x| x);
+                let idx = keys
+                    .binary_search_by(|separator| self.comparator.compare(&separator.0, &key))
+                    .unwrap_or_else(|x| x);
                 let child_offset: Offset = children.get(idx).ok_or(Error::UnexpectedError)?.clone();
-                let child_page = self.pager.get_page(&child_offset)?;
-                let mut child = Node::try_from(child_page)?;
                 // Copy each branching-node on the root-to-leaf walk.
-                // write_page appends the given page to the db file thus creating a new node.
-                let new_child_offset = self.pager.write_page(Page::try_from(&child)?)?;
+                // copy_node adds a copy of a committed node to the pending commit thus creating a new node.
+                let (mut child, new_child_offset) = self.copy_node(&child_offset)?;
                 // Assign copied child at the proper place.
                 children[idx] = new_child_offset.to_owned();
                 if self.is_node_full(&child)? {
                     // split will split the child at b leaving the [0, b-1] keys
                     // while moving the set of [b, 2b-1] keys to the sibling.
-                    let (median, mut sibling) = child.split(self.b, &mut self.pager)?;
-                    self.pager
-                        .write_page_at_offset(Page::try_from(&child)?, &new_child_offset)?;
+                    let (median, mut sibling) = self.split(&mut child)?;
+                    self.write_node_at(&child, &new_child_offset)?;
                     // Write the newly created sibling to disk.
-                    let sibling_offset = self.pager.write_page(Page::try_from(&sibling)?)?;
+                    let sibling_offset = self.write_node(&sibling)?;
 
                     // Siblings keys are larger than the splitted child thus need to be inserted
                     // at the next index.
                     children.insert(idx + 1, sibling_offset.clone());
                     keys.insert(idx, median.clone());
 
-                    // Write the parent page to disk.
-                    self.pager
-                        .write_page_at_offset(Page::try_from(&*node)?, &node_offset)?;
+                    // Store the updated parent.
+                    self.write_node_at(node, &node_offset)?;
                     // Continue recursively.
-                    if key <= median.0 {
-                        self.insert_non_full(&mut child, new_child_offset, key, value)
+                    if self.comparator.compare(&key, &median.0) != cmp::Ordering::Greater {
+                        self.insert_non_full(&mut child, new_child_offset, key, value, expiry)
                     } else {
-                        self.insert_non_full(&mut sibling, sibling_offset, key, value)
+                        self.insert_non_full(&mut sibling, sibling_offset, key, value, expiry)
                     }
                 } else {
-                    self.pager
-                        .write_page_at_offset(Page::try_from(&*node)?, &node_offset)?;
-                    self.insert_non_full(&mut child, new_child_offset, key, value)
+                    self.write_node_at(node, &node_offset)?;
+                    self.insert_non_full(&mut child, new_child_offset, key, value, expiry)
                 }
             }
             NodeType::Unexpected => Err(Error::UnexpectedError),
@@ -215,179 +1258,369 @@ impl BTree {
 
     /// search searches for a specific key in the BTree.
     pub fn search(&mut self, key: String) -> Result<String, Error> {
-        let root_offset = self.wal.get_root()?;
-        let root_page = self.pager.get_page(&root_offset)?;
-        let root = Node::try_from(root_page)?;
-        self.search_node(root, &key)
+        let root_offset = self.header.root.clone();
+        self.search_in(&root_offset, &key)
     }
 
-    /// search_node recursively searches a sub tree rooted at node for a key.
-    fn search_node(&mut self, node: Node, search: &str) -> Result<String, Error> {
-        match node.node_type {
+    /// search_by_index searches for the value whose index key, in the index registered under name, is the given key.
+    pub fn search_by_index(&mut self, name: &str, key: String) -> Result<String, Error> {
+        let index_root = self
+            .indexes
+            .iter()
+            .find(|index| index.name == name)
+            .map(|index| index.root.clone())
+            .ok_or(Error::IndexNotFound)?;
+        let primary_key = self.search_in(&index_root, &key)?;
+        self.search(primary_key)
+    }
+
+    /// search_in searches for a specific key in the tree rooted at the given offset hiding expired pairs.
+    pub(crate) fn search_in(&mut self, root_offset: &Offset, key: &str) -> Result<String, Error> {
+        let now = self.now();
+        self.search_node(root_offset, key, Some(now))
+    }
+
+    /// lookup_in searches for a specific key in the tree rooted at the given offset including expired pairs.
+    pub(crate) fn lookup_in(&mut self, root_offset: &Offset, key: &str) -> Result<String, Error> {
+        self.search_node(root_offset, key, None)
+    }
+
+    /// search_node searches a sub tree rooted at the node at the given offset for a key,
+    /// pairs expired at now are treated as missing unless now is None.
+    /// Nodes are searched in place through views and the value is read directly from its slot.
+    fn search_node(
+        &mut self,
+        offset: &Offset,
+        search: &str,
+        now: Option<u64>,
+    ) -> Result<String, Error> {
+        let mut offset = offset.clone();
+        loop {
+            // Only trees of the pending commit, such as indexes being built, are searched before they are written.
+            let page = match self.dirty.get(&offset) {
+                Some(DirtyPage::Node(node)) => Page::try_from(node)?,
+                Some(DirtyPage::DataPage(_)) => return Err(Error::UnexpectedError),
+                None => {
+                    self.counters.node_read();
+                    self.pager.get_page(&offset)?
+                }
+            };
+            match self
+                .decoder
+                .view(&page)?
+                .search(search, self.comparator.as_ref())?
+            {
+                Lookup::Child(child_offset) => offset = child_offset,
+                Lookup::Pair {
+                    data_page,
+                    idx,
+                    expiry,
+                } => {
+                    if matches!((now, expiry), (Some(now), Some(expiry)) if expiry <= now) {
+                        return Err(Error::KeyNotFound);
+                    }
+                    if self.dirty.contains(&data_page) {
+                        return self
+                            .read_data_page(&data_page)?
+                            .get(idx)
+                            .ok_or(Error::UnexpectedError);
+                    }
+                    let page = self.pager.get_page(&data_page)?;
+                    self.counters.data_page_read();
+                    return self.decoder.value(&page, idx);
+                }
+                Lookup::NotFound => return Err(Error::KeyNotFound),
+            }
+        }
+    }
+
+    /// delete deletes a given key from the tree along with its index entries.
+    pub fn delete(&mut self, key: Key) -> Result<(), Error> {
+        self.begin()?;
+        let root_offset = self.header.root.clone();
+        let mut index_roots = self.index_roots();
+        let change = Change::new(ChangeOp::Delete, key.0.clone(), None);
+        let new_root_offset = self.remove_entry(root_offset, &mut index_roots, key)?;
+        self.commit(new_root_offset, index_roots, vec![change])
+    }
+
+    /// purge_expired removes up to batch_size expired pairs following the given cursor, along with their index entries,
+    /// in a single commit. Returns the number of pairs removed along with the cursor the next batch resumes from,
+    /// None once the last pair of the tree is reached. Only the sub trees following the cursor are walked,
+    /// so a pass over the tree in batches reads each leaf about once.
+    pub fn purge_expired(
+        &mut self,
+        cursor: Option<Key>,
+        batch_size: usize,
+    ) -> Result<(usize, Option<Key>), Error> {
+        self.begin()?;
+        let now = self.now();
+        let mut root_offset = self.header.root.clone();
+        let mut expired = vec![];
+        let full = batch_size == 0
+            || self.collect_expired(
+                root_offset.clone(),
+                cursor.as_ref(),
+                now,
+                batch_size,
+                &mut expired,
+            )?;
+        // A full batch resumes after its last pair, which may be followed by more expired pairs.
+        let cursor = if full {
+            expired.last().cloned().or(cursor)
+        } else {
+            None
+        };
+        if expired.is_empty() {
+            return Ok((0, cursor));
+        }
+        let mut index_roots = self.index_roots();
+        let mut changes = vec![];
+        for key in expired.iter() {
+            root_offset = self.remove_entry(root_offset, &mut index_roots, key.clone())?;
+            changes.push(Change::new(ChangeOp::Delete, key.0.clone(), None));
+        }
+        self.commit(root_offset, index_roots, changes)?;
+        Ok((expired.len(), cursor))
+    }
+
+    /// collect_expired collects the keys of the pairs expired at now in the sub tree rooted at the given offset
+    /// in key order, skipping the keys up to and including the given cursor. The walk stops as soon as batch_size keys
+    /// are collected, returns whether it did.
+    fn collect_expired(
+        &mut self,
+        offset: Offset,
+        cursor: Option<&Key>,
+        now: u64,
+        batch_size: usize,
+        expired: &mut Vec<Key>,
+    ) -> Result<bool, Error> {
+        let follows = |btree: &BTree, key: &str| match cursor {
+            Some(Key(cursor)) => btree.comparator.compare(key, cursor) == cmp::Ordering::Greater,
+            None => true,
+        };
+        match self.read_node(&offset)?.node_type {
             NodeType::Internal(children, keys) => {
-                let idx = keys
-                    .binary_search(&Key(search.to_string()))
-                    .unwrap_or_else(|x| x);
-                // Retrieve child page from disk and deserialize.
-                let child_offset = children.get(idx).ok_or(Error::UnexpectedError)?;
-                let page = self.pager.get_page(child_offset)?;
-                let child_node = Node::try_from(page)?;
-                self.search_node(child_node, search)
-            }
-            NodeType::Leaf(offset, pairs) => {
-                if let Ok(idx) =
-                    pairs.binary_search_by_key(&search.to_string(), |pair| pair.key.clone())
-                {
-                    let value = pairs.get(idx).ok_or(Error::KeyNotFound)?;
-                    let page = self.pager.get_page(&offset)?;
-                    let data_page = DataPage::try_from(page)?;
-                    let value = data_page.get(value.idx).ok_or(Error::UnexpectedError)?;
-                    return Ok(value);
+                // A key equal to a separator is found in the left child, so only the children
+                // following the separators up to the cursor may hold keys following it.
+                let first = keys
+                    .iter()
+                    .take_while(|Key(separator)| !follows(self, separator))
+                    .count();
+                for child_offset in children.into_iter().skip(first) {
+                    if self.collect_expired(child_offset, cursor, now, batch_size, expired)? {
+                        return Ok(true);
+                    }
                 }
-                Err(Error::KeyNotFound)
+                Ok(false)
+            }
+            NodeType::Leaf(_, pairs) => {
+                for pair in pairs {
+                    if pair.is_expired(now) && follows(self, &pair.key) {
+                        expired.push(Key(pair.key));
+                        if expired.len() == batch_size {
+                            return Ok(true);
+                        }
+                    }
+                }
+                Ok(false)
             }
             NodeType::Unexpected => Err(Error::UnexpectedError),
         }
     }
 
-    /// delete deletes a given key from the tree.
-    pub fn delete(&mut self, key: Key) -> Result<(), Error> {
-        let root_offset = self.wal.get_root()?;
-        let root_page = self.pager.get_page(&root_offset)?;
-        // Shadow the new root and rewrite it.
-        let mut new_root = Node::try_from(root_page)?;
-        let new_root_page = Page::try_from(&new_root)?;
-        let new_root_offset = self.pager.write_page(new_root_page)?;
-        self.delete_key_from_subtree(key, &mut new_root, &new_root_offset)?;
-        self.wal.set_root(new_root_offset)
-    }
-
-    /// delete key from subtree recursively traverses a tree rooted at a node in certain offset
-    /// until it finds the given key and delete the key-value pair. Here we assume the node is
-    /// already a copy of an existing node in a copy-on-write root to node traversal.
-    fn delete_key_from_subtree(
+    /// remove_entry deletes a given key from the tree rooted at the given offset and its entries from
+    /// the index trees rooted at the given offsets, the roots are replaced by the new roots without being committed.
+    fn remove_entry(
         &mut self,
+        root_offset: Offset,
+        index_roots: &mut [Offset],
         key: Key,
-        node: &mut Node,
-        node_offset: &Offset,
-    ) -> Result<(), Error> {
-        match &mut node.node_type {
-            NodeType::Leaf(ref mut data_offset, ref mut pairs) => {
-                let key_idx = pairs
-                    .binary_search_by_key(&key, |kv| Key(kv.key.clone()))
-                    .map_err(|_| Error::KeyNotFound)?;
-
-                // remove key from data page
-                let page = self.pager.get_page(&data_offset)?;
-                let mut data_page = DataPage::try_from(page)?;
-                data_page.values.remove(key_idx);
-
-                let offset = self.pager.write_page(Page::try_from(&data_page)?)?;
-                *data_offset = offset;
-
-                pairs.remove(key_idx);
-                self.pager
-                    .write_page_at_offset(Page::try_from(&*node)?, node_offset)?;
-                // Check for underflow - if it occures,
-                // we need to merge with a sibling.
-                // this can only occur if node is not the root (as it cannot "underflow").
-                // continue recoursively up the tree.
-                self.borrow_if_needed(node.to_owned(), &key)?;
+    ) -> Result<Offset, Error> {
+        if !self.indexes.is_empty() {
+            let previous = self.lookup_in(&root_offset, &key.0)?;
+            for (idx, index_root) in index_roots.iter_mut().enumerate() {
+                let extractor = self.indexes[idx].extractor.clone();
+                if let Some(index_key) = extractor(&previous) {
+                    *index_root = self.delete_from(index_root.clone(), Key(index_key))?;
+                }
             }
-            NodeType::Internal(children, keys) => {
-                let node_idx = keys.binary_search(&key).unwrap_or_else(|x| x);
-                // Retrieve child page from disk and deserialize,
-                // copy over the child page and continue recursively.
-                let child_offset = children.get(node_idx).ok_or(Error::UnexpectedError)?;
-                let child_page = self.pager.get_page(child_offset)?;
-                let mut child_node = Node::try_from(child_page)?;
-                // Fix the parent_offset as the child node is a child of a copied parent
-                // in a copy-on-write root to leaf traversal.
-                // This is important for the case of a node underflow which might require a leaf to root traversal.
-                child_node.parent_offset = Some(node_offset.to_owned());
-                let new_child_page = Page::try_from(&child_node)?;
-                let new_child_offset = self.pager.write_page(new_child_page)?;
-                // Assign the new pointer in the parent and continue reccoursively.
-                children[node_idx] = new_child_offset.to_owned();
-                self.pager
-                    .write_page_at_offset(Page::try_from(&*node)?, node_offset)?;
-                return self.delete_key_from_subtree(key, &mut child_node, &new_child_offset);
-            }
-            NodeType::Unexpected => return Err(Error::UnexpectedError),
         }
-        Ok(())
+        self.delete_from(root_offset, key)
+    }
+
+    /// delete_from deletes a given key from the tree rooted at the given offset
+    /// and returns the offset of the new root without committing it.
+    /// Every node along the root to leaf descent is pushed to the descent, which is then used
+    /// to rebalance the tree bottom up, copying each node on write once its final content is known.
+    pub(crate) fn delete_from(&mut self, root_offset: Offset, key: Key) -> Result<Offset, Error> {
+        let mut node = self.read_node(&root_offset)?;
+        let mut node_offset = root_offset;
+        let mut descent = Descent::new();
+        while let NodeType::Internal(children, keys) = &node.node_type {
+            let idx = keys
+                .binary_search_by(|separator| self.comparator.compare(&separator.0, &key.0))
+                .unwrap_or_else(|x| x);
+            let child_offset = children.get(idx).ok_or(Error::UnexpectedError)?.clone();
+            let child = self.read_node(&child_offset)?;
+            descent.push((node, node_offset, idx));
+            node = child;
+            node_offset = child_offset;
+        }
+
+        let (data_offset, pairs) = match &mut node.node_type {
+            NodeType::Leaf(data_offset, pairs) => (data_offset, pairs),
+            _ => return Err(Error::UnexpectedError),
+        };
+        let key_idx = pairs
+            .binary_search_by(|kv| self.comparator.compare(&kv.key, &key.0))
+            .map_err(|_| Error::KeyNotFound)?;
+        let pair = pairs.remove(key_idx);
+
+        // remove the value from the data page, the slots of the remaining ones are unaffected.
+        let mut data_page = self.read_data_page(data_offset)?;
+        data_page.remove(pair.idx);
+        self.update_data_page(data_offset, data_page)?;
+
+        self.rebalance(node, node_offset, descent)
     }
 
-    /// borrow_if_needed checks the node for underflow (following a removal of a key),
-    /// if it underflows it is merged with a sibling node, and than called recoursively
-    /// up the tree. Since the downward root-to-leaf traversal was done using the copy-on-write
-    /// technique we are ensured that any merges will only be reflected in the copied parent in the path.
-    fn borrow_if_needed(&mut self, node: Node, key: &Key) -> Result<(), Error> {
-        if self.is_node_underflow(&node)? {
-            // Fetch the sibling from the parent -
-            // This could be quicker if we implement sibling pointers.
-            let parent_offset = node.parent_offset.clone().ok_or(Error::UnexpectedError)?;
-            let parent_page = self.pager.get_page(&parent_offset)?;
-            let mut parent_node = Node::try_from(parent_page)?;
+    /// rebalance writes the node modified by a removal along with its ancestors, popped off the descent
+    /// which led to it, and returns the offset of the new root.
+    /// An underflowing node is merged with a sibling node, and the parent is checked in turn up the tree.
+    /// If the merged nodes overflow they are split again, effectively borrowing keys from the sibling.
+    /// If the root is left with a single child it is replaced by it.
+    /// Nodes are only written once merged, so a node of the pending commit is replaced in place
+    /// and the root left behind by a merge is never copied.
+    fn rebalance(
+        &mut self,
+        mut node: Node,
+        mut node_offset: Offset,
+        mut descent: Descent,
+    ) -> Result<Offset, Error> {
+        while let Some((mut parent_node, parent_offset, idx)) = descent.pop() {
             // The parent has to be an "internal" node.
-            match parent_node.node_type {
-                NodeType::Internal(ref mut children, ref mut keys) => {
-                    let idx = keys.binary_search(key).unwrap_or_else(|x| x);
-                    // The sibling is in idx +- 1 as the above index led
-                    // the downward search to node.
-                    let sibling_idx;
-                    match idx > 0 {
-                        false => sibling_idx = idx + 1,
-                        true => sibling_idx = idx - 1,
-                    }
+            let (children, keys) = match &mut parent_node.node_type {
+                NodeType::Internal(children, keys) => (children, keys),
+                _ => return Err(Error::UnexpectedError),
+            };
+            if !self.is_node_underflow(&node)? {
+                children[idx] = self.store_node(&node, node_offset)?;
+                node = parent_node;
+                node_offset = parent_offset;
+                continue;
+            }
+            // The sibling is in idx +- 1 as idx led the descent to node.
+            let sibling_idx = if idx > 0 { idx - 1 } else { idx + 1 };
+
+            let sibling_offset = children
+                .get(sibling_idx)
+                .ok_or(Error::UnexpectedError)?
+                .clone();
+            let sibling = self.read_node(&sibling_offset)?;
+            let merged_node_idx = cmp::min(idx, sibling_idx);
+            // The key separating the two nodes in the parent.
+            let separator = keys.remove(merged_node_idx);
+            let mut merged_node = if sibling_idx < idx {
+                self.merge(sibling, node, separator)?
+            } else {
+                self.merge(node, sibling, separator)?
+            };
+            // remove the old nodes.
+            children.remove(merged_node_idx);
+            // remove shifts nodes to the left.
+            children.remove(merged_node_idx);
+            // The merged node takes the place of either node if it is part of the pending commit.
+            let (merged_node_offset, sibling_offset) = if self.dirty.contains(&node_offset) {
+                (node_offset, sibling_offset)
+            } else {
+                (sibling_offset, node_offset)
+            };
 
-                    let sibling_offset = children.get(sibling_idx).ok_or(Error::UnexpectedError)?;
-                    let sibling_page = self.pager.get_page(sibling_offset)?;
-                    let sibling = Node::try_from(sibling_page)?;
-                    let merged_node = self.merge(node, sibling)?;
-                    let merged_node_offset =
-                        self.pager.write_page(Page::try_from(&merged_node)?)?;
-                    let merged_node_idx = cmp::min(idx, sibling_idx);
-                    // remove the old nodes.
-                    children.remove(merged_node_idx);
-                    // remove shifts nodes to the left.
-                    children.remove(merged_node_idx);
+            if self.is_node_overflow(&merged_node)? {
+                // Redistribute the keys of the two nodes by splitting them again.
+                let (median, sibling) = self.split(&mut merged_node)?;
+                let merged_node_offset = self.store_node(&merged_node, merged_node_offset)?;
+                let sibling_offset = self.store_node(&sibling, sibling_offset)?;
+                children.insert(merged_node_idx, sibling_offset);
+                children.insert(merged_node_idx, merged_node_offset);
+                keys.insert(merged_node_idx, median);
+            } else {
+                if self.dirty.contains(&sibling_offset) {
+                    self.dirty.release(&sibling_offset)?;
+                }
+                if parent_node.is_root && children.is_empty() {
                     // if the parent is the root, and there is a single child - the merged node -
                     // we can safely replace the root with the child.
-                    if parent_node.is_root && children.is_empty() {
-                        self.wal.set_root(merged_node_offset)?;
-                        return Ok(());
+                    self.counters.root_flip();
+                    if self.dirty.contains(&parent_offset) {
+                        self.dirty.release(&parent_offset)?;
                     }
-                    // remove the keys that separated the two nodes from each other:
-                    keys.remove(idx);
-                    // write the new node in place.
-                    children.insert(merged_node_idx, merged_node_offset);
-                    // write the updated parent back to disk and continue up the tree.
-                    self.pager
-                        .write_page_at_offset(Page::try_from(&parent_node)?, &parent_offset)?;
-                    return self.borrow_if_needed(parent_node, key);
+                    merged_node.is_root = true;
+                    return self.store_node(&merged_node, merged_node_offset);
                 }
-                _ => return Err(Error::UnexpectedError),
+                children.insert(
+                    merged_node_idx,
+                    self.store_node(&merged_node, merged_node_offset)?,
+                );
             }
+            // continue up the tree with the updated parent.
+            node = parent_node;
+            node_offset = parent_offset;
+        }
+        self.store_node(&node, node_offset)
+    }
+
+    /// store_node stores a modified node, a node of the pending commit is replaced in place
+    /// while a committed one is copied, returns the offset the node is going to be written at.
+    fn store_node(&mut self, node: &Node, offset: Offset) -> Result<Offset, Error> {
+        if self.dirty.contains(&offset) {
+            self.write_node_at(node, &offset)?;
+            return Ok(offset);
+        }
+        self.write_node(node)
+    }
+
+    fn is_node_overflow(&self, node: &Node) -> Result<bool, Error> {
+        match &node.node_type {
+            NodeType::Leaf(_, pairs) => Ok(pairs.len() > (2 * self.b - 1)),
+            NodeType::Internal(_, keys) => Ok(keys.len() > (2 * self.b - 1)),
+            NodeType::Unexpected => Err(Error::UnexpectedError),
         }
-        Ok(())
     }
 
     // merges two *sibling* nodes, it assumes the following:
     // 1. the two nodes are of the same type.
-    // 2. the two nodes do not accumulate to an overflow,
-    // i.e. |first.keys| + |second.keys| <= [2*(b-1) for keys or 2*b for offsets].
-    fn merge(&self, first: Node, second: Node) -> Result<Node, Error> {
+    // 2. the first node holds the smaller keys.
+    // The separator is the key dividing the two nodes in their parent,
+    // it is moved down to the merged node when merging internal nodes.
+    fn merge(&mut self, first: Node, second: Node, separator: Key) -> Result<Node, Error> {
+        self.counters.merge();
         match first.node_type {
-            NodeType::Leaf(first_offset, first_pairs) => {
-                if let NodeType::Leaf(second_offset, second_pairs) = second.node_type {
-                    let merged_pairs: Vec<KeyValuePair> = first_pairs
-                        .into_iter()
-                        .chain(second_pairs.into_iter())
-                        .collect();
-                    let new_offset = todo!();
+            NodeType::Leaf(first_offset, mut first_pairs) => {
+                if let NodeType::Leaf(second_offset, mut second_pairs) = second.node_type {
+                    // Move the values of both data pages to a new data page.
+                    let first_data = self.read_data_page(&first_offset)?;
+                    let second_data = self.read_data_page(&second_offset)?;
+                    let mut data_page = first_data.extract(&mut first_pairs)?;
+                    for pair in second_pairs.iter_mut() {
+                        let value = second_data.get(pair.idx).ok_or(Error::UnexpectedError)?;
+                        pair.idx = data_page.insert(value)?;
+                    }
+                    let merged_pairs: Vec<KeyValuePair> =
+                        first_pairs.into_iter().chain(second_pairs).collect();
+                    // The merged values replace a data page of the pending commit, if any.
+                    let mut new_offset = if self.dirty.contains(&first_offset) {
+                        if self.dirty.contains(&second_offset) {
+                            self.dirty.release(&second_offset)?;
+                        }
+                        first_offset
+                    } else {
+                        second_offset
+                    };
+                    self.update_data_page(&mut new_offset, data_page)?;
                     let node_type = NodeType::Leaf(new_offset, merged_pairs);
-                    Ok(Node::new(node_type, first.is_root, first.parent_offset))
+                    Ok(Node::new(node_type, first.is_root))
                 } else {
                     Err(Error::UnexpectedError)
                 }
@@ -396,14 +1629,13 @@ impl BTree {
                 if let NodeType::Internal(second_offsets, second_keys) = second.node_type {
                     let merged_keys: Vec<Key> = first_keys
                         .into_iter()
-                        .chain(second_keys.into_iter())
-                        .collect();
-                    let merged_offsets: Vec<Offset> = first_offsets
-                        .into_iter()
-                        .chain(second_offsets.into_iter())
+                        .chain(std::iter::once(separator))
+                        .chain(second_keys)
                         .collect();
+                    let merged_offsets: Vec<Offset> =
+                        first_offsets.into_iter().chain(second_offsets).collect();
                     let node_type = NodeType::Internal(merged_offsets, merged_keys);
-                    Ok(Node::new(node_type, first.is_root, first.parent_offset))
+                    Ok(Node::new(node_type, first.is_root))
                 } else {
                     Err(Error::UnexpectedError)
                 }
@@ -412,12 +1644,64 @@ impl BTree {
         }
     }
 
+    /// migrate rewrites every key-value pair of the tree into a new tree built by the given builder,
+    /// the new tree is written using the newest page layout regardless of the layout of this tree.
+    /// The tree is consumed as the new tree is meant to replace it.
+    pub fn migrate(mut self, builder: &BTreeBuilder) -> Result<BTree, Error> {
+        let root_offset = self.header.root.clone();
+        let mut btree = builder.build()?;
+        let now = self.now();
+        for (pair, value) in self.sub_tree_entries(root_offset)? {
+            if !pair.is_expired(now) {
+                btree.insert_entry(pair.key, value, pair.expiry)?;
+            }
+        }
+        Ok(btree)
+    }
+
+    /// sub_tree_pairs recursively collects the key-value pairs of the nodes rooted at a node given by its offset
+    /// in key order, expired pairs included.
+    pub(crate) fn sub_tree_pairs(
+        &mut self,
+        offset: Offset,
+    ) -> Result<Vec<(String, String)>, Error> {
+        Ok(self
+            .sub_tree_entries(offset)?
+            .into_iter()
+            .map(|(pair, value)| (pair.key, value))
+            .collect())
+    }
+
+    /// sub_tree_entries recursively collects the pairs of the nodes rooted at a node given by its offset
+    /// along with their values in key order.
+    fn sub_tree_entries(&mut self, offset: Offset) -> Result<Vec<(KeyValuePair, String)>, Error> {
+        match self.read_node(&offset)?.node_type {
+            NodeType::Internal(children, _) => {
+                let mut res = vec![];
+                for child_offset in children {
+                    res.append(&mut self.sub_tree_entries(child_offset)?);
+                }
+                Ok(res)
+            }
+            NodeType::Leaf(data_offset, pairs) => {
+                let data_page = self.read_data_page(&data_offset)?;
+                pairs
+                    .into_iter()
+                    .map(|pair| {
+                        let value = data_page.get(pair.idx).ok_or(Error::UnexpectedError)?;
+                        Ok((pair, value))
+                    })
+                    .collect()
+            }
+            NodeType::Unexpected => Err(Error::UnexpectedError),
+        }
+    }
+
     /// print_sub_tree is a helper function for recursively printing the nodes rooted at a node given by its offset.
     fn print_sub_tree(&mut self, prefix: String, offset: Offset) -> Result<(), Error> {
         println!("{}Node at offset: {}", prefix, offset.0);
         let curr_prefix = format!("{}|->", prefix);
-        let page = self.pager.get_page(&offset)?;
-        let node = Node::try_from(page)?;
+        let node = self.read_node(&offset)?;
         match node.node_type {
             NodeType::Internal(children, keys) => {
                 println!("{}Keys: {:?}", curr_prefix, keys);
@@ -442,7 +1726,7 @@ impl BTree {
     /// print is a helper for recursively printing the tree.
     pub fn print(&mut self) -> Result<(), Error> {
         println!();
-        let root_offset = self.wal.get_root()?;
+        let root_offset = self.header.root.clone();
         self.print_sub_tree("".to_string(), root_offset)
     }
 }
@@ -456,10 +1740,11 @@ mod tests {
         use crate::btree::BTreeBuilder;
         use std::path::Path;
 
-        let mut btree = BTreeBuilder::new()
-            .path(Path::new("/tmp/db"))
-            .b_parameter(2)
-            .build()?;
+        let path = Path::new("/tmp/search_works/db");
+        std::fs::create_dir_all(path.parent().unwrap())?;
+        let _ = std::fs::remove_file(path);
+
+        let mut btree = BTreeBuilder::new().path(path).b_parameter(2).build()?;
         btree.insert("a".to_string(), "shalom".to_string())?;
         btree.insert("b".to_string(), "hello".to_string())?;
         btree.insert("c".to_string(), "marhaba".to_string())?;
@@ -478,10 +1763,11 @@ mod tests {
         use crate::btree::BTreeBuilder;
         use std::path::Path;
 
-        let mut btree = BTreeBuilder::new()
-            .path(Path::new("/tmp/db"))
-            .b_parameter(2)
-            .build()?;
+        let path = Path::new("/tmp/insert_works/db");
+        std::fs::create_dir_all(path.parent().unwrap())?;
+        let _ = std::fs::remove_file(path);
+
+        let mut btree = BTreeBuilder::new().path(path).b_parameter(2).build()?;
         btree.insert("a".to_string(), "shalom".to_string())?;
         btree.insert("b".to_string(), "hello".to_string())?;
         btree.insert("c".to_string(), "marhaba".to_string())?;
@@ -522,16 +1808,147 @@ mod tests {
     }
 
     #[test]
-    fn delete_works() -> Result<(), Error> {
-        use crate::btree::BTreeBuilder;
-        use crate::error::Error;
+    fn long_keys_work() -> Result<(), Error> {
+        use crate::btree::{BTreeBuilder, MAX_KEY_SIZE};
+        use crate::node_type::Key;
+        use std::path::Path;
+
+        let path = Path::new("/tmp/long_keys_work/db");
+        std::fs::create_dir_all(path.parent().unwrap())?;
+        let _ = std::fs::remove_file(path);
+
+        // Keys longer than 32 bytes, sharing a long prefix or differing right away.
+        let keys: Vec<String> = (0..40)
+            .map(|idx| match idx % 2 {
+                0 => format!("tenants/acme/users/{:04}/profile/settings", idx),
+                _ => format!("{:04}/{}", idx, "x".repeat(100)),
+            })
+            .collect();
+        let mut btree = BTreeBuilder::new().path(path).b_parameter(2).build()?;
+        for key in keys.iter() {
+            btree.insert(key.clone(), key.to_uppercase())?;
+        }
+        for key in keys.iter() {
+            assert_eq!(btree.search(key.clone())?, key.to_uppercase());
+        }
+        // A key sharing the first 32 bytes of a stored key is a different key.
+        let res = btree.search(keys[0][..32].to_string());
+        assert!(matches!(res, Err(Error::KeyNotFound)));
+
+        // Keys are at most MAX_KEY_SIZE bytes long, the tree is left as it was.
+        let res = btree.insert("y".repeat(MAX_KEY_SIZE + 1), "too long".to_string());
+        assert!(matches!(res, Err(Error::KeyOverflowError)));
+
+        drop(btree);
+        let mut btree = BTreeBuilder::new().path(path).b_parameter(2).build()?;
+        for key in keys.iter().step_by(3) {
+            btree.delete(Key(key.clone()))?;
+        }
+        for (idx, key) in keys.iter().enumerate() {
+            match idx % 3 {
+                0 => assert!(matches!(btree.search(key.clone()), Err(Error::KeyNotFound))),
+                _ => assert_eq!(btree.search(key.clone())?, key.to_uppercase()),
+            }
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn long_keys_never_block_other_writes() -> Result<(), Error> {
+        use crate::btree::{BTreeBuilder, WriteOp, MAX_KEY_SIZE};
         use crate::node_type::Key;
+        use crate::storage::MemoryBackend;
         use std::path::Path;
 
         let mut btree = BTreeBuilder::new()
-            .path(Path::new("/tmp/db"))
+            .path(Path::new("/db"))
             .b_parameter(2)
+            .backend(MemoryBackend::new())
             .build()?;
+        let neighbour = format!("{}b", "p".repeat(200));
+        btree.insert(neighbour.clone(), "neighbour".to_string())?;
+        // A key is refused for its length alone, whatever its neighbours share with it.
+        let too_long = format!("{}{}", "p".repeat(200), "a".repeat(100));
+        let res = btree.insert(too_long.clone(), "too long".to_string());
+        assert!(matches!(res, Err(Error::KeyOverflowError)));
+        let results = btree.write_batch(&[
+            WriteOp::Insert(too_long, "too long".to_string()),
+            WriteOp::Insert("b".to_string(), "b".to_string()),
+        ])?;
+        assert!(matches!(results[0], Err(Error::KeyOverflowError)));
+        assert!(results[1].is_ok());
+
+        // The longest keys accepted are stored next to unrelated keys, which can be inserted and deleted.
+        let longest = format!("{}{}", "p".repeat(200), "a".repeat(MAX_KEY_SIZE - 200));
+        btree.insert(longest.clone(), "longest".to_string())?;
+        btree.insert("a".to_string(), "a".to_string())?;
+        btree.delete(Key(neighbour.clone()))?;
+        btree.delete(Key("b".to_string()))?;
+        assert_eq!(btree.search(longest.clone())?, "longest");
+        assert_eq!(btree.search("a".to_string())?, "a");
+        assert!(matches!(btree.search(neighbour), Err(Error::KeyNotFound)));
+        btree.delete(Key(longest))?;
+        btree.check()
+    }
+
+    #[test]
+    fn large_values_work() -> Result<(), Error> {
+        use crate::btree::BTreeBuilder;
+        use crate::node_type::Key;
+        use crate::page_layout::PAGE_SIZE;
+        use crate::storage::MemoryBackend;
+        use std::path::Path;
+
+        let builder = BTreeBuilder::new()
+            .path(Path::new("/db"))
+            .b_parameter(2)
+            .backend(MemoryBackend::new());
+        let mut btree = builder.build()?;
+        let value = |idx: usize| format!("{:02}", idx).repeat(650);
+        for idx in 0..20 {
+            btree.insert(format!("{:02}", idx), value(idx))?;
+        }
+        // The values of a leaf share its data page, a value is refused if it does not fit next to them.
+        let res = btree.insert("19a".to_string(), "x".repeat(PAGE_SIZE / 2));
+        assert!(matches!(res, Err(Error::ValueOverflowError)));
+        let res = btree.insert("big".to_string(), "x".repeat(PAGE_SIZE));
+        assert!(matches!(res, Err(Error::ValueOverflowError)));
+        btree.insert("19a".to_string(), "x".repeat(PAGE_SIZE / 4))?;
+        for idx in (0..20).step_by(3) {
+            btree.delete(Key(format!("{:02}", idx)))?;
+        }
+        drop(btree);
+
+        let mut btree = builder.build()?;
+        for idx in 0..20 {
+            match idx % 3 {
+                0 => assert!(matches!(
+                    btree.search(format!("{:02}", idx)),
+                    Err(Error::KeyNotFound)
+                )),
+                _ => assert_eq!(btree.search(format!("{:02}", idx))?, value(idx)),
+            }
+        }
+        assert_eq!(btree.search("19a".to_string())?, "x".repeat(PAGE_SIZE / 4));
+        assert!(matches!(
+            btree.search("big".to_string()),
+            Err(Error::KeyNotFound)
+        ));
+        btree.check()
+    }
+
+    #[test]
+    fn delete_works() -> Result<(), Error> {
+        use crate::btree::BTreeBuilder;
+        use crate::error::Error;
+        use crate::node_type::Key;
+        use std::path::Path;
+
+        let path = Path::new("/tmp/delete_works/db");
+        std::fs::create_dir_all(path.parent().unwrap())?;
+        let _ = std::fs::remove_file(path);
+
+        let mut btree = BTreeBuilder::new().path(path).b_parameter(2).build()?;
         btree.insert("d".to_string(), "olah".to_string())?;
         btree.insert("e".to_string(), "salam".to_string())?;
         btree.insert("f".to_string(), "hallo".to_string())?;
@@ -563,4 +1980,1100 @@ mod tests {
 
         Ok(())
     }
+
+    #[test]
+    fn delete_after_split_works() -> Result<(), Error> {
+        use crate::btree::BTreeBuilder;
+        use crate::node_type::Key;
+        use std::path::Path;
+
+        let path = Path::new("/tmp/delete_after_split_works/db");
+        std::fs::create_dir_all(path.parent().unwrap())?;
+        let _ = std::fs::remove_file(path);
+
+        let mut btree = BTreeBuilder::new().path(path).b_parameter(2).build()?;
+        // Values are appended to the data page in insertion order rather than in key order,
+        // splitting a leaf has to move each value along with the key referring to it.
+        let keys = ["d", "a", "f", "b", "h", "c", "g", "e"];
+        for key in keys.iter() {
+            btree.insert(key.to_string(), format!("value {}", key))?;
+        }
+        for key in keys.iter() {
+            assert_eq!(btree.search(key.to_string())?, format!("value {}", key));
+        }
+
+        // Deleting a key drops its own value, the values of the remaining keys are left intact.
+        for key in ["a", "f", "c"].iter() {
+            btree.delete(Key(key.to_string()))?;
+            let res = btree.search(key.to_string());
+            assert!(matches!(res, Err(Error::KeyNotFound)));
+        }
+        for key in ["d", "b", "h", "g", "e"].iter() {
+            assert_eq!(btree.search(key.to_string())?, format!("value {}", key));
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn delete_merges_nodes() -> Result<(), Error> {
+        use crate::btree::BTreeBuilder;
+        use crate::node_type::Key;
+        use std::path::Path;
+
+        let path = Path::new("/tmp/delete_merges_nodes/db");
+        std::fs::create_dir_all(path.parent().unwrap())?;
+        let _ = std::fs::remove_file(path);
+
+        let mut btree = BTreeBuilder::new().path(path).b_parameter(2).build()?;
+        let keys: Vec<String> = (0..16)
+            .map(|idx| format!("key{:02}", (idx * 7) % 16))
+            .collect();
+        for key in keys.iter() {
+            btree.insert(key.clone(), format!("value {}", key))?;
+        }
+        // Underflowing leaves and internal nodes are merged with their siblings, or borrow from them,
+        // until the root is left with a single child and replaced by it.
+        for (idx, key) in keys.iter().enumerate() {
+            btree.delete(Key(key.clone()))?;
+            for (remaining_idx, remaining) in keys.iter().enumerate() {
+                let res = btree.search(remaining.clone());
+                if remaining_idx <= idx {
+                    assert!(matches!(res, Err(Error::KeyNotFound)));
+                } else {
+                    assert_eq!(res?, format!("value {}", remaining));
+                }
+            }
+        }
+
+        // The emptied tree remains usable.
+        btree.insert("a".to_string(), "shalom".to_string())?;
+        assert_eq!(btree.search("a".to_string())?, "shalom");
+        Ok(())
+    }
+
+    #[test]
+    fn reopen_works() -> Result<(), Error> {
+        use crate::btree::BTreeBuilder;
+        use std::path::Path;
+
+        let path = Path::new("/tmp/reopen_works/db");
+        std::fs::create_dir_all(path.parent().unwrap())?;
+        let _ = std::fs::remove_file(path);
+
+        let mut btree = BTreeBuilder::new().path(path).b_parameter(2).build()?;
+        btree.insert("a".to_string(), "shalom".to_string())?;
+        btree.insert("b".to_string(), "hello".to_string())?;
+        btree.insert("c".to_string(), "marhaba".to_string())?;
+        btree.insert("d".to_string(), "olah".to_string())?;
+        drop(btree);
+
+        let mut btree = BTreeBuilder::new().path(path).b_parameter(2).build()?;
+        let v = btree.search("a".to_string())?;
+        assert_eq!(v, "shalom");
+
+        let v = btree.search("d".to_string())?;
+        assert_eq!(v, "olah");
+        Ok(())
+    }
+
+    #[test]
+    fn reopen_validates_header() -> Result<(), Error> {
+        use crate::btree::BTreeBuilder;
+        use std::path::Path;
+
+        let path = Path::new("/tmp/reopen_validates_header/db");
+        std::fs::create_dir_all(path.parent().unwrap())?;
+        let _ = std::fs::remove_file(path);
+
+        BTreeBuilder::new().path(path).b_parameter(2).build()?;
+        let res = BTreeBuilder::new().path(path).b_parameter(3).build();
+        assert!(matches!(res, Err(Error::BParameterMismatch)));
+
+        // Bump the format version to one this version cannot read.
+        let mut raw = std::fs::read(path)?;
+        raw[8..16].clone_from_slice(&99u64.to_be_bytes());
+        std::fs::write(path, &raw)?;
+        let res = BTreeBuilder::new().path(path).b_parameter(2).build();
+        assert!(matches!(res, Err(Error::UnsupportedFormatVersion(99))));
+
+        std::fs::write(path, [0x01; 8192])?;
+        let res = BTreeBuilder::new().path(path).b_parameter(2).build();
+        assert!(matches!(res, Err(Error::InvalidFileHeader)));
+        Ok(())
+    }
+
+    #[test]
+    fn migrate_works() -> Result<(), Error> {
+        use crate::btree::BTreeBuilder;
+        use std::path::Path;
+
+        let path = Path::new("/tmp/migrate_works/db");
+        let new_path = Path::new("/tmp/migrate_works/db.new");
+        std::fs::create_dir_all(path.parent().unwrap())?;
+        let _ = std::fs::remove_file(path);
+        let _ = std::fs::remove_file(new_path);
+
+        let mut btree = BTreeBuilder::new().path(path).b_parameter(2).build()?;
+        btree.insert("d".to_string(), "olah".to_string())?;
+        btree.insert("e".to_string(), "salam".to_string())?;
+        btree.insert("f".to_string(), "hallo".to_string())?;
+        btree.insert("a".to_string(), "shalom".to_string())?;
+        btree.insert("b".to_string(), "hello".to_string())?;
+        btree.insert("c".to_string(), "marhaba".to_string())?;
+
+        let mut btree = btree.migrate(&BTreeBuilder::new().path(new_path).b_parameter(2))?;
+        for (key, value) in [
+            ("a", "shalom"),
+            ("b", "hello"),
+            ("c", "marhaba"),
+            ("d", "olah"),
+            ("e", "salam"),
+            ("f", "hallo"),
+        ] {
+            assert_eq!(btree.search(key.to_string())?, value);
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn compression_works() -> Result<(), Error> {
+        use crate::btree::BTreeBuilder;
+        use crate::compression::Compression;
+        use std::path::Path;
+
+        let path = Path::new("/tmp/compression_works/db");
+        std::fs::create_dir_all(path.parent().unwrap())?;
+        let _ = std::fs::remove_file(path);
+
+        let mut btree = BTreeBuilder::new()
+            .path(path)
+            .b_parameter(2)
+            .compression(Compression::Lz)
+            .build()?;
+        let value = |name: &str| format!(r#"{{"name":"{}","status":"active"}}"#, name).repeat(5);
+        for key in ["a", "b", "c", "d", "e", "f"] {
+            btree.insert(key.to_string(), value(key))?;
+        }
+        drop(btree);
+
+        // Pages written compressed stay readable when the codec is changed.
+        let mut btree = BTreeBuilder::new().path(path).b_parameter(2).build()?;
+        btree.insert("g".to_string(), "olah".to_string())?;
+        for key in ["a", "b", "c", "d", "e", "f"] {
+            assert_eq!(btree.search(key.to_string())?, value(key));
+        }
+        assert_eq!(btree.search("g".to_string())?, "olah");
+        Ok(())
+    }
+
+    #[test]
+    fn encryption_works() -> Result<(), Error> {
+        use crate::btree::BTreeBuilder;
+        use crate::encryption::{Cipher, XChaCha20Poly1305Cipher};
+        use crate::page_layout::{PAGE_SIZE, PAGE_TRAILER_SIZE};
+        use std::path::Path;
+
+        let path = Path::new("/tmp/encryption_works/db");
+        std::fs::create_dir_all(path.parent().unwrap())?;
+        let _ = std::fs::remove_file(path);
+        let builder = |key: u8| {
+            BTreeBuilder::new()
+                .path(path)
+                .b_parameter(2)
+                .cipher(move || {
+                    let cipher: Box<dyn Cipher> =
+                        Box::new(XChaCha20Poly1305Cipher::new(&[key; 32]));
+                    Ok(cipher)
+                })
+        };
+
+        let mut btree = builder(7).build()?;
+        btree.insert("a".to_string(), "shalom".to_string())?;
+        btree.insert("b".to_string(), "marhaba".to_string())?;
+        drop(btree);
+
+        let raw = std::fs::read(path)?;
+        assert!(!raw.windows(7).any(|window| window == b"marhaba"));
+
+        let mut btree = builder(7).build()?;
+        assert_eq!(btree.search("b".to_string())?, "marhaba");
+        drop(btree);
+
+        let res = BTreeBuilder::new().path(path).b_parameter(2).build();
+        assert!(matches!(res, Err(Error::EncryptionMismatch)));
+        let res = builder(8).build();
+        assert!(matches!(res, Err(Error::AuthenticationFailed)));
+
+        // Tamper with the last page written.
+        let mut raw = std::fs::read(path)?;
+        let last_page = raw.len() - PAGE_SIZE - PAGE_TRAILER_SIZE;
+        raw[last_page] ^= 0x01;
+        std::fs::write(path, &raw)?;
+        let mut btree = builder(7).build()?;
+        let res = btree.search("b".to_string());
+        assert!(matches!(res, Err(Error::AuthenticationFailed)));
+        Ok(())
+    }
+
+    #[test]
+    fn damaged_header_falls_back_to_previous_slot() -> Result<(), Error> {
+        use crate::btree::BTreeBuilder;
+        use crate::encryption::{Cipher, XChaCha20Poly1305Cipher};
+        use crate::page_layout::{PAGE_SIZE, PAGE_TRAILER_SIZE};
+        use std::path::Path;
+
+        let path = Path::new("/tmp/damaged_header_falls_back_to_previous_slot/db");
+        std::fs::create_dir_all(path.parent().unwrap())?;
+        let _ = std::fs::remove_file(path);
+        let builder = BTreeBuilder::new().path(path).b_parameter(2).cipher(|| {
+            let cipher: Box<dyn Cipher> = Box::new(XChaCha20Poly1305Cipher::new(&[7; 32]));
+            Ok(cipher)
+        });
+
+        // The empty tree is written to the first slot, each commit then writes to the other slot.
+        let mut btree = builder.build()?;
+        btree.insert("a".to_string(), "shalom".to_string())?;
+        btree.insert("b".to_string(), "marhaba".to_string())?;
+        drop(btree);
+
+        // The tag of the first slot no longer matches, the second slot holds the previous commit.
+        let slot_size = PAGE_SIZE + PAGE_TRAILER_SIZE;
+        let mut raw = std::fs::read(path)?;
+        raw[slot_size - 1] ^= 0x01;
+        std::fs::write(path, &raw)?;
+        let mut btree = builder.build()?;
+        assert_eq!(btree.search("a".to_string())?, "shalom");
+        let res = btree.search("b".to_string());
+        assert!(matches!(res, Err(Error::KeyNotFound)));
+        btree.insert("c".to_string(), "hallo".to_string())?;
+        drop(btree);
+
+        // The commit following the fall back overwrote the damaged slot.
+        let mut btree = builder.build()?;
+        assert_eq!(btree.search("c".to_string())?, "hallo");
+        drop(btree);
+
+        let mut raw = std::fs::read(path)?;
+        raw[0..slot_size * 2].fill(0x01);
+        std::fs::write(path, &raw)?;
+        let res = builder.build();
+        assert!(matches!(res, Err(Error::InvalidFileHeader)));
+        Ok(())
+    }
+
+    #[test]
+    fn index_works() -> Result<(), Error> {
+        use crate::btree::BTreeBuilder;
+        use crate::node_type::Key;
+        use std::path::Path;
+
+        let path = Path::new("/tmp/index_works/db");
+        std::fs::create_dir_all(path.parent().unwrap())?;
+        let _ = std::fs::remove_file(path);
+        let email = |value: &str| value.split(',').nth(1).map(|email| email.to_string());
+
+        let mut btree = BTreeBuilder::new().path(path).b_parameter(2).build()?;
+        btree.insert("1".to_string(), "alice,alice@x.io".to_string())?;
+        btree.insert("2".to_string(), "bob,bob@x.io".to_string())?;
+        btree.insert("3".to_string(), "carol".to_string())?;
+        drop(btree);
+
+        // Registering the index on an existing tree builds it from the existing pairs.
+        let mut btree = BTreeBuilder::new()
+            .path(path)
+            .b_parameter(2)
+            .index("email", email)
+            .build()?;
+        assert_eq!(
+            btree.search_by_index("email", "bob@x.io".to_string())?,
+            "bob,bob@x.io"
+        );
+        for (key, value) in [("4", "dave,dave@x.io"), ("5", "erin,erin@x.io")] {
+            btree.insert(key.to_string(), value.to_string())?;
+        }
+        btree.insert("2".to_string(), "bob,robert@x.io".to_string())?;
+        btree.delete(Key("1".to_string()))?;
+        let res = btree.insert("6".to_string(), "eve,erin@x.io".to_string());
+        assert!(matches!(res, Err(Error::KeyAlreadyExists)));
+        let res = btree.search("6".to_string());
+        assert!(matches!(res, Err(Error::KeyNotFound)));
+        drop(btree);
+
+        let mut btree = BTreeBuilder::new()
+            .path(path)
+            .b_parameter(2)
+            .index("email", email)
+            .build()?;
+        assert_eq!(
+            btree.search_by_index("email", "robert@x.io".to_string())?,
+            "bob,robert@x.io"
+        );
+        assert_eq!(
+            btree.search_by_index("email", "erin@x.io".to_string())?,
+            "erin,erin@x.io"
+        );
+        for stale in ["bob@x.io", "alice@x.io"] {
+            let res = btree.search_by_index("email", stale.to_string());
+            assert!(matches!(res, Err(Error::KeyNotFound)));
+        }
+        let res = btree.search_by_index("name", "bob".to_string());
+        assert!(matches!(res, Err(Error::IndexNotFound)));
+        Ok(())
+    }
+
+    #[test]
+    fn ttl_works() -> Result<(), Error> {
+        use crate::btree::BTreeBuilder;
+        use crate::node_type::Key;
+        use std::cell::Cell;
+        use std::path::Path;
+        use std::rc::Rc;
+        use std::time::{Duration, SystemTime};
+
+        let path = Path::new("/tmp/ttl_works/db");
+        std::fs::create_dir_all(path.parent().unwrap())?;
+        let _ = std::fs::remove_file(path);
+        let now = Rc::new(Cell::new(
+            SystemTime::UNIX_EPOCH + Duration::from_secs(1000),
+        ));
+        let clock = now.clone();
+        let mut btree = BTreeBuilder::new()
+            .path(path)
+            .b_parameter(2)
+            .index("user", |value| Some(value.to_string()))
+            .clock(move || clock.get())
+            .build()?;
+
+        let minute = Duration::from_secs(60);
+        for key in ["a", "b", "c", "d", "e"] {
+            btree.insert_with_ttl(key.to_string(), format!("user {}", key), minute)?;
+        }
+        btree.insert_with_ttl("f".to_string(), "user f".to_string(), minute * 10)?;
+        btree.insert("g".to_string(), "user g".to_string())?;
+        assert_eq!(btree.search("a".to_string())?, "user a");
+
+        now.set(now.get() + minute * 2);
+        for key in ["a", "b", "c", "d", "e"] {
+            let res = btree.search(key.to_string());
+            assert!(matches!(res, Err(Error::KeyNotFound)));
+        }
+        let res = btree.search_by_index("user", "user a".to_string());
+        assert!(matches!(res, Err(Error::KeyNotFound)));
+        assert_eq!(btree.search("f".to_string())?, "user f");
+
+        // Inserting over an expired pair replaces it.
+        btree.insert("a".to_string(), "user a2".to_string())?;
+        assert_eq!(btree.search("a".to_string())?, "user a2");
+
+        let (purged, cursor) = btree.purge_expired(None, 3)?;
+        assert_eq!((purged, &cursor), (3, &Some(Key("d".to_string()))));
+        assert_eq!(btree.purge_expired(cursor, 3)?, (1, None));
+        assert_eq!(btree.purge_expired(None, 3)?, (0, None));
+        assert_eq!(btree.search("a".to_string())?, "user a2");
+        for key in ["f", "g"] {
+            assert_eq!(btree.search(key.to_string())?, format!("user {}", key));
+        }
+        // The index entries of the purged pairs are gone as well.
+        btree.insert("h".to_string(), "user b".to_string())?;
+        assert_eq!(
+            btree.search_by_index("user", "user b".to_string())?,
+            "user b"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn purge_expired_resumes_from_cursor() -> Result<(), Error> {
+        use crate::btree::BTreeBuilder;
+        use crate::node_type::Key;
+        use crate::storage::MemoryBackend;
+        use std::path::Path;
+        use std::time::Duration;
+
+        let mut btree = BTreeBuilder::new()
+            .path(Path::new("/db"))
+            .b_parameter(2)
+            .backend(MemoryBackend::new())
+            .build()?;
+        for idx in 0..300 {
+            let key = format!("{:03}", idx);
+            match idx % 10 {
+                0 => btree.insert_with_ttl(key.clone(), key, Duration::ZERO)?,
+                _ => btree.insert(key.clone(), key)?,
+            }
+        }
+        let before = btree.stats();
+        assert_eq!(
+            btree.purge_expired(Some(Key("999".to_string())), 5)?,
+            (0, None)
+        );
+        let walk = btree.stats().node_pages_read - before.node_pages_read;
+
+        // Each batch resumes after the last pair it purged rather than walking the tree from its start.
+        let before = btree.stats();
+        let (mut purged, mut batches, mut cursor) = (0, 0, None);
+        loop {
+            let (count, next) = btree.purge_expired(cursor, 5)?;
+            purged += count;
+            batches += 1;
+            cursor = match next {
+                Some(next) => Some(next),
+                None => break,
+            };
+        }
+        assert_eq!((purged, batches), (30, 7));
+        let reads = btree.stats().node_pages_read - before.node_pages_read;
+        let full = btree.stats();
+        btree.purge_expired(None, 5)?;
+        let full_walk = btree.stats().node_pages_read - full.node_pages_read;
+        assert!(walk < full_walk);
+        assert!(reads < 2 * full_walk);
+        for idx in 0..300 {
+            let key = format!("{:03}", idx);
+            match idx % 10 {
+                0 => assert!(matches!(btree.search(key), Err(Error::KeyNotFound))),
+                _ => assert_eq!(btree.search(key.clone())?, key),
+            }
+        }
+        btree.check()
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn replication_works() -> Result<(), Error> {
+        use crate::btree::BTreeBuilder;
+        use crate::node_type::Key;
+        use std::os::unix::net::UnixStream;
+        use std::path::Path;
+        use std::thread;
+
+        let path = Path::new("/tmp/replication_works/db");
+        let follower_path = Path::new("/tmp/replication_works/follower");
+        std::fs::create_dir_all(path.parent().unwrap())?;
+        let _ = std::fs::remove_file(path);
+        let _ = std::fs::remove_file(follower_path);
+
+        let mut btree = BTreeBuilder::new().path(path).b_parameter(2).build()?;
+        btree.insert("a".to_string(), "shalom".to_string())?;
+
+        let (source, mut destination) = UnixStream::pair()?;
+        let follower = thread::spawn(move || -> Result<(), Error> {
+            let mut follower = BTreeBuilder::new()
+                .path(follower_path)
+                .b_parameter(2)
+                .follower(true)
+                .build()?;
+            let res = follower.insert("z".to_string(), "zap".to_string());
+            assert!(matches!(res, Err(Error::ReadOnly)));
+            while follower.apply_replication(&mut destination)? {}
+            Ok(())
+        });
+
+        btree.add_replica(source)?;
+        for (key, value) in [
+            ("b", "hello"),
+            ("c", "marhaba"),
+            ("d", "olah"),
+            ("e", "salam"),
+        ] {
+            btree.insert(key.to_string(), value.to_string())?;
+        }
+        btree.delete(Key("b".to_string()))?;
+        // Closing the stream ends the follower's loop.
+        drop(btree);
+        follower.join().unwrap()?;
+
+        let mut follower = BTreeBuilder::new()
+            .path(follower_path)
+            .b_parameter(2)
+            .follower(true)
+            .build()?;
+        for (key, value) in [
+            ("a", "shalom"),
+            ("c", "marhaba"),
+            ("d", "olah"),
+            ("e", "salam"),
+        ] {
+            assert_eq!(follower.search(key.to_string())?, value);
+        }
+        let res = follower.search("b".to_string());
+        assert!(matches!(res, Err(Error::KeyNotFound)));
+        Ok(())
+    }
+
+    #[test]
+    fn replication_survives_power_loss() -> Result<(), Error> {
+        use crate::btree::BTreeBuilder;
+        use crate::fault::FaultyBackend;
+        use crate::storage::MemoryBackend;
+        use std::cell::{Cell, RefCell};
+        use std::io::Write;
+        use std::path::Path;
+        use std::rc::Rc;
+
+        /// Stream buffers the stream shipped to a replica, its writes fail once closed.
+        #[derive(Clone, Default)]
+        struct Stream {
+            buf: Rc<RefCell<Vec<u8>>>,
+            closed: Rc<Cell<bool>>,
+        }
+
+        impl Write for Stream {
+            fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+                if self.closed.get() {
+                    return Err(std::io::Error::other("closed"));
+                }
+                self.buf.borrow_mut().write(buf)
+            }
+
+            fn flush(&mut self) -> std::io::Result<()> {
+                Ok(())
+            }
+        }
+
+        let mut btree = BTreeBuilder::new()
+            .path(Path::new("/db"))
+            .b_parameter(2)
+            .backend(MemoryBackend::new())
+            .build()?;
+        let stream = Stream::default();
+        btree.add_replica(stream.clone())?;
+        for key in ["a", "b", "c", "d", "e"] {
+            btree.insert(key.to_string(), format!("value {}", key))?;
+        }
+
+        let backend = FaultyBackend::new(MemoryBackend::new());
+        let follower_builder = BTreeBuilder::new()
+            .path(Path::new("/follower"))
+            .b_parameter(2)
+            .follower(true)
+            .backend(backend.clone());
+        let mut follower = follower_builder.build()?;
+        let shipped = stream.buf.borrow().clone();
+        let mut reader = shipped.as_slice();
+        while follower.apply_replication(&mut reader)? {}
+        drop(follower);
+        // The batches applied are durable.
+        backend.power_loss()?;
+        let mut follower = follower_builder.build()?;
+        for key in ["a", "b", "c", "d", "e"] {
+            assert_eq!(follower.search(key.to_string())?, format!("value {}", key));
+        }
+
+        // A replica which cannot be shipped a commit is dropped, without failing the commit, and counted.
+        stream.closed.set(true);
+        btree.insert("f".to_string(), "value f".to_string())?;
+        btree.insert("g".to_string(), "value g".to_string())?;
+        assert_eq!(btree.stats().replicas_dropped, 1);
+        Ok(())
+    }
+
+    #[test]
+    fn rollback_works() -> Result<(), Error> {
+        use crate::btree::BTreeBuilder;
+        use crate::change_log::{Change, ChangeOp};
+        use crate::node_type::Key;
+        use std::path::Path;
+
+        let path = Path::new("/tmp/rollback_works/db");
+        let copy_path = Path::new("/tmp/rollback_works/copy");
+        std::fs::create_dir_all(path.parent().unwrap())?;
+        let _ = std::fs::remove_file(path);
+        let _ = std::fs::remove_file(copy_path);
+        let upper = |value: &str| Some(value.to_uppercase());
+        let builder = BTreeBuilder::new()
+            .path(path)
+            .b_parameter(2)
+            .index("upper", upper);
+
+        let mut btree = builder.build()?;
+        for key in ["a", "b", "c"] {
+            btree.insert(key.to_string(), format!("value {}", key))?;
+        }
+        btree.delete(Key("b".to_string()))?;
+        btree.insert("d".to_string(), "value d".to_string())?;
+        let history = btree.history()?;
+        assert_eq!(history.last().map(|commit| commit.seq), Some(5));
+        assert!(history
+            .windows(2)
+            .all(|commits| commits[0].seq <= commits[1].seq
+                && commits[0].timestamp <= commits[1].timestamp));
+
+        btree.copy_version_to(3, copy_path)?;
+        let mut copy = BTreeBuilder::new()
+            .path(copy_path)
+            .b_parameter(2)
+            .index("upper", upper)
+            .build()?;
+        assert_eq!(
+            copy.search_by_index("upper", "VALUE B".to_string())?,
+            "value b"
+        );
+        let res = copy.search("d".to_string());
+        assert!(matches!(res, Err(Error::KeyNotFound)));
+
+        btree.rollback_to(3)?;
+        assert_eq!(btree.search("b".to_string())?, "value b");
+        assert_eq!(
+            btree.search_by_index("upper", "VALUE B".to_string())?,
+            "value b"
+        );
+        let res = btree.search_by_index("upper", "VALUE D".to_string());
+        assert!(matches!(res, Err(Error::KeyNotFound)));
+        // The rollback is recorded as the changes it undid.
+        let changes = btree.changes_since(5)?.collect::<Result<Vec<_>, Error>>()?;
+        let mut insert = Change::new(
+            ChangeOp::Insert,
+            "b".to_string(),
+            Some("value b".to_string()),
+        );
+        insert.seq = 6;
+        let mut delete = Change::new(ChangeOp::Delete, "d".to_string(), None);
+        delete.seq = 7;
+        assert_eq!(changes, vec![insert, delete]);
+        drop(btree);
+
+        // The history is kept across reopens.
+        let mut btree = builder.build()?;
+        assert_eq!(btree.history()?.len(), history.len() + 1);
+        assert_eq!(btree.search("b".to_string())?, "value b");
+        let res = btree.search("d".to_string());
+        assert!(matches!(res, Err(Error::KeyNotFound)));
+        Ok(())
+    }
+
+    #[test]
+    fn stats_works() -> Result<(), Error> {
+        use crate::btree::BTreeBuilder;
+        use crate::node_type::Key;
+        use crate::storage::MemoryBackend;
+        use std::path::Path;
+
+        let mut btree = BTreeBuilder::new()
+            .path(Path::new("/stats_works/db"))
+            .b_parameter(2)
+            .backend(MemoryBackend::new())
+            .build()?;
+        let counters = btree.counters();
+        let opened = btree.stats();
+        assert_eq!(opened.wal_records, 1);
+        assert_eq!(opened.user_bytes, 0);
+        assert_eq!(opened.write_amplification(), 0.0);
+
+        for key in ["a", "b", "c", "d", "e", "f"] {
+            btree.insert(key.to_string(), "value".to_string())?;
+        }
+        btree.search("c".to_string())?;
+        let inserted = btree.stats();
+        assert_eq!(inserted, counters.snapshot());
+        assert_eq!(inserted.wal_records, 7);
+        assert_eq!(inserted.user_bytes, 6 * 6);
+        assert!(inserted.splits >= 2);
+        assert_eq!(inserted.root_flips, 1);
+        assert!(inserted.node_pages_read > 0 && inserted.data_pages_read > 0);
+        assert!(inserted.node_pages_written > 0 && inserted.data_pages_written > 0);
+        assert!(inserted.bytes_appended > 0 && inserted.bytes_written > inserted.bytes_appended);
+        assert!(inserted.write_amplification() > 1.0);
+
+        for key in ["a", "b", "c", "d", "e", "f"] {
+            btree.delete(Key(key.to_string()))?;
+        }
+        let deleted = counters.snapshot();
+        assert_eq!(deleted.wal_records, 13);
+        assert_eq!(deleted.user_bytes, inserted.user_bytes);
+        assert!(deleted.merges >= 2);
+        assert_eq!(deleted.root_flips, 2);
+        btree.check()
+    }
+
+    #[test]
+    fn delete_rebalances_along_descent() -> Result<(), Error> {
+        use crate::btree::BTreeBuilder;
+        use crate::node_type::Key;
+        use crate::storage::MemoryBackend;
+        use std::path::Path;
+
+        let mut btree = BTreeBuilder::new()
+            .path(Path::new("/delete_rebalances_along_descent/db"))
+            .b_parameter(2)
+            .backend(MemoryBackend::new())
+            .build()?;
+        let keys: Vec<String> = (0..64).map(|idx| format!("key{:02}", idx)).collect();
+        for key in &keys {
+            btree.insert(key.clone(), key.to_uppercase())?;
+        }
+
+        // Deleting every other key, then the rest in reverse order, merges nodes across several levels.
+        for (idx, key) in keys.iter().enumerate().filter(|(idx, _)| idx % 2 == 0) {
+            btree.delete(Key(key.clone()))?;
+            btree.check()?;
+            assert!(matches!(btree.search(key.clone()), Err(Error::KeyNotFound)));
+            assert_eq!(
+                btree.search(keys[idx + 1].clone())?,
+                keys[idx + 1].to_uppercase()
+            );
+        }
+        for key in keys.iter().skip(1).step_by(2).rev() {
+            btree.delete(Key(key.clone()))?;
+            btree.check()?;
+        }
+        assert!(btree.stats().merges > 0);
+        Ok(())
+    }
+
+    #[test]
+    fn delete_appends_reachable_pages_only() -> Result<(), Error> {
+        use crate::btree::{BTree, BTreeBuilder};
+        use crate::node_type::{Key, NodeType, Offset};
+        use crate::page_layout::PAGE_SIZE;
+        use crate::storage::MemoryBackend;
+        use std::path::Path;
+        use std::time::Duration;
+
+        /// appended_pages returns the pages reachable from the root which were appended from the given offset on.
+        fn appended_pages(btree: &mut BTree, offset: Offset, start: usize) -> Result<usize, Error> {
+            let appended = (offset.0 >= start) as usize;
+            match btree.read_node(&offset)?.node_type {
+                NodeType::Internal(children, _) => {
+                    let mut res = appended;
+                    for child in children {
+                        res += appended_pages(btree, child, start)?;
+                    }
+                    Ok(res)
+                }
+                NodeType::Leaf(data_offset, _) => Ok(appended + (data_offset.0 >= start) as usize),
+                NodeType::Unexpected => Err(Error::UnexpectedError),
+            }
+        }
+
+        let mut btree = BTreeBuilder::new()
+            .path(Path::new("/delete_appends_reachable_pages_only/db"))
+            .b_parameter(2)
+            .backend(MemoryBackend::new())
+            .build()?;
+        let keys: Vec<String> = (0..64)
+            .map(|idx| format!("key{:02}", (idx * 37) % 64))
+            .collect();
+        for key in &keys {
+            btree.insert(key.clone(), key.to_uppercase())?;
+        }
+        let before = btree.stats();
+        // Merges, merges split again and root flips only append the pages of the new tree.
+        for key in keys.iter().rev() {
+            let start = btree.pager.len();
+            let appended = btree.stats().bytes_appended;
+            btree.delete(Key(key.clone()))?;
+            let root = btree.root()?;
+            assert_eq!(
+                (btree.stats().bytes_appended - appended) as usize,
+                appended_pages(&mut btree, root, start)? * PAGE_SIZE
+            );
+        }
+        let after = btree.stats();
+        assert!(after.merges > before.merges && after.splits > before.splits);
+        assert!(after.root_flips > before.root_flips);
+
+        // So do many deletions in a single commit.
+        for key in &keys {
+            btree.insert_with_ttl(key.clone(), key.to_uppercase(), Duration::ZERO)?;
+        }
+        let start = btree.pager.len();
+        let appended = btree.stats().bytes_appended;
+        assert_eq!(btree.purge_expired(None, keys.len())?.0, keys.len());
+        let root = btree.root()?;
+        assert_eq!(
+            (btree.stats().bytes_appended - appended) as usize,
+            appended_pages(&mut btree, root, start)? * PAGE_SIZE
+        );
+        btree.check()
+    }
+
+    #[test]
+    fn commit_writes_each_page_once() -> Result<(), Error> {
+        use crate::btree::BTreeBuilder;
+        use crate::node_type::Key;
+        use crate::page_layout::PAGE_SIZE;
+        use crate::stats::Stats;
+        use crate::storage::MemoryBackend;
+        use std::cell::Cell;
+        use std::path::Path;
+        use std::rc::Rc;
+        use std::time::{Duration, SystemTime};
+
+        let now = Rc::new(Cell::new(SystemTime::UNIX_EPOCH));
+        let clock = now.clone();
+        let mut btree = BTreeBuilder::new()
+            .path(Path::new("/commit_writes_each_page_once/db"))
+            .b_parameter(2)
+            .backend(MemoryBackend::new())
+            .clock(move || clock.get())
+            .build()?;
+        // Besides appending its pages, a commit only overwrites the header.
+        let check = |before: &Stats, after: &Stats| {
+            let pages = after.node_pages_written + after.data_pages_written
+                - before.node_pages_written
+                - before.data_pages_written;
+            assert!(pages > 0);
+            assert_eq!(
+                after.bytes_appended - before.bytes_appended,
+                pages * PAGE_SIZE as u64
+            );
+        };
+        let keys: Vec<String> = (0..32).map(|idx| format!("key{:02}", idx)).collect();
+        for key in &keys {
+            let before = btree.stats();
+            btree.insert(key.clone(), "value".to_string())?;
+            check(&before, &btree.stats());
+        }
+        for key in &keys {
+            let before = btree.stats();
+            btree.delete(Key(key.clone()))?;
+            check(&before, &btree.stats());
+        }
+
+        // A page modified many times by a commit is copied once, the root leaf
+        // and its data page are written once however many expired pairs are purged from it.
+        let second = Duration::from_secs(1);
+        for key in &keys[..3] {
+            btree.insert_with_ttl(key.clone(), "value".to_string(), second)?;
+        }
+        now.set(now.get() + second);
+        let before = btree.stats();
+        assert_eq!(btree.purge_expired(None, 3)?.0, 3);
+        let after = btree.stats();
+        check(&before, &after);
+        assert_eq!(after.node_pages_written - before.node_pages_written, 1);
+        assert_eq!(after.data_pages_written - before.data_pages_written, 1);
+        btree.check()
+    }
+
+    #[test]
+    fn write_batch_works() -> Result<(), Error> {
+        use crate::btree::{BTreeBuilder, WriteOp};
+        use crate::node_type::Key;
+        use crate::storage::MemoryBackend;
+        use std::path::Path;
+        use std::time::Duration;
+
+        let mut btree = BTreeBuilder::new()
+            .path(Path::new("/write_batch_works/db"))
+            .b_parameter(2)
+            .index("email", |value| Some(value.to_string()))
+            .backend(MemoryBackend::new())
+            .build()?;
+        btree.insert("a".to_string(), "a@example.com".to_string())?;
+        let before = btree.stats();
+
+        let results = btree.write_batch(&[
+            WriteOp::Insert("b".to_string(), "b@example.com".to_string()),
+            WriteOp::Delete(Key("missing".to_string())),
+            WriteOp::Insert("c".to_string(), "a@example.com".to_string()),
+            WriteOp::InsertWithTtl(
+                "d".to_string(),
+                "d@example.com".to_string(),
+                Duration::from_secs(60),
+            ),
+            WriteOp::Delete(Key("a".to_string())),
+            WriteOp::Insert("e".to_string(), "a@example.com".to_string()),
+        ])?;
+        // The failed writes are left out of the commit without affecting the others.
+        assert!(results[0].is_ok());
+        assert!(matches!(results[1], Err(Error::KeyNotFound)));
+        assert!(matches!(results[2], Err(Error::KeyAlreadyExists)));
+        assert!(results[3].is_ok() && results[4].is_ok() && results[5].is_ok());
+        assert_eq!(btree.stats().wal_records - before.wal_records, 1);
+        assert_eq!(btree.search("b".to_string())?, "b@example.com");
+        assert!(matches!(
+            btree.search("c".to_string()),
+            Err(Error::KeyNotFound)
+        ));
+        assert_eq!(btree.search("d".to_string())?, "d@example.com");
+        assert!(matches!(
+            btree.search("a".to_string()),
+            Err(Error::KeyNotFound)
+        ));
+        assert_eq!(
+            btree.search_by_index("email", "a@example.com".to_string())?,
+            "a@example.com"
+        );
+        assert_eq!(btree.changes_since(1)?.count(), 4);
+        btree.check()
+    }
+
+    #[test]
+    fn write_batch_leaves_oversized_writes_out() -> Result<(), Error> {
+        use crate::btree::{BTreeBuilder, WriteOp};
+        use crate::page_layout::PAGE_SIZE;
+        use crate::storage::MemoryBackend;
+        use std::path::Path;
+
+        let mut btree = BTreeBuilder::new()
+            .path(Path::new("/write_batch_leaves_oversized_writes_out/db"))
+            .b_parameter(2)
+            .backend(MemoryBackend::new())
+            .build()?;
+        let results = btree.write_batch(&[
+            WriteOp::Insert("a".to_string(), "x".to_string()),
+            WriteOp::Insert("b".to_string(), "y".repeat(5000)),
+        ])?;
+        assert!(results[0].is_ok());
+        assert!(matches!(results[1], Err(Error::ValueOverflowError)));
+        assert_eq!(btree.search("a".to_string())?, "x");
+
+        // A value which only overflows the data page of its leaf is found once the batch is applied.
+        btree.insert("b".to_string(), "b".repeat(PAGE_SIZE * 3 / 5))?;
+        let results = btree.write_batch(&[
+            WriteOp::Insert("d".to_string(), "d".repeat(PAGE_SIZE * 3 / 5)),
+            WriteOp::Insert("e".to_string(), "e".to_string()),
+        ])?;
+        assert!(matches!(results[0], Err(Error::ValueOverflowError)));
+        assert!(results[1].is_ok());
+        assert!(matches!(
+            btree.search("d".to_string()),
+            Err(Error::KeyNotFound)
+        ));
+        assert_eq!(btree.search("e".to_string())?, "e");
+        btree.check()
+    }
+
+    #[test]
+    fn read_only_works() -> Result<(), Error> {
+        use crate::btree::BTreeBuilder;
+        use crate::node_type::Key;
+        use std::path::Path;
+
+        let path = Path::new("/tmp/read_only_works/db");
+        std::fs::create_dir_all(path.parent().unwrap())?;
+        for suffix in ["", ".wal", ".changes"] {
+            let _ = std::fs::remove_file(format!("{}{}", path.display(), suffix));
+        }
+        let builder = BTreeBuilder::new()
+            .path(path)
+            .b_parameter(2)
+            .index("upper", |value| Some(value.to_uppercase()));
+        let reader_builder = BTreeBuilder::new()
+            .path(path)
+            .b_parameter(2)
+            .read_only(true);
+
+        // A file which does not exist yet cannot be created by a reader.
+        assert!(reader_builder.build().is_err());
+        let mut btree = builder.build()?;
+        for key in ["a", "b", "c", "d"] {
+            btree.insert(key.to_string(), format!("value {}", key))?;
+        }
+        // A writer excludes other writers, readers attach alongside it and read what it committed.
+        assert!(matches!(builder.build(), Err(Error::Locked)));
+        let mut reader = reader_builder.build()?;
+        assert_eq!(reader.search("d".to_string())?, "value d");
+        drop(reader);
+        drop(btree);
+
+        let files: Vec<Vec<u8>> = ["", ".wal", ".changes"]
+            .iter()
+            .map(|suffix| std::fs::read(format!("{}{}", path.display(), suffix)))
+            .collect::<Result<_, _>>()?;
+        let mut reader = reader_builder.build()?;
+        // Readers share the file.
+        let mut other_reader = reader_builder.build()?;
+        assert_eq!(reader.search("b".to_string())?, "value b");
+        assert_eq!(other_reader.search("d".to_string())?, "value d");
+        assert_eq!(reader.history()?.len(), 6);
+        assert_eq!(reader.changes_since(0)?.count(), 4);
+        let res = reader.insert("e".to_string(), "value e".to_string());
+        assert!(matches!(res, Err(Error::ReadOnly)));
+        let res = reader.delete(Key("a".to_string()));
+        assert!(matches!(res, Err(Error::ReadOnly)));
+        let res = reader.purge_expired(None, 10);
+        assert!(matches!(res, Err(Error::ReadOnly)));
+        // The files are left untouched.
+        for (suffix, bytes) in ["", ".wal", ".changes"].iter().zip(files) {
+            assert_eq!(
+                std::fs::read(format!("{}{}", path.display(), suffix))?,
+                bytes
+            );
+        }
+        drop(reader);
+        drop(other_reader);
+
+        let mut btree = builder.build()?;
+        btree.insert("e".to_string(), "value e".to_string())?;
+        assert_eq!(
+            btree.search_by_index("upper", "VALUE E".to_string())?,
+            "value e"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn comparator_works() -> Result<(), Error> {
+        use crate::btree::BTreeBuilder;
+        use crate::comparator::{CaseInsensitive, Natural};
+        use crate::node_type::Key;
+        use crate::storage::MemoryBackend;
+        use std::path::Path;
+
+        let backend = MemoryBackend::new();
+        let path = Path::new("/comparator_works/users");
+        let mut btree = BTreeBuilder::new()
+            .path(path)
+            .b_parameter(2)
+            .backend(backend.clone())
+            .comparator(CaseInsensitive)
+            .build()?;
+        for name in [
+            "bob", "Alice", "carol", "Dave", "erin", "Frank", "grace", "Heidi",
+        ] {
+            btree.insert(name.to_string(), format!("user {}", name.to_lowercase()))?;
+        }
+        // Usernames differing only by case are the same key.
+        btree.insert("ALICE".to_string(), "user alice2".to_string())?;
+        assert_eq!(btree.search("alice".to_string())?, "user alice2");
+        assert_eq!(btree.search("FRANK".to_string())?, "user frank");
+        btree.delete(Key("BOB".to_string()))?;
+        let res = btree.search("bob".to_string());
+        assert!(matches!(res, Err(Error::KeyNotFound)));
+        let root = btree.header().root.clone();
+        let keys: Vec<String> = btree
+            .sub_tree_pairs(root)?
+            .into_iter()
+            .map(|(key, _)| key)
+            .collect();
+        assert_eq!(
+            keys,
+            vec!["ALICE", "carol", "Dave", "erin", "Frank", "grace", "Heidi"]
+        );
+        btree.check()?;
+        drop(btree);
+
+        // The file is ordered case-insensitively, it cannot be opened using another ordering.
+        let res = BTreeBuilder::new()
+            .path(path)
+            .b_parameter(2)
+            .backend(backend.clone())
+            .build();
+        assert!(matches!(res, Err(Error::ComparatorMismatch)));
+
+        let mut btree = BTreeBuilder::new()
+            .path(Path::new("/comparator_works/versions"))
+            .b_parameter(2)
+            .backend(backend)
+            .comparator(Natural)
+            .build()?;
+        for version in ["v1.10", "v1.9", "v10.0", "v1.2", "v2.0", "v1.0", "v9.1"] {
+            btree.insert(version.to_string(), format!("release {}", version))?;
+        }
+        assert_eq!(btree.search("v1.10".to_string())?, "release v1.10");
+        let root = btree.header().root.clone();
+        let keys: Vec<String> = btree
+            .sub_tree_pairs(root)?
+            .into_iter()
+            .map(|(key, _)| key)
+            .collect();
+        assert_eq!(
+            keys,
+            vec!["v1.0", "v1.2", "v1.9", "v1.10", "v2.0", "v9.1", "v10.0"]
+        );
+        btree.check()
+    }
 }
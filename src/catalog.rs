@@ -0,0 +1,338 @@
+use crate::btree::{check_key_size, BTree, WriteOp};
+use crate::error::Error;
+use crate::node_type::{Key, Offset};
+use crate::page_layout::CATALOG_ROOT_SIZE;
+use std::collections::BTreeMap;
+
+/// Catalog holds many named trees in a single file.
+/// The root of the file is the root of the catalog, a tree mapping the name of each tree to its root,
+/// so every change to a named tree is committed by committing a new catalog root.
+/// Writes to many trees are committed together by write_batch, which stores the new root of each of them at once.
+/// All of the trees share the pager and wal of the file.
+pub struct Catalog {
+    btree: BTree,
+}
+
+/// Tree is a named tree of a catalog, it borrows the catalog for as long as it is used.
+pub struct Tree<'a> {
+    catalog: &'a mut Catalog,
+    name: String,
+}
+
+/// encode_root spells out the root of a tree the way catalog entries store it, see CATALOG_ROOT_SIZE.
+fn encode_root(Offset(root): &Offset) -> String {
+    format!("{:0width$x}", root, width = CATALOG_ROOT_SIZE)
+}
+
+impl Catalog {
+    pub(crate) fn new(btree: BTree) -> Catalog {
+        Catalog { btree }
+    }
+
+    /// create_tree adds a new empty tree to the catalog.
+    pub fn create_tree(&mut self, name: &str) -> Result<(), Error> {
+        self.btree.begin()?;
+        match self.tree_root(name) {
+            Ok(_) => return Err(Error::KeyAlreadyExists),
+            Err(Error::TreeNotFound) => {}
+            Err(e) => return Err(e),
+        }
+        let root = self.btree.write_empty_tree()?;
+        self.set_tree_root(name, Some(root))
+    }
+
+    /// open_tree returns the tree stored under the given name.
+    pub fn open_tree(&mut self, name: &str) -> Result<Tree<'_>, Error> {
+        self.tree_root(name)?;
+        Ok(Tree {
+            catalog: self,
+            name: name.to_string(),
+        })
+    }
+
+    /// drop_tree removes a tree from the catalog.
+    pub fn drop_tree(&mut self, name: &str) -> Result<(), Error> {
+        self.btree.begin()?;
+        self.tree_root(name)?;
+        self.set_tree_root(name, None)
+    }
+
+    /// list_trees returns the names of the trees in the catalog in order.
+    pub fn list_trees(&mut self) -> Result<Vec<String>, Error> {
+        let catalog_root = self.btree.root()?;
+        Ok(self
+            .btree
+            .sub_tree_pairs(catalog_root)?
+            .into_iter()
+            .map(|(name, _)| name)
+            .collect())
+    }
+
+    /// write_batch applies writes to the named trees in order and commits all of them at once,
+    /// the pages of every tree written to are written along with a single new catalog root.
+    /// The result of each write is returned in order, writes failing with TreeNotFound, KeyNotFound, KeyOverflowError
+    /// or ValueOverflowError are skipped and the others are committed. An error reading or writing the file
+    /// fails the whole batch.
+    pub fn write_batch(
+        &mut self,
+        writes: &[(String, WriteOp)],
+    ) -> Result<Vec<Result<(), Error>>, Error> {
+        let now = self.btree.now();
+        // A write overflowing its pages is only found once it is applied, the batch is then applied again
+        // from the last commit leaving the failed write out.
+        let mut failed: Vec<Option<Error>> = vec![None; writes.len()];
+        'apply: loop {
+            self.btree.begin()?;
+            // The new roots of the trees written to, the catalog is only modified once every write is applied.
+            let mut roots: BTreeMap<&str, Offset> = BTreeMap::new();
+            let mut results = vec![];
+            for ((name, write), failed) in writes.iter().zip(failed.iter_mut()) {
+                if let Some(e) = failed {
+                    results.push(Err(e.clone()));
+                    continue;
+                }
+                let root = match roots.get(name.as_str()) {
+                    Some(root) => root.clone(),
+                    None => match self.tree_root(name) {
+                        Ok(root) => root,
+                        Err(Error::TreeNotFound) => {
+                            results.push(Err(Error::TreeNotFound));
+                            continue;
+                        }
+                        Err(e) => return Err(e),
+                    },
+                };
+                if let WriteOp::Insert(key, _) | WriteOp::InsertWithTtl(key, _, _) = write {
+                    if let Err(e) = check_key_size(key) {
+                        results.push(Err(e));
+                        continue;
+                    }
+                }
+                let applied = match write {
+                    WriteOp::Insert(key, value) => {
+                        self.btree
+                            .insert_into(root, key.clone(), value.clone(), None)
+                    }
+                    WriteOp::InsertWithTtl(key, value, ttl) => {
+                        let expiry = now.saturating_add(ttl.as_millis() as u64);
+                        self.btree
+                            .insert_into(root, key.clone(), value.clone(), Some(expiry))
+                    }
+                    WriteOp::Delete(key) => match self.btree.lookup_in(&root, &key.0) {
+                        Ok(_) => self.btree.delete_from(root, key.clone()),
+                        Err(Error::KeyNotFound) => {
+                            results.push(Err(Error::KeyNotFound));
+                            continue;
+                        }
+                        Err(e) => return Err(e),
+                    },
+                };
+                match applied {
+                    Ok(root) => {
+                        roots.insert(name, root);
+                        results.push(Ok(()));
+                    }
+                    Err(e @ (Error::KeyOverflowError | Error::ValueOverflowError)) => {
+                        *failed = Some(e);
+                        continue 'apply;
+                    }
+                    Err(e) => return Err(e),
+                }
+            }
+            if !roots.is_empty() {
+                let mut catalog_root = self.btree.root()?;
+                for (name, root) in roots {
+                    catalog_root = self.btree.insert_into(
+                        catalog_root,
+                        name.to_string(),
+                        encode_root(&root),
+                        None,
+                    )?;
+                }
+                let index_roots = self.btree.index_roots();
+                self.btree.commit(catalog_root, index_roots, vec![])?;
+            }
+            return Ok(results);
+        }
+    }
+
+    /// tree_root looks up the root of a tree in the last committed catalog.
+    fn tree_root(&mut self, name: &str) -> Result<Offset, Error> {
+        let catalog_root = self.btree.root()?;
+        match self.btree.search_in(&catalog_root, name) {
+            Ok(root) => self.btree.decoder().tree_root(&root),
+            Err(Error::KeyNotFound) => Err(Error::TreeNotFound),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// set_tree_root inserts or replaces the catalog entry of a tree, or removes it when root is None,
+    /// and commits the new catalog root.
+    fn set_tree_root(&mut self, name: &str, root: Option<Offset>) -> Result<(), Error> {
+        let catalog_root = self.btree.root()?;
+        let catalog_root = match root {
+            Some(root) => {
+                self.btree
+                    .insert_into(catalog_root, name.to_string(), encode_root(&root), None)?
+            }
+            None => self
+                .btree
+                .delete_from(catalog_root, Key(name.to_string()))?,
+        };
+        let index_roots = self.btree.index_roots();
+        self.btree.commit(catalog_root, index_roots, vec![])
+    }
+}
+
+impl Tree<'_> {
+    /// insert inserts a key value pair to the tree.
+    pub fn insert(&mut self, key: String, value: String) -> Result<(), Error> {
+        self.write(WriteOp::Insert(key, value))
+    }
+
+    /// search searches for a specific key in the tree.
+    pub fn search(&mut self, key: String) -> Result<String, Error> {
+        let root = self.catalog.tree_root(&self.name)?;
+        self.catalog.btree.search_in(&root, &key)
+    }
+
+    /// delete deletes a given key from the tree.
+    pub fn delete(&mut self, key: Key) -> Result<(), Error> {
+        self.write(WriteOp::Delete(key))
+    }
+
+    /// write commits a single write to the tree, see Catalog::write_batch.
+    fn write(&mut self, write: WriteOp) -> Result<(), Error> {
+        let mut results = self.catalog.write_batch(&[(self.name.clone(), write)])?;
+        results.pop().ok_or(Error::UnexpectedError)?
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::error::Error;
+
+    #[test]
+    fn catalog_works() -> Result<(), Error> {
+        use crate::btree::BTreeBuilder;
+        use crate::node_type::Key;
+        use std::path::Path;
+
+        let path = Path::new("/tmp/catalog_works/db");
+        std::fs::create_dir_all(path.parent().unwrap())?;
+        let _ = std::fs::remove_file(path);
+        let builder = BTreeBuilder::new().path(path).b_parameter(2);
+
+        let mut catalog = builder.build_catalog()?;
+        catalog.create_tree("users")?;
+        catalog.create_tree("orders")?;
+        let res = catalog.create_tree("users");
+        assert!(matches!(res, Err(Error::KeyAlreadyExists)));
+
+        let mut users = catalog.open_tree("users")?;
+        for key in ["a", "b", "c", "d", "e", "f"] {
+            users.insert(key.to_string(), format!("user {}", key))?;
+        }
+        users.delete(Key("c".to_string()))?;
+        let mut orders = catalog.open_tree("orders")?;
+        orders.insert("a".to_string(), "order a".to_string())?;
+        drop(catalog);
+
+        let mut catalog = builder.build_catalog()?;
+        assert_eq!(catalog.list_trees()?, vec!["orders", "users"]);
+        let mut users = catalog.open_tree("users")?;
+        assert_eq!(users.search("f".to_string())?, "user f");
+        assert!(matches!(
+            users.search("c".to_string()),
+            Err(Error::KeyNotFound)
+        ));
+        assert_eq!(
+            catalog.open_tree("orders")?.search("a".to_string())?,
+            "order a"
+        );
+
+        catalog.drop_tree("orders")?;
+        assert_eq!(catalog.list_trees()?, vec!["users"]);
+        assert!(matches!(
+            catalog.open_tree("orders"),
+            Err(Error::TreeNotFound)
+        ));
+        drop(catalog);
+
+        let res = builder.build();
+        assert!(matches!(res, Err(Error::CatalogMismatch)));
+        Ok(())
+    }
+
+    #[test]
+    fn catalog_write_batch_works() -> Result<(), Error> {
+        use crate::btree::{BTreeBuilder, WriteOp};
+        use crate::node_type::Key;
+        use crate::page_layout::CATALOG_ROOT_SIZE;
+        use crate::storage::MemoryBackend;
+        use std::path::Path;
+
+        let builder = BTreeBuilder::new()
+            .path(Path::new("/db"))
+            .b_parameter(2)
+            .backend(MemoryBackend::new());
+        let mut catalog = builder.build_catalog()?;
+        catalog.create_tree("users")?;
+        catalog.create_tree("orders")?;
+        catalog
+            .open_tree("orders")?
+            .insert("a".to_string(), "order a".to_string())?;
+
+        let opened = catalog.btree.stats();
+        let mut writes = vec![];
+        for key in ["a", "b", "c", "d", "e", "f"] {
+            writes.push((
+                "users".to_string(),
+                WriteOp::Insert(key.to_string(), format!("user {}", key)),
+            ));
+            writes.push((
+                "orders".to_string(),
+                WriteOp::Insert(key.to_string(), format!("order {}", key)),
+            ));
+        }
+        writes.push(("users".to_string(), WriteOp::Delete(Key("c".to_string()))));
+        writes.push(("users".to_string(), WriteOp::Delete(Key("z".to_string()))));
+        writes.push((
+            "invoices".to_string(),
+            WriteOp::Insert("a".to_string(), "invoice a".to_string()),
+        ));
+        writes.push((
+            "orders".to_string(),
+            WriteOp::Insert("g".to_string(), "g".repeat(5000)),
+        ));
+        let results = catalog.write_batch(&writes)?;
+        assert!(results[..13].iter().all(|result| result.is_ok()));
+        assert!(matches!(results[13], Err(Error::KeyNotFound)));
+        assert!(matches!(results[14], Err(Error::TreeNotFound)));
+        assert!(matches!(results[15], Err(Error::ValueOverflowError)));
+        // Both trees are committed along with a single catalog root.
+        assert_eq!(catalog.btree.stats().wal_records - opened.wal_records, 1);
+        drop(catalog);
+
+        let mut catalog = builder.build_catalog()?;
+        assert_eq!(catalog.list_trees()?, vec!["orders", "users"]);
+        let mut users = catalog.open_tree("users")?;
+        assert_eq!(users.search("f".to_string())?, "user f");
+        assert!(matches!(
+            users.search("c".to_string()),
+            Err(Error::KeyNotFound)
+        ));
+        assert_eq!(
+            catalog.open_tree("orders")?.search("f".to_string())?,
+            "order f"
+        );
+
+        // Tree roots are stored as fixed width hex offsets.
+        let catalog_root = catalog.btree.root()?;
+        for (_, root) in catalog.btree.sub_tree_pairs(catalog_root)? {
+            assert_eq!(root.len(), CATALOG_ROOT_SIZE);
+            assert!(root.bytes().all(|byte| byte.is_ascii_hexdigit()));
+        }
+        Ok(())
+    }
+}
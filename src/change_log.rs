@@ -0,0 +1,379 @@
+use crate::encryption::{initial_generation, nonce, Cipher, TAG_SIZE};
+use crate::error::Error;
+use crate::page_layout::{PAGE_GENERATION_SIZE, PTR_SIZE};
+use crate::storage::{Backend, Reader, Storage};
+use std::convert::TryFrom;
+use std::io::{BufReader, Read};
+use std::path::PathBuf;
+use std::rc::Rc;
+use std::sync::Arc;
+
+/// Each record starts with its sequence number and the length of its payload,
+/// encrypted records follow with the generation of the write and the authentication tag.
+/// The payload is made of the operation byte, the key length, the key and the value (if any).
+const RECORD_HEADER_SIZE: usize = PTR_SIZE + PTR_SIZE;
+const ENCRYPTED_RECORD_HEADER_SIZE: usize = RECORD_HEADER_SIZE + PAGE_GENERATION_SIZE + TAG_SIZE;
+/// Record nonces are derived from the sequence number with the second highest bit set,
+/// which keeps them apart from page and wal nonces.
+const RECORD_POSITION_FLAG: u64 = 1 << 62;
+
+/// ChangeOp is the operation a change recorded.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ChangeOp {
+    Insert,
+    Delete,
+}
+
+/// Subscriber is a callback called with every change once it is committed.
+pub type Subscriber = Box<dyn FnMut(&Change)>;
+
+/// Change is a committed insert or delete of a key.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Change {
+    /// The sequence number of the change, it is assigned when the change is committed.
+    pub seq: u64,
+    pub op: ChangeOp,
+    pub key: String,
+    /// The inserted value, None for deletes.
+    pub value: Option<String>,
+}
+
+impl Change {
+    pub fn new(op: ChangeOp, key: String, value: Option<String>) -> Change {
+        Change {
+            seq: 0,
+            op,
+            key,
+            value,
+        }
+    }
+
+    fn payload(&self) -> Vec<u8> {
+        let mut payload = vec![u8::from(self.op)];
+        payload.extend_from_slice(&(self.key.len() as u64).to_be_bytes());
+        payload.extend_from_slice(self.key.as_bytes());
+        if let Some(value) = &self.value {
+            payload.extend_from_slice(value.as_bytes());
+        }
+        payload
+    }
+
+    fn from_payload(seq: u64, payload: &[u8]) -> Result<Change, Error> {
+        let op = ChangeOp::try_from(*payload.first().ok_or(Error::UnexpectedError)?)?;
+        let key_len = payload.get(1..1 + PTR_SIZE).ok_or(Error::UnexpectedError)?;
+        let key_len = usize::try_from(u64::from_be_bytes(
+            <[u8; PTR_SIZE]>::try_from(key_len).map_err(|_| Error::UnexpectedError)?,
+        ))
+        .map_err(|_| Error::IntegerOverflowError)?;
+        let rest = &payload[1 + PTR_SIZE..];
+        let key = rest.get(..key_len).ok_or(Error::UnexpectedError)?;
+        let key = String::from_utf8(key.to_vec()).map_err(|_| Error::UTF8Error)?;
+        let value = match op {
+            ChangeOp::Insert => {
+                Some(String::from_utf8(rest[key_len..].to_vec()).map_err(|_| Error::UTF8Error)?)
+            }
+            ChangeOp::Delete => None,
+        };
+        Ok(Change {
+            seq,
+            op,
+            key,
+            value,
+        })
+    }
+}
+
+// Converts a byte to a ChangeOp.
+impl TryFrom<u8> for ChangeOp {
+    type Error = Error;
+
+    fn try_from(orig: u8) -> Result<Self, Self::Error> {
+        match orig {
+            0x01 => Ok(ChangeOp::Insert),
+            0x02 => Ok(ChangeOp::Delete),
+            _ => Err(Error::UnexpectedError),
+        }
+    }
+}
+
+// Converts a ChangeOp to a byte.
+impl From<ChangeOp> for u8 {
+    fn from(orig: ChangeOp) -> u8 {
+        match orig {
+            ChangeOp::Insert => 0x01,
+            ChangeOp::Delete => 0x02,
+        }
+    }
+}
+
+/// read_record reads the record at the reader's position, remaining is the number of bytes left in the log.
+/// None is returned at the end of the log, at a record which was only partially written
+/// or at a record following the given sequence number, which belongs to a commit which never completed.
+/// The length of a committed record is validated against the remaining bytes before its payload is read.
+fn read_record(
+    reader: &mut impl Read,
+    cipher: &Option<Arc<dyn Cipher>>,
+    remaining: u64,
+    last_seq: u64,
+) -> Result<Option<(Change, u64)>, Error> {
+    let header_size = match cipher {
+        Some(_) => ENCRYPTED_RECORD_HEADER_SIZE,
+        None => RECORD_HEADER_SIZE,
+    };
+    let mut header = [0x00; ENCRYPTED_RECORD_HEADER_SIZE];
+    let header = &mut header[..header_size];
+    if reader.read_exact(header).is_err() {
+        return Ok(None);
+    }
+    let mut seq = [0x00; PTR_SIZE];
+    seq.clone_from_slice(&header[..PTR_SIZE]);
+    if u64::from_be_bytes(seq) > last_seq {
+        return Ok(None);
+    }
+    let mut payload_len = [0x00; PTR_SIZE];
+    payload_len.clone_from_slice(&header[PTR_SIZE..RECORD_HEADER_SIZE]);
+    let payload_len = u64::from_be_bytes(payload_len);
+    if payload_len > remaining.saturating_sub(header_size as u64) {
+        return Err(Error::CorruptedPage);
+    }
+    let payload_len = usize::try_from(payload_len).map_err(|_| Error::IntegerOverflowError)?;
+    let mut payload = vec![0x00; payload_len];
+    if reader.read_exact(&mut payload).is_err() {
+        return Ok(None);
+    }
+    if let Some(cipher) = cipher {
+        let mut generation = [0x00; PAGE_GENERATION_SIZE];
+        generation.clone_from_slice(
+            &header[RECORD_HEADER_SIZE..RECORD_HEADER_SIZE + PAGE_GENERATION_SIZE],
+        );
+        let mut tag = [0x00; TAG_SIZE];
+        tag.clone_from_slice(&header[RECORD_HEADER_SIZE + PAGE_GENERATION_SIZE..]);
+        let position = RECORD_POSITION_FLAG | u64::from_be_bytes(seq);
+        let nonce = nonce(position, u64::from_be_bytes(generation));
+        cipher.decrypt(&nonce, &seq, &mut payload, &tag)?;
+    }
+    let change = Change::from_payload(u64::from_be_bytes(seq), &payload)?;
+    Ok(Some((change, (header_size + payload_len) as u64)))
+}
+
+/// ChangeLog is a durable log of the committed changes, it lives next to the tree file.
+/// Records are appended before the commit they belong to, the header of the tree records the sequence number
+/// of the last committed change so the records of a commit which never completed are discarded when the log is opened.
+pub struct ChangeLog {
+    storage: Box<dyn Storage>,
+    backend: Rc<dyn Backend>,
+    path: PathBuf,
+    cipher: Option<Arc<dyn Cipher>>,
+    generation: u64,
+    /// The length of the committed records.
+    len: u64,
+    /// The length of the committed records and the records appended for the pending commit.
+    pending_len: u64,
+    /// Whether the log is opened without write permission, see BTreeBuilder::read_only.
+    read_only: bool,
+}
+
+impl ChangeLog {
+    /// new opens the change log at the given path discarding records following the given sequence number,
+    /// a read-only log ignores them rather than discarding them.
+    pub fn new(
+        backend: Rc<dyn Backend>,
+        path: PathBuf,
+        cipher: Option<Arc<dyn Cipher>>,
+        last_seq: u64,
+        read_only: bool,
+    ) -> Result<ChangeLog, Error> {
+        let open = |path: &PathBuf| {
+            if read_only {
+                backend.open_read_only(path)
+            } else {
+                backend.open(path)
+            }
+        };
+        let mut storage = open(&path)?;
+        let storage_len = storage.len()?;
+        let mut reader = BufReader::new(Reader::new(open(&path)?, storage_len));
+        let mut len = 0;
+        while let Some((_, record_len)) =
+            read_record(&mut reader, &cipher, storage_len - len, last_seq)?
+        {
+            len += record_len;
+        }
+        if !read_only {
+            storage.set_len(len)?;
+        }
+
+        Ok(ChangeLog {
+            storage,
+            backend,
+            path,
+            cipher,
+            generation: initial_generation(),
+            len,
+            pending_len: len,
+            read_only,
+        })
+    }
+
+    /// append writes the given changes following the committed records, overwriting the records of a failed commit.
+    /// Returns the number of bytes written.
+    pub fn append(&mut self, changes: &[Change]) -> Result<usize, Error> {
+        let mut records = vec![];
+        for change in changes {
+            let seq = change.seq.to_be_bytes();
+            let mut payload = change.payload();
+            records.extend_from_slice(&seq);
+            records.extend_from_slice(&(payload.len() as u64).to_be_bytes());
+            if let Some(cipher) = &self.cipher {
+                self.generation += 1;
+                let position = RECORD_POSITION_FLAG | change.seq;
+                let tag = cipher.encrypt(&nonce(position, self.generation), &seq, &mut payload)?;
+                records.extend_from_slice(&self.generation.to_be_bytes());
+                records.extend_from_slice(&tag);
+            }
+            records.extend_from_slice(&payload);
+        }
+        // Drop the records of a commit which failed, a shorter write could leave part of them behind.
+        if self.pending_len != self.len {
+            self.storage.set_len(self.len)?;
+        }
+        self.storage.write_at(self.len, &records)?;
+        self.pending_len = self.len + records.len() as u64;
+        Ok(records.len())
+    }
+
+    /// sync makes the appended changes durable.
+    pub fn sync(&mut self) -> Result<(), Error> {
+        self.storage.sync()
+    }
+
+    /// commit marks the appended changes as committed.
+    pub fn commit(&mut self) {
+        self.len = self.pending_len;
+    }
+
+    /// changes_since returns the committed changes following the given sequence number.
+    pub fn changes_since(&self, seq: u64) -> Result<Changes, Error> {
+        let storage = if self.read_only {
+            self.backend.open_read_only(&self.path)?
+        } else {
+            self.backend.open(&self.path)?
+        };
+        Ok(Changes {
+            reader: BufReader::new(Reader::new(storage, self.len)),
+            cipher: self.cipher.clone(),
+            since: seq,
+            remaining: self.len,
+        })
+    }
+}
+
+/// Changes iterates over the committed changes following a sequence number in order.
+pub struct Changes {
+    reader: BufReader<Reader>,
+    cipher: Option<Arc<dyn Cipher>>,
+    since: u64,
+    /// The number of committed bytes left to read.
+    remaining: u64,
+}
+
+impl Iterator for Changes {
+    type Item = Result<Change, Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let record = read_record(&mut self.reader, &self.cipher, self.remaining, u64::MAX);
+            if let Ok(Some((_, record_len))) = &record {
+                self.remaining -= record_len;
+            }
+            match record {
+                Ok(Some((change, _))) if change.seq <= self.since => continue,
+                Ok(Some((change, _))) => return Some(Ok(change)),
+                Ok(None) => return None,
+                Err(e) => return Some(Err(e)),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::change_log::{Change, ChangeOp};
+    use crate::error::Error;
+
+    #[test]
+    fn change_feed_works() -> Result<(), Error> {
+        use crate::btree::BTreeBuilder;
+        use crate::node_type::Key;
+        use std::cell::RefCell;
+        use std::path::Path;
+        use std::rc::Rc;
+
+        let path = Path::new("/tmp/change_feed_works/db");
+        std::fs::create_dir_all(path.parent().unwrap())?;
+        let _ = std::fs::remove_file(path);
+        let _ = std::fs::remove_file("/tmp/change_feed_works/db.changes");
+        let builder = BTreeBuilder::new().path(path).b_parameter(2);
+        let insert = |seq, key: &str, value: &str| Change {
+            seq,
+            op: ChangeOp::Insert,
+            key: key.to_string(),
+            value: Some(value.to_string()),
+        };
+
+        let mut btree = builder.build()?;
+        let notified = Rc::new(RefCell::new(vec![]));
+        let subscriber = notified.clone();
+        btree.subscribe(move |change| subscriber.borrow_mut().push(change.clone()));
+        btree.insert("a".to_string(), "shalom".to_string())?;
+        btree.insert("b".to_string(), "hello".to_string())?;
+        btree.delete(Key("a".to_string()))?;
+        let res = btree.delete(Key("c".to_string()));
+        assert!(matches!(res, Err(Error::KeyNotFound)));
+
+        let expected = vec![
+            insert(1, "a", "shalom"),
+            insert(2, "b", "hello"),
+            Change {
+                seq: 3,
+                op: ChangeOp::Delete,
+                key: "a".to_string(),
+                value: None,
+            },
+        ];
+        assert_eq!(*notified.borrow(), expected);
+        drop(btree);
+
+        let mut btree = builder.build()?;
+        let changes = btree.changes_since(1)?.collect::<Result<Vec<_>, Error>>()?;
+        assert_eq!(changes, expected[1..]);
+        btree.insert("c".to_string(), "marhaba".to_string())?;
+        let changes = btree.changes_since(3)?.collect::<Result<Vec<_>, Error>>()?;
+        assert_eq!(changes, vec![insert(4, "c", "marhaba")]);
+        Ok(())
+    }
+
+    #[test]
+    fn corrupted_record_length_is_rejected() -> Result<(), Error> {
+        use crate::btree::BTreeBuilder;
+        use crate::page_layout::PTR_SIZE;
+        use crate::storage::{Backend, MemoryBackend};
+        use std::path::Path;
+
+        let backend = MemoryBackend::new();
+        let builder = BTreeBuilder::new()
+            .path(Path::new("/corrupted_record_length_is_rejected/db"))
+            .b_parameter(2)
+            .backend(backend.clone());
+        let mut btree = builder.build()?;
+        btree.insert("a".to_string(), "shalom".to_string())?;
+        drop(btree);
+
+        // The length of the first record follows its sequence number.
+        let mut storage =
+            backend.open(Path::new("/corrupted_record_length_is_rejected/db.changes"))?;
+        storage.write_at(PTR_SIZE as u64, &(u64::MAX >> 1).to_be_bytes())?;
+        assert!(matches!(builder.build(), Err(Error::CorruptedPage)));
+        Ok(())
+    }
+}
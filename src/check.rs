@@ -0,0 +1,262 @@
+use crate::btree::BTree;
+use crate::error::Error;
+use crate::node_type::{NodeType, Offset};
+use std::cmp::Ordering;
+use std::collections::HashSet;
+
+/// violation returns the error reporting the invariant the node at the given offset violates.
+fn violation(offset: &Offset, invariant: &str) -> Error {
+    Error::InvariantViolation(format!("node at offset {}: {}", offset.0, invariant))
+}
+
+impl BTree {
+    /// check validates the invariants of the tree and of its indexes, the first invariant violated is returned:
+    /// - keys are sorted, by the comparator of the tree, and within the bounds set by the separators of the parent,
+    /// - nodes other than the root hold between b-1 and 2b-1 keys,
+    /// - leaves are all at the same depth,
+    /// - leaves refer to distinct values of their data page.
+    pub fn check(&mut self) -> Result<(), Error> {
+        let header = self.header().clone();
+        let roots =
+            std::iter::once(header.root).chain(header.indexes.into_iter().map(|(_, root)| root));
+        for root in roots {
+            self.check_sub_tree(&root, None, None, true)?;
+        }
+        Ok(())
+    }
+
+    /// check_sub_tree checks the nodes rooted at the given offset, their keys are bounded by lower (exclusive)
+    /// and upper (inclusive) as searches descend to the left of a separator equal to the key.
+    /// Returns the depth of the leaves.
+    fn check_sub_tree(
+        &mut self,
+        offset: &Offset,
+        lower: Option<&str>,
+        upper: Option<&str>,
+        is_root: bool,
+    ) -> Result<usize, Error> {
+        let node = self.read_node(offset)?;
+        if node.is_root != is_root {
+            return Err(violation(offset, "root flag mismatch"));
+        }
+        let b = self.header().b;
+        let keys: Vec<&str> = match &node.node_type {
+            NodeType::Internal(_, keys) => keys.iter().map(|key| key.0.as_str()).collect(),
+            NodeType::Leaf(_, pairs) => pairs.iter().map(|pair| pair.key.as_str()).collect(),
+            NodeType::Unexpected => return Err(violation(offset, "unexpected node type")),
+        };
+        if keys.len() > 2 * b - 1 || (!is_root && keys.len() < b - 1) {
+            return Err(violation(offset, &format!("holds {} keys", keys.len())));
+        }
+        let comparator = self.comparator();
+        let less = |a: &str, b: &str| comparator.compare(a, b) == Ordering::Less;
+        if keys.windows(2).any(|pair| !less(pair[0], pair[1])) {
+            return Err(violation(offset, "keys are not sorted"));
+        }
+        let out_of_bounds = |key: &&str| {
+            lower.is_some_and(|lower| !less(lower, key))
+                || upper.is_some_and(|upper| less(upper, key))
+        };
+        if keys.iter().any(out_of_bounds) {
+            return Err(violation(offset, "key out of bounds"));
+        }
+
+        match &node.node_type {
+            NodeType::Internal(children, _) => {
+                if children.len() != keys.len() + 1 || (is_root && keys.is_empty()) {
+                    return Err(violation(
+                        offset,
+                        &format!("has {} children", children.len()),
+                    ));
+                }
+                let mut depth = None;
+                for (idx, child) in children.iter().enumerate() {
+                    let child_lower = if idx == 0 { lower } else { Some(keys[idx - 1]) };
+                    let child_upper = keys.get(idx).copied().or(upper);
+                    let child_depth =
+                        self.check_sub_tree(child, child_lower, child_upper, false)?;
+                    if depth.is_some_and(|depth| depth != child_depth) {
+                        return Err(violation(offset, "leaves at different depths"));
+                    }
+                    depth = Some(child_depth);
+                }
+                Ok(depth.unwrap_or(0) + 1)
+            }
+            NodeType::Leaf(data_offset, pairs) => {
+                let data_page = self.read_data_page(data_offset)?;
+                let mut seen = HashSet::new();
+                for pair in pairs {
+                    if data_page.get(pair.idx).is_none() || !seen.insert(pair.idx) {
+                        return Err(violation(
+                            offset,
+                            &format!("bad value index of {}", pair.key),
+                        ));
+                    }
+                }
+                Ok(0)
+            }
+            NodeType::Unexpected => Err(violation(offset, "unexpected node type")),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::btree::BTreeBuilder;
+    use crate::error::Error;
+    use crate::node_type::Key;
+    use crate::storage::MemoryBackend;
+    use std::collections::BTreeMap;
+    use std::path::Path;
+
+    #[derive(Clone, Debug)]
+    enum Op {
+        Insert(String, String),
+        Delete(String),
+        Search(String),
+        Scan,
+    }
+
+    /// Rng is a splitmix64 generator, seeded so failures are reproducible.
+    struct Rng(u64);
+
+    impl Rng {
+        fn next(&mut self) -> u64 {
+            self.0 = self.0.wrapping_add(0x9e3779b97f4a7c15);
+            let mut z = self.0;
+            z = (z ^ (z >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+            z = (z ^ (z >> 27)).wrapping_mul(0x94d049bb133111eb);
+            z ^ (z >> 31)
+        }
+
+        fn below(&mut self, n: usize) -> usize {
+            (self.next() % n as u64) as usize
+        }
+
+        /// key returns a short key over a small alphabet so keys collide and share prefixes.
+        fn key(&mut self) -> String {
+            let len = 1 + self.below(3);
+            (0..len)
+                .map(|_| char::from(b'a' + self.below(5) as u8))
+                .collect()
+        }
+    }
+
+    fn generate(seed: u64, len: usize) -> Vec<Op> {
+        let mut rng = Rng(seed);
+        (0..len)
+            .map(|_| match rng.below(10) {
+                0..=4 => {
+                    let key = rng.key();
+                    let value = format!("{}{}", key, rng.below(100));
+                    Op::Insert(key, value)
+                }
+                5..=7 => Op::Delete(rng.key()),
+                8 => Op::Search(rng.key()),
+                _ => Op::Scan,
+            })
+            .collect()
+    }
+
+    /// run runs the operations against both the tree and the model comparing their results
+    /// and checking the invariants of the tree after every step, the first discrepancy is returned.
+    fn run(b: usize, ops: &[Op]) -> Result<(), String> {
+        let mut btree = BTreeBuilder::new()
+            .path(Path::new("/db"))
+            .b_parameter(b)
+            .backend(MemoryBackend::new())
+            .build()
+            .map_err(|e| format!("build: {:?}", e))?;
+        let mut model = BTreeMap::new();
+        for (step, op) in ops.iter().enumerate() {
+            let (res, expected) = match op {
+                Op::Insert(key, value) => {
+                    model.insert(key.clone(), value.clone());
+                    (
+                        btree.insert(key.clone(), value.clone()).map(|_| None),
+                        Ok(None),
+                    )
+                }
+                Op::Delete(key) => {
+                    let expected = model.remove(key).map(|_| None).ok_or(());
+                    (btree.delete(Key(key.clone())).map(|_| None), expected)
+                }
+                Op::Search(key) => {
+                    let expected = model.get(key).cloned().map(Some).ok_or(());
+                    (btree.search(key.clone()).map(Some), expected)
+                }
+                Op::Scan => {
+                    let expected = model
+                        .iter()
+                        .map(|(key, value)| format!("{}={}", key, value))
+                        .collect::<Vec<_>>()
+                        .join(",");
+                    let res = btree.root().and_then(|root| btree.sub_tree_pairs(root));
+                    let res = res.map(|pairs| {
+                        let pairs: Vec<String> = pairs
+                            .into_iter()
+                            .map(|(key, value)| format!("{}={}", key, value))
+                            .collect();
+                        Some(pairs.join(","))
+                    });
+                    (res, Ok(Some(expected)))
+                }
+            };
+            let res = match res {
+                Ok(res) => Ok(res),
+                Err(Error::KeyNotFound) => Err(()),
+                Err(e) => return Err(format!("step {} {:?}: {:?}", step, op, e)),
+            };
+            if res != expected {
+                return Err(format!(
+                    "step {} {:?}: got {:?} expected {:?}",
+                    step, op, res, expected
+                ));
+            }
+            btree
+                .check()
+                .map_err(|e| format!("step {} {:?}: {:?}", step, op, e))?;
+        }
+        Ok(())
+    }
+
+    /// shrink removes operations from a failing sequence for as long as it keeps failing,
+    /// first in large chunks and then one at a time.
+    fn shrink(b: usize, mut ops: Vec<Op>) -> Vec<Op> {
+        let mut chunk = ops.len() / 2;
+        while chunk > 0 {
+            let mut start = 0;
+            while start < ops.len() {
+                let end = std::cmp::min(start + chunk, ops.len());
+                let candidate: Vec<Op> = ops[..start].iter().chain(&ops[end..]).cloned().collect();
+                if run(b, &candidate).is_err() {
+                    ops = candidate;
+                } else {
+                    start += chunk;
+                }
+            }
+            chunk /= 2;
+        }
+        ops
+    }
+
+    #[test]
+    fn model_based_test() {
+        for b in [2, 3, 4, 5, 8, 16] {
+            for seed in 0..4 {
+                let ops = generate(seed * 1000 + b as u64, 300);
+                if let Err(e) = run(b, &ops) {
+                    let ops = shrink(b, ops);
+                    panic!(
+                        "b {} seed {} failed: {}\nminimal reproduction: {:?}\n{:?}",
+                        b,
+                        seed,
+                        e,
+                        ops,
+                        run(b, &ops)
+                    );
+                }
+            }
+        }
+    }
+}
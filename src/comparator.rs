@@ -0,0 +1,138 @@
+use std::cmp::Ordering;
+
+/// The name of the byte-wise ordering, files written before comparators were introduced are ordered by it.
+pub const BYTEWISE: &str = "bytewise";
+
+/// Comparator orders the keys of a tree, every search, insertion and deletion descends the tree using it.
+/// The name of the comparator is recorded in the file when the tree is created, a file has to be opened
+/// with a comparator of the same name since the pages are sorted by it, see BTreeBuilder::comparator.
+/// Keys comparing equal are the same key, inserting one replaces the other.
+pub trait Comparator {
+    /// name identifies the ordering, it is at most KEY_SIZE bytes long.
+    fn name(&self) -> &str;
+
+    fn compare(&self, a: &str, b: &str) -> Ordering;
+
+    /// is_bytewise returns true if the ordering is the byte-wise ordering of the keys,
+    /// which allows nodes to be searched without decoding their keys.
+    fn is_bytewise(&self) -> bool {
+        false
+    }
+}
+
+/// Bytewise orders keys by their UTF-8 bytes, it is the default ordering.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Bytewise;
+
+impl Comparator for Bytewise {
+    fn name(&self) -> &str {
+        BYTEWISE
+    }
+
+    fn compare(&self, a: &str, b: &str) -> Ordering {
+        a.cmp(b)
+    }
+
+    fn is_bytewise(&self) -> bool {
+        true
+    }
+}
+
+/// CaseInsensitive orders keys by their lowercase form, for example usernames,
+/// keys differing only by case are the same key.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct CaseInsensitive;
+
+impl Comparator for CaseInsensitive {
+    fn name(&self) -> &str {
+        "case_insensitive"
+    }
+
+    fn compare(&self, a: &str, b: &str) -> Ordering {
+        a.chars()
+            .flat_map(char::to_lowercase)
+            .cmp(b.chars().flat_map(char::to_lowercase))
+    }
+}
+
+/// Natural orders runs of ASCII digits by their numeric value and the rest of the keys byte-wise,
+/// for example version strings so that "v2" precedes "v10".
+/// Numbers differing only by leading zeros are ordered byte-wise, so "v01" and "v1" remain distinct keys.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Natural;
+
+/// chunks splits a key into alternating runs of ASCII digits and of other characters.
+fn chunks(key: &str) -> impl Iterator<Item = &str> {
+    let mut rest = key;
+    std::iter::from_fn(move || {
+        let digits = rest.chars().next()?.is_ascii_digit();
+        let end = rest
+            .find(|c: char| c.is_ascii_digit() != digits)
+            .unwrap_or(rest.len());
+        let (chunk, tail) = rest.split_at(end);
+        rest = tail;
+        Some(chunk)
+    })
+}
+
+/// cmp_numbers compares two runs of digits by their value without parsing them, so they may be of any length.
+fn cmp_numbers(a: &str, b: &str) -> Ordering {
+    let a = a.trim_start_matches('0');
+    let b = b.trim_start_matches('0');
+    a.len().cmp(&b.len()).then_with(|| a.cmp(b))
+}
+
+impl Comparator for Natural {
+    fn name(&self) -> &str {
+        "natural"
+    }
+
+    fn compare(&self, a: &str, b: &str) -> Ordering {
+        let mut a_chunks = chunks(a);
+        let mut b_chunks = chunks(b);
+        loop {
+            let order = match (a_chunks.next(), b_chunks.next()) {
+                (Some(a_chunk), Some(b_chunk)) => {
+                    let numbers = a_chunk.starts_with(|c: char| c.is_ascii_digit())
+                        && b_chunk.starts_with(|c: char| c.is_ascii_digit());
+                    if numbers {
+                        cmp_numbers(a_chunk, b_chunk)
+                    } else {
+                        a_chunk.cmp(b_chunk)
+                    }
+                }
+                (Some(_), None) => Ordering::Greater,
+                (None, Some(_)) => Ordering::Less,
+                (None, None) => return a.cmp(b),
+            };
+            if order != Ordering::Equal {
+                return order;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::comparator::{CaseInsensitive, Comparator, Natural};
+    use std::cmp::Ordering;
+
+    #[test]
+    fn comparators_work() {
+        let mut usernames = vec!["bob", "Alice", "carol", "alice2", "Bobby"];
+        usernames.sort_by(|a, b| CaseInsensitive.compare(a, b));
+        assert_eq!(usernames, vec!["Alice", "alice2", "bob", "Bobby", "carol"]);
+        assert_eq!(CaseInsensitive.compare("ALICE", "alice"), Ordering::Equal);
+
+        let mut versions = vec!["v10.0", "v2.10", "v2.9", "v1", "v01", "v2", "w"];
+        versions.sort_by(|a, b| Natural.compare(a, b));
+        assert_eq!(
+            versions,
+            vec!["v01", "v1", "v2", "v2.9", "v2.10", "v10.0", "w"]
+        );
+        assert_eq!(
+            Natural.compare("18446744073709551616", "9"),
+            Ordering::Greater
+        );
+    }
+}
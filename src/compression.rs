@@ -0,0 +1,181 @@
+use crate::error::Error;
+use std::convert::TryFrom;
+
+/// Compression is the codec used to compress the values of a data page.
+/// The codec is recorded in every data page so pages written using different codecs
+/// can live side by side in the same file.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Default)]
+pub enum Compression {
+    /// Values are stored as is.
+    #[default]
+    None,
+    /// Values are compressed using a byte oriented LZ77 codec, see compress_lz.
+    Lz,
+}
+
+impl Compression {
+    /// compress compresses a single value.
+    pub fn compress(&self, value: &[u8]) -> Vec<u8> {
+        match self {
+            Compression::None => value.to_vec(),
+            Compression::Lz => compress_lz(value),
+        }
+    }
+
+    /// decompress reverses compress, malformed input results in an error.
+    pub fn decompress(&self, value: &[u8]) -> Result<Vec<u8>, Error> {
+        match self {
+            Compression::None => Ok(value.to_vec()),
+            Compression::Lz => decompress_lz(value),
+        }
+    }
+}
+
+// Converts a byte to a Compression.
+impl TryFrom<u8> for Compression {
+    type Error = Error;
+
+    fn try_from(orig: u8) -> Result<Self, Self::Error> {
+        match orig {
+            0x00 => Ok(Compression::None),
+            0x01 => Ok(Compression::Lz),
+            _ => Err(Error::UnsupportedCompression(orig)),
+        }
+    }
+}
+
+// Converts a Compression to a byte.
+impl From<Compression> for u8 {
+    fn from(orig: Compression) -> u8 {
+        match orig {
+            Compression::None => 0x00,
+            Compression::Lz => 0x01,
+        }
+    }
+}
+
+/// The LZ stream is a sequence of tokens, a token byte with its high bit unset is followed by
+/// (token + 1) literal bytes, a token byte with its high bit set is followed by a two bytes BigEndian
+/// distance and repeats ((token & 0x7F) + MIN_MATCH) bytes starting distance bytes back.
+const MATCH_FLAG: u8 = 0x80;
+const MIN_MATCH: usize = 4;
+const MAX_MATCH: usize = 0x7F + MIN_MATCH;
+const MAX_LITERALS: usize = 0x80;
+const MAX_DISTANCE: usize = u16::MAX as usize;
+const HASH_BITS: u32 = 12;
+
+fn hash(bytes: &[u8]) -> usize {
+    let word = u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]);
+    (word.wrapping_mul(2_654_435_761) >> (32 - HASH_BITS)) as usize
+}
+
+fn push_literals(out: &mut Vec<u8>, literals: &[u8]) {
+    for chunk in literals.chunks(MAX_LITERALS) {
+        out.push((chunk.len() - 1) as u8);
+        out.extend_from_slice(chunk);
+    }
+}
+
+/// compress_lz greedily replaces repeated sequences of at least MIN_MATCH bytes
+/// with references to their previous occurrence.
+fn compress_lz(input: &[u8]) -> Vec<u8> {
+    let mut out = Vec::with_capacity(input.len());
+    let mut table = vec![usize::MAX; 1 << HASH_BITS];
+    let mut literal_start = 0;
+    let mut pos = 0;
+    while pos + MIN_MATCH <= input.len() {
+        let slot = hash(&input[pos..]);
+        let candidate = table[slot];
+        table[slot] = pos;
+        if candidate != usize::MAX
+            && pos - candidate <= MAX_DISTANCE
+            && input[candidate..candidate + MIN_MATCH] == input[pos..pos + MIN_MATCH]
+        {
+            let len = input[pos..]
+                .iter()
+                .zip(&input[candidate..])
+                .take(MAX_MATCH)
+                .take_while(|(a, b)| a == b)
+                .count();
+            push_literals(&mut out, &input[literal_start..pos]);
+            out.push(MATCH_FLAG | (len - MIN_MATCH) as u8);
+            out.extend_from_slice(&((pos - candidate) as u16).to_be_bytes());
+            pos += len;
+            literal_start = pos;
+        } else {
+            pos += 1;
+        }
+    }
+    push_literals(&mut out, &input[literal_start..]);
+    out
+}
+
+fn decompress_lz(input: &[u8]) -> Result<Vec<u8>, Error> {
+    let mut out = Vec::with_capacity(input.len() * 2);
+    let mut pos = 0;
+    while pos < input.len() {
+        let token = input[pos];
+        pos += 1;
+        if token & MATCH_FLAG == 0 {
+            let len = token as usize + 1;
+            let literals = input.get(pos..pos + len).ok_or(Error::CorruptedValue)?;
+            out.extend_from_slice(literals);
+            pos += len;
+        } else {
+            let len = (token & !MATCH_FLAG) as usize + MIN_MATCH;
+            let distance = match input.get(pos..pos + 2) {
+                Some(bytes) => u16::from_be_bytes([bytes[0], bytes[1]]) as usize,
+                None => return Err(Error::CorruptedValue),
+            };
+            pos += 2;
+            if distance == 0 || distance > out.len() {
+                return Err(Error::CorruptedValue);
+            }
+            // Copy byte by byte as the match may overlap the bytes it produces.
+            let start = out.len() - distance;
+            for idx in start..start + len {
+                out.push(out[idx]);
+            }
+        }
+    }
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::compression::Compression;
+    use crate::error::Error;
+
+    #[test]
+    fn lz_round_trip_works() -> Result<(), Error> {
+        let values: [&[u8]; 5] = [
+            b"",
+            b"a",
+            b"abcabcabcabcabcabcabcabcabcabc",
+            b"no repeated sequences here",
+            br#"[{"id":1,"name":"foo"},{"id":2,"name":"bar"},{"id":3,"name":"baz"}]"#,
+        ];
+        for value in values.iter() {
+            let compressed = Compression::Lz.compress(value);
+            assert_eq!(Compression::Lz.decompress(&compressed)?, value.to_vec());
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn lz_compresses_repetitive_values() {
+        let value = r#"{"status":"active","status":"active","status":"active"}"#.repeat(8);
+        let compressed = Compression::Lz.compress(value.as_bytes());
+        assert!(compressed.len() < value.len() / 4);
+    }
+
+    #[test]
+    fn lz_rejects_malformed_input() {
+        // A match referring to bytes before the start of the value.
+        let res = Compression::Lz.decompress(&[0x00, b'a', 0x80, 0x00, 0x02]);
+        assert!(matches!(res, Err(Error::CorruptedValue)));
+        // A literal run longer than the input.
+        let res = Compression::Lz.decompress(&[0x05, b'a']);
+        assert!(matches!(res, Err(Error::CorruptedValue)));
+    }
+}
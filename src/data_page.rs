@@ -1,10 +1,78 @@
 use std::convert::TryFrom;
 
-use crate::{error::Error, page::Page};
+use crate::{
+    compression::Compression,
+    error::Error,
+    node_type::KeyValuePair,
+    page::Page,
+    page_layout::{
+        COMPRESSION_FORMAT_VERSION, DATA_PAGE_COMPRESSION_OFFSET, DATA_PAGE_FREE_END_OFFSET,
+        DATA_PAGE_HEADER_SIZE, DATA_PAGE_NUM_SLOTS_OFFSET, DATA_PAGE_NUM_VALUES_OFFSET,
+        DATA_PAGE_SLOTTED_COMPRESSION_OFFSET, DATA_PAGE_SLOTTED_HEADER_SIZE,
+        DATA_PAGE_SLOT_FIELD_SIZE, DATA_PAGE_SLOT_SIZE, FORMAT_VERSION, PAGE_SIZE,
+        SLOTTED_DATA_PAGE_FORMAT_VERSION,
+    },
+};
 
+/// DataPage holds the values of a leaf, each value occupies a slot whose id is the idx of the pair referring to it.
+/// Slot ids are stable, removing a value leaves a tombstone behind rather than shifting the values following it.
+/// The space taken by the slots and the compressed values is tracked so a value which does not fit is refused
+/// when it is inserted rather than when the page is written.
 #[derive(Clone, Debug, Default)]
 pub struct DataPage {
-    pub values: Vec<String>,
+    /// The values by slot id, None marks a tombstone.
+    pub slots: Vec<Option<String>>,
+    /// The codec used to compress the values once the page is written.
+    compression: Compression,
+    /// The bytes taken by the slot directory and the compressed values once the page is written.
+    used: usize,
+}
+
+/// The bytes of a slotted data page available to the slot directory and the values.
+const DATA_PAGE_CAPACITY: usize = PAGE_SIZE - DATA_PAGE_SLOTTED_HEADER_SIZE;
+
+/// read_u16 reads a BigEndian u16 field of a slotted data page.
+fn read_u16(page: &Page, offset: usize) -> Result<usize, Error> {
+    let raw = page.get_ptr_from_offset(offset, DATA_PAGE_SLOT_FIELD_SIZE)?;
+    Ok(u16::from_be_bytes([raw[0], raw[1]]) as usize)
+}
+
+/// Slot is the location of a value within a slotted data page, None for a tombstone.
+type Slot = Option<(usize, usize)>;
+
+/// read_slot reads a slot of a slotted data page validating it points at the values area.
+fn read_slot(page: &Page, free_end: usize, idx: usize) -> Result<Slot, Error> {
+    let offset = DATA_PAGE_SLOTTED_HEADER_SIZE + idx * DATA_PAGE_SLOT_SIZE;
+    let value_offset = read_u16(page, offset)?;
+    let len = read_u16(page, offset + DATA_PAGE_SLOT_FIELD_SIZE)?;
+    if value_offset == 0 {
+        return Ok(None);
+    }
+    if value_offset < free_end || value_offset + len > PAGE_SIZE {
+        return Err(Error::CorruptedPage);
+    }
+    Ok(Some((value_offset, len)))
+}
+
+/// read_slotted_header reads the codec, the number of slots and the free space end of a slotted data page.
+fn read_slotted_header(page: &Page) -> Result<(Compression, usize, usize), Error> {
+    let compression = Compression::try_from(
+        page.get_ptr_from_offset(DATA_PAGE_SLOTTED_COMPRESSION_OFFSET, 1)?[0],
+    )?;
+    let num_slots = read_u16(page, DATA_PAGE_NUM_SLOTS_OFFSET)?;
+    let free_end = read_u16(page, DATA_PAGE_FREE_END_OFFSET)?;
+    if DATA_PAGE_SLOTTED_HEADER_SIZE + num_slots * DATA_PAGE_SLOT_SIZE > free_end
+        || free_end > PAGE_SIZE
+    {
+        return Err(Error::CorruptedPage);
+    }
+    Ok((compression, num_slots, free_end))
+}
+
+/// decode_value decompresses a value read from a data page.
+fn decode_value(compression: Compression, raw: &[u8]) -> Result<String, Error> {
+    let raw_value = compression.decompress(raw)?;
+    String::from_utf8(raw_value).map_err(|_| Error::UnexpectedError)
 }
 
 impl DataPage {
@@ -13,44 +81,182 @@ impl DataPage {
     }
 
     pub fn get(&self, idx: usize) -> Option<String> {
-        self.values.get(idx).cloned()
+        self.slots.get(idx).cloned().flatten()
     }
 
-    pub fn insert(&mut self, value: String) -> usize {
-        self.values.push(value);
-        self.values.len() - 1
+    /// compression returns the codec used to compress the values once the page is written.
+    pub fn compression(&self) -> Compression {
+        self.compression
     }
 
-    pub fn split(&mut self, b: usize) -> (Self, Self) {
-        let sibling_values = self.values.split_off(b);
-        (
-            Self {
-                values: self.values.clone(),
-            },
-            Self {
-                values: sibling_values,
-            },
-        )
+    /// set_compression changes the codec compressing the values, the page keeps its codec if its values
+    /// would no longer fit into it. Every page records its codec so pages using different codecs can be read alike.
+    pub fn set_compression(&mut self, compression: Compression) {
+        if compression == self.compression {
+            return;
+        }
+        let used = self.slots.len() * DATA_PAGE_SLOT_SIZE
+            + self
+                .slots
+                .iter()
+                .flatten()
+                .map(|value| compression.compress(value.as_bytes()).len())
+                .sum::<usize>();
+        if used <= DATA_PAGE_CAPACITY {
+            self.compression = compression;
+            self.used = used;
+        }
     }
-}
 
-impl TryFrom<Page> for DataPage {
-    type Error = Error;
+    /// free_space returns the bytes left for new slots and values.
+    pub fn free_space(&self) -> usize {
+        DATA_PAGE_CAPACITY.saturating_sub(self.used)
+    }
 
-    fn try_from(page: Page) -> Result<Self, Self::Error> {
+    /// fits returns whether the value fits into the page in a new slot.
+    pub fn fits(&self, value: &str) -> bool {
+        self.value_size(value) + DATA_PAGE_SLOT_SIZE <= self.free_space()
+    }
+
+    /// value_size returns the bytes a value takes once compressed.
+    fn value_size(&self, value: &str) -> usize {
+        self.compression.compress(value.as_bytes()).len()
+    }
+
+    /// insert stores the value in the first tombstone, or a new slot if there is none, returning the slot id.
+    /// Fails with ValueOverflowError, leaving the page as it was, if the value does not fit into the page.
+    pub fn insert(&mut self, value: String) -> Result<usize, Error> {
+        let tombstone = self.slots.iter().position(|slot| slot.is_none());
+        let size = self.value_size(&value)
+            + match tombstone {
+                Some(_) => 0,
+                None => DATA_PAGE_SLOT_SIZE,
+            };
+        if size > self.free_space() {
+            return Err(Error::ValueOverflowError);
+        }
+        self.used += size;
+        match tombstone {
+            Some(idx) => {
+                self.slots[idx] = Some(value);
+                Ok(idx)
+            }
+            None => {
+                self.slots.push(Some(value));
+                Ok(self.slots.len() - 1)
+            }
+        }
+    }
+
+    /// remove replaces the value of the given slot by a tombstone, the ids of the other slots are unaffected.
+    pub fn remove(&mut self, idx: usize) -> Option<String> {
+        let value = self.slots.get_mut(idx)?.take();
+        if let Some(value) = &value {
+            self.used = self.used.saturating_sub(self.value_size(value));
+        }
+        // Trailing tombstones are dropped, no pair can refer to them.
+        while matches!(self.slots.last(), Some(None)) {
+            self.slots.pop();
+            self.used = self.used.saturating_sub(DATA_PAGE_SLOT_SIZE);
+        }
+        value
+    }
+
+    /// extract copies the values referred to by the given pairs to a new data page
+    /// and points the pairs at their values in the new page.
+    pub fn extract(&self, pairs: &mut [KeyValuePair]) -> Result<Self, Error> {
+        let mut data_page = Self {
+            compression: self.compression,
+            ..Self::default()
+        };
+        for pair in pairs.iter_mut() {
+            let value = self.get(pair.idx).ok_or(Error::UnexpectedError)?;
+            pair.idx = data_page.insert(value)?;
+        }
+        Ok(data_page)
+    }
+
+    /// read_value reads the value of a single slot of a page written using the given format version.
+    /// The value is read directly from a slotted page, older pages are deserialized in full.
+    pub fn read_value(page: &Page, version: usize, idx: usize) -> Result<String, Error> {
+        if version < SLOTTED_DATA_PAGE_FORMAT_VERSION {
+            let data_page = Self::decode(Page::new(page.get_data()), version)?;
+            return data_page.get(idx).ok_or(Error::UnexpectedError);
+        }
+        let (compression, num_slots, free_end) = read_slotted_header(page)?;
+        if idx >= num_slots {
+            return Err(Error::UnexpectedError);
+        }
+        let (offset, len) = read_slot(page, free_end, idx)?.ok_or(Error::UnexpectedError)?;
+        decode_value(compression, page.get_ptr_from_offset(offset, len)?)
+    }
+
+    /// decode deserializes a page written using the given format version.
+    pub fn decode(page: Page, version: usize) -> Result<Self, Error> {
+        if version >= SLOTTED_DATA_PAGE_FORMAT_VERSION {
+            return Self::decode_slotted(&page);
+        }
         let raw = page.get_data();
-        let mut values = vec![];
-        let num_values = raw[0];
-        let mut offset = 1;
+        let mut slots = vec![];
+        let num_values = raw[DATA_PAGE_NUM_VALUES_OFFSET];
+        // Values were stored uncompressed up until format version 3.
+        let (compression, mut offset) = if version < COMPRESSION_FORMAT_VERSION {
+            (Compression::None, DATA_PAGE_COMPRESSION_OFFSET)
+        } else {
+            (
+                Compression::try_from(raw[DATA_PAGE_COMPRESSION_OFFSET])?,
+                DATA_PAGE_HEADER_SIZE,
+            )
+        };
+        let mut used = 0;
         for _ in 0..num_values {
-            let len_value = raw[offset] as usize;
+            let len_value = *raw.get(offset).ok_or(Error::CorruptedPage)? as usize;
             offset += 1;
-            let raw_value = &raw[offset..offset + len_value];
-            let value = std::str::from_utf8(raw_value).map_err(|_| Error::UnexpectedError)?;
-            values.push(value.to_string());
+            let raw_value = raw
+                .get(offset..offset + len_value)
+                .ok_or(Error::CorruptedPage)?;
+            slots.push(Some(decode_value(compression, raw_value)?));
             offset += len_value;
+            used += DATA_PAGE_SLOT_SIZE + len_value;
+        }
+
+        Ok(Self {
+            slots,
+            compression,
+            used,
+        })
+    }
+
+    /// decode_slotted deserializes a slotted page.
+    fn decode_slotted(page: &Page) -> Result<Self, Error> {
+        let (compression, num_slots, free_end) = read_slotted_header(page)?;
+        let mut slots = Vec::with_capacity(num_slots);
+        let mut used = num_slots * DATA_PAGE_SLOT_SIZE;
+        for idx in 0..num_slots {
+            let slot = match read_slot(page, free_end, idx)? {
+                Some((offset, len)) => {
+                    used += len;
+                    Some(decode_value(
+                        compression,
+                        page.get_ptr_from_offset(offset, len)?,
+                    )?)
+                }
+                None => None,
+            };
+            slots.push(slot);
         }
+        Ok(Self {
+            slots,
+            compression,
+            used,
+        })
+    }
+}
+
+impl TryFrom<Page> for DataPage {
+    type Error = Error;
 
-        Ok(Self { values })
+    fn try_from(page: Page) -> Result<Self, Self::Error> {
+        DataPage::decode(page, FORMAT_VERSION)
     }
 }
@@ -0,0 +1,63 @@
+use crate::data_page::DataPage;
+use crate::error::Error;
+use crate::node::Node;
+use crate::node_type::Offset;
+use crate::node_view::NodeView;
+use crate::page::Page;
+use crate::page_layout::{
+    CATALOG_ROOT_SIZE, FIXED_CATALOG_ROOT_FORMAT_VERSION, FORMAT_VERSION, MIN_FORMAT_VERSION,
+};
+
+/// Decoder deserializes pages according to the format version of the file they were read from.
+/// Pages are always written using the newest layout, older layouts are only ever read
+/// either to serve searches or to migrate the file to the newest layout.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Decoder {
+    version: usize,
+}
+
+impl Decoder {
+    pub fn new(version: usize) -> Result<Decoder, Error> {
+        if !(MIN_FORMAT_VERSION..=FORMAT_VERSION).contains(&version) {
+            return Err(Error::UnsupportedFormatVersion(version));
+        }
+        Ok(Decoder { version })
+    }
+
+    /// is_current returns true if the file uses the newest layout and thus can be written to.
+    pub fn is_current(&self) -> bool {
+        self.version == FORMAT_VERSION
+    }
+
+    /// node deserializes a node page.
+    pub fn node(&self, page: Page) -> Result<Node, Error> {
+        Node::decode(page, self.version)
+    }
+
+    /// view views a node page without deserializing it, see NodeView.
+    pub fn view<'a>(&self, page: &'a Page) -> Result<NodeView<'a>, Error> {
+        NodeView::new(page, self.version)
+    }
+
+    /// data_page deserializes a data page.
+    pub fn data_page(&self, page: Page) -> Result<DataPage, Error> {
+        DataPage::decode(page, self.version)
+    }
+
+    /// value reads a single value of a data page, see DataPage::read_value.
+    pub fn value(&self, page: &Page, idx: usize) -> Result<String, Error> {
+        DataPage::read_value(page, self.version, idx)
+    }
+
+    /// tree_root parses the root of a named tree from the value of its catalog entry, see the catalog module.
+    pub fn tree_root(&self, value: &str) -> Result<Offset, Error> {
+        let root = if self.version < FIXED_CATALOG_ROOT_FORMAT_VERSION {
+            value.parse().ok()
+        } else if value.len() == CATALOG_ROOT_SIZE {
+            usize::from_str_radix(value, 16).ok()
+        } else {
+            None
+        };
+        root.map(Offset).ok_or(Error::CorruptedPage)
+    }
+}
@@ -0,0 +1,172 @@
+use crate::data_page::DataPage;
+use crate::error::Error;
+use crate::node::Node;
+use crate::node_type::Offset;
+use crate::page_layout::PAGE_SIZE;
+
+/// DirtyPage is a page of the pending commit, it is kept deserialized until the commit writes it.
+pub enum DirtyPage {
+    Node(Node),
+    DataPage(DataPage),
+}
+
+/// DirtyPages buffers the pages of the pending commit in memory, a page modified many times by the commit
+/// is serialized and written once when the commit completes, see BTree::commit.
+/// The pages are appended to the file in the order they are allocated so the offset of each page
+/// is known as soon as it is allocated, committed pages are never part of the buffer.
+pub struct DirtyPages {
+    /// The offset of the first page of the pending commit, which is the end of the file.
+    start: usize,
+    pages: Vec<DirtyPage>,
+    /// The pages released by the pending commit, they are reused by the next pages allocated.
+    released: Vec<usize>,
+}
+
+impl DirtyPages {
+    /// new creates an empty buffer whose pages are appended starting at the given offset.
+    pub fn new(start: usize) -> DirtyPages {
+        DirtyPages {
+            start,
+            pages: vec![],
+            released: vec![],
+        }
+    }
+
+    /// allocate buffers a new page and returns the offset it is going to be written at.
+    pub fn allocate(&mut self, page: DirtyPage) -> Offset {
+        if let Some(idx) = self.released.pop() {
+            self.pages[idx] = page;
+            return Offset(self.start + idx * PAGE_SIZE);
+        }
+        self.pages.push(page);
+        Offset(self.start + (self.pages.len() - 1) * PAGE_SIZE)
+    }
+
+    /// release drops a page of the pending commit which is no longer referenced, its offset is reused
+    /// by the next page allocated. The pages ending the commit are dropped at once, while a page released
+    /// but never reused is still written, as the pages following it are written at their offsets.
+    pub fn release(&mut self, offset: &Offset) -> Result<(), Error> {
+        let idx = self.idx(offset).ok_or(Error::UnexpectedError)?;
+        self.released.push(idx);
+        // Drop the released pages ending the commit.
+        while let Some(pos) = self
+            .released
+            .iter()
+            .position(|idx| idx + 1 == self.pages.len())
+        {
+            self.released.swap_remove(pos);
+            self.pages.pop();
+        }
+        Ok(())
+    }
+
+    fn idx(&self, offset: &Offset) -> Option<usize> {
+        let distance = offset.0.checked_sub(self.start)?;
+        let idx = distance / PAGE_SIZE;
+        if distance.is_multiple_of(PAGE_SIZE)
+            && idx < self.pages.len()
+            && !self.released.contains(&idx)
+        {
+            Some(idx)
+        } else {
+            None
+        }
+    }
+
+    /// get returns the page at the given offset if it belongs to the pending commit.
+    pub fn get(&self, offset: &Offset) -> Option<&DirtyPage> {
+        self.idx(offset).map(|idx| &self.pages[idx])
+    }
+
+    /// contains returns true if the page at the given offset belongs to the pending commit.
+    pub fn contains(&self, offset: &Offset) -> bool {
+        self.idx(offset).is_some()
+    }
+
+    /// replace replaces a page of the pending commit, committed pages may not be replaced.
+    pub fn replace(&mut self, offset: &Offset, page: DirtyPage) -> Result<(), Error> {
+        let idx = self.idx(offset).ok_or(Error::UnexpectedError)?;
+        self.pages[idx] = page;
+        Ok(())
+    }
+
+    /// take removes the pages of the pending commit along with their offsets in order,
+    /// the pages of the next commit are appended following them.
+    pub fn take(&mut self) -> Vec<(Offset, DirtyPage)> {
+        let first = self.start;
+        self.start += self.pages.len() * PAGE_SIZE;
+        self.released.clear();
+        std::mem::take(&mut self.pages)
+            .into_iter()
+            .enumerate()
+            .map(|(idx, page)| (Offset(first + idx * PAGE_SIZE), page))
+            .collect()
+    }
+
+    /// clear drops the pages of the pending commit, the pages of the next commit are appended starting at the given offset.
+    pub fn clear(&mut self, start: usize) {
+        self.start = start;
+        self.pages.clear();
+        self.released.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::data_page::DataPage;
+    use crate::dirty::{DirtyPage, DirtyPages};
+    use crate::error::Error;
+    use crate::node_type::Offset;
+    use crate::page_layout::PAGE_SIZE;
+
+    #[test]
+    fn dirty_pages_are_appended_in_order() -> Result<(), Error> {
+        let mut dirty = DirtyPages::new(2 * PAGE_SIZE);
+        assert_eq!(
+            dirty.allocate(DirtyPage::DataPage(DataPage::new())),
+            Offset(2 * PAGE_SIZE)
+        );
+        assert_eq!(
+            dirty.allocate(DirtyPage::DataPage(DataPage::new())),
+            Offset(3 * PAGE_SIZE)
+        );
+        assert!(!dirty.contains(&Offset(PAGE_SIZE)));
+        assert!(!dirty.contains(&Offset(4 * PAGE_SIZE)));
+        assert!(dirty
+            .replace(&Offset(PAGE_SIZE), DirtyPage::DataPage(DataPage::new()))
+            .is_err());
+
+        let offsets: Vec<Offset> = dirty.take().into_iter().map(|(offset, _)| offset).collect();
+        assert_eq!(offsets, vec![Offset(2 * PAGE_SIZE), Offset(3 * PAGE_SIZE)]);
+        assert!(!dirty.contains(&Offset(2 * PAGE_SIZE)));
+        assert_eq!(
+            dirty.allocate(DirtyPage::DataPage(DataPage::new())),
+            Offset(4 * PAGE_SIZE)
+        );
+
+        // A released page is reused by the next page allocated, the last page is dropped at once.
+        assert_eq!(
+            dirty.allocate(DirtyPage::DataPage(DataPage::new())),
+            Offset(5 * PAGE_SIZE)
+        );
+        dirty.release(&Offset(4 * PAGE_SIZE))?;
+        assert!(!dirty.contains(&Offset(4 * PAGE_SIZE)));
+        assert_eq!(
+            dirty.allocate(DirtyPage::DataPage(DataPage::new())),
+            Offset(4 * PAGE_SIZE)
+        );
+        dirty.release(&Offset(5 * PAGE_SIZE))?;
+        let offsets: Vec<Offset> = dirty.take().into_iter().map(|(offset, _)| offset).collect();
+        assert_eq!(offsets, vec![Offset(4 * PAGE_SIZE)]);
+        assert!(dirty.release(&Offset(4 * PAGE_SIZE)).is_err());
+
+        // The pages of a failed commit are dropped.
+        dirty.clear(3 * PAGE_SIZE);
+        assert!(!dirty.contains(&Offset(4 * PAGE_SIZE)));
+        assert_eq!(
+            dirty.allocate(DirtyPage::DataPage(DataPage::new())),
+            Offset(3 * PAGE_SIZE)
+        );
+        Ok(())
+    }
+}
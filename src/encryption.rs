@@ -0,0 +1,101 @@
+use crate::error::Error;
+use chacha20poly1305::aead::{AeadInPlace, KeyInit};
+use chacha20poly1305::{Key, Tag, XChaCha20Poly1305, XNonce};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// A nonce is made of the position of the encrypted data (a page offset or a wal record index)
+/// followed by the generation of the write, both as BigEndian u64.
+pub const NONCE_SIZE: usize = 16;
+pub const TAG_SIZE: usize = 16;
+pub const ENCRYPTION_KEY_SIZE: usize = 32;
+
+/// Cipher is an authenticated cipher used to encrypt pages and wal records at rest.
+pub trait Cipher: Send + Sync {
+    /// encrypt encrypts data in place and returns a tag authenticating
+    /// both the encrypted data and the associated data.
+    fn encrypt(
+        &self,
+        nonce: &[u8; NONCE_SIZE],
+        associated_data: &[u8],
+        data: &mut [u8],
+    ) -> Result<[u8; TAG_SIZE], Error>;
+
+    /// decrypt authenticates data and the associated data against the tag and decrypts data in place,
+    /// it fails with Error::AuthenticationFailed if the data was tampered with or encrypted using another key.
+    fn decrypt(
+        &self,
+        nonce: &[u8; NONCE_SIZE],
+        associated_data: &[u8],
+        data: &mut [u8],
+        tag: &[u8; TAG_SIZE],
+    ) -> Result<(), Error>;
+}
+
+/// nonce derives the nonce of a write at a given position.
+pub fn nonce(position: u64, generation: u64) -> [u8; NONCE_SIZE] {
+    let mut nonce = [0x00; NONCE_SIZE];
+    nonce[..8].clone_from_slice(&position.to_be_bytes());
+    nonce[8..].clone_from_slice(&generation.to_be_bytes());
+    nonce
+}
+
+/// initial_generation returns the generation to start counting writes from.
+/// Pages may be rewritten in place, the generation makes sure no two writes
+/// to the same position share a nonce, including writes of previous runs.
+pub fn initial_generation() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|elapsed| elapsed.as_nanos() as u64)
+        .unwrap_or(0)
+}
+
+/// XChaCha20Poly1305Cipher is the built-in Cipher, the nonce is zero padded to the 24 bytes XChaCha20 expects.
+pub struct XChaCha20Poly1305Cipher {
+    cipher: XChaCha20Poly1305,
+}
+
+impl XChaCha20Poly1305Cipher {
+    pub fn new(key: &[u8; ENCRYPTION_KEY_SIZE]) -> XChaCha20Poly1305Cipher {
+        XChaCha20Poly1305Cipher {
+            cipher: XChaCha20Poly1305::new(Key::from_slice(key)),
+        }
+    }
+
+    fn extended_nonce(nonce: &[u8; NONCE_SIZE]) -> XNonce {
+        let mut extended = XNonce::default();
+        extended[..NONCE_SIZE].clone_from_slice(nonce);
+        extended
+    }
+}
+
+impl Cipher for XChaCha20Poly1305Cipher {
+    fn encrypt(
+        &self,
+        nonce: &[u8; NONCE_SIZE],
+        associated_data: &[u8],
+        data: &mut [u8],
+    ) -> Result<[u8; TAG_SIZE], Error> {
+        let tag = self
+            .cipher
+            .encrypt_in_place_detached(&Self::extended_nonce(nonce), associated_data, data)
+            .map_err(|_| Error::UnexpectedError)?;
+        Ok(tag.into())
+    }
+
+    fn decrypt(
+        &self,
+        nonce: &[u8; NONCE_SIZE],
+        associated_data: &[u8],
+        data: &mut [u8],
+        tag: &[u8; TAG_SIZE],
+    ) -> Result<(), Error> {
+        self.cipher
+            .decrypt_in_place_detached(
+                &Self::extended_nonce(nonce),
+                associated_data,
+                data,
+                Tag::from_slice(tag),
+            )
+            .map_err(|_| Error::AuthenticationFailed)
+    }
+}
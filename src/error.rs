@@ -1,12 +1,51 @@
-#[derive(Debug)]
+#[derive(Clone, Debug)]
 pub enum Error {
     KeyNotFound,
     KeyAlreadyExists,
     UnexpectedError,
+    /// A key is longer than MAX_KEY_SIZE bytes, or the keys of a node do not fit into its page.
     KeyOverflowError,
     ValueOverflowError,
     TryFromSliceError(&'static str),
     UTF8Error,
+    /// A data page was written using a compression codec this version does not know.
+    UnsupportedCompression(u8),
+    /// A stored value could not be decompressed.
+    CorruptedValue,
+    /// A page holds counts or lengths which do not fit into it.
+    CorruptedPage,
+    /// The file is encrypted but no cipher was configured or the other way around.
+    EncryptionMismatch,
+    /// Encrypted data was tampered with or encrypted using another key.
+    AuthenticationFailed,
+    /// An integer read from disk does not fit into the host's usize.
+    IntegerOverflowError,
+    /// The file is not a tree file, its first page does not start with the magic number.
+    InvalidFileHeader,
+    /// The file was written using a page layout this version cannot read.
+    UnsupportedFormatVersion(usize),
+    /// The file was written using an older page layout, it has to be migrated before it is modified.
+    MigrationRequired,
+    /// The file was written using a different page size.
+    PageSizeMismatch,
+    /// The file was written using a different b parameter.
+    BParameterMismatch,
+    /// The file was written using a different key ordering, see the comparator module.
+    ComparatorMismatch,
+    /// No secondary index is registered under the given name.
+    IndexNotFound,
+    /// No tree is stored in the catalog under the given name.
+    TreeNotFound,
+    /// The file holds a catalog of trees but was opened as a single tree or the other way around.
+    CatalogMismatch,
+    /// The tree was opened read-only, for example as a follower, and cannot be modified.
+    ReadOnly,
+    /// The file is locked by another writer, writers exclude each other while readers exclude no tree.
+    Locked,
+    /// No root was committed up until the given sequence number, or the history does not reach back that far.
+    CommitNotFound,
+    /// The tree does not hold one of its invariants, see BTree::check.
+    InvariantViolation(String),
 }
 
 impl std::convert::From<std::io::Error> for Error {
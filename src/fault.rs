@@ -0,0 +1,359 @@
+use crate::error::Error;
+use crate::storage::{Backend, Storage};
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+
+/// Fault is a fault injected into a write.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Fault {
+    /// The write fails without writing anything.
+    FailWrite,
+    /// The first half of the write is written before it fails, as when the process dies in the middle of a write.
+    /// Writes are assumed to be torn at sector boundaries so the first half of a page is written as a whole.
+    TearWrite,
+}
+
+/// Undo restores the bytes overwritten by a write which was not synced yet, along with the previous length.
+struct Undo {
+    len: u64,
+    offset: u64,
+    bytes: Vec<u8>,
+}
+
+/// Journal holds the unsynced writes made to a storage along with another handle on it used to revert them.
+struct Journal {
+    storage: Box<dyn Storage>,
+    undo: Vec<Undo>,
+}
+
+#[derive(Default)]
+struct State {
+    /// The number of writes made so far across every storage.
+    writes: usize,
+    /// The index of the write to inject a fault into.
+    fault: Option<(usize, Fault)>,
+    fail_reads: bool,
+    journals: HashMap<PathBuf, Rc<RefCell<Journal>>>,
+}
+
+/// FaultyBackend wraps a backend injecting faults into the storage it opens, it is meant for testing
+/// how the tree copes with failing writes, torn writes, failing reads and power loss.
+/// Writes are counted across every file of the tree, clones share the faults and the count.
+#[derive(Clone)]
+pub struct FaultyBackend {
+    backend: Rc<dyn Backend>,
+    state: Rc<RefCell<State>>,
+}
+
+impl FaultyBackend {
+    pub fn new(backend: impl Backend + 'static) -> FaultyBackend {
+        FaultyBackend {
+            backend: Rc::new(backend),
+            state: Rc::new(RefCell::new(State::default())),
+        }
+    }
+
+    /// inject injects the given fault into the write made once the given number of writes are made,
+    /// replacing the fault previously injected if it was not triggered yet.
+    pub fn inject(&self, writes: usize, fault: Fault) {
+        let mut state = self.state.borrow_mut();
+        state.fault = Some((state.writes + writes, fault));
+    }
+
+    /// clear removes the fault injected if it was not triggered yet and lets reads succeed.
+    pub fn clear(&self) {
+        let mut state = self.state.borrow_mut();
+        state.fault = None;
+        state.fail_reads = false;
+    }
+
+    /// writes returns the number of writes made so far.
+    pub fn writes(&self) -> usize {
+        self.state.borrow().writes
+    }
+
+    /// fail_reads makes every read fail with an error of kind Other while set.
+    pub fn fail_reads(&self, fail: bool) {
+        self.state.borrow_mut().fail_reads = fail;
+    }
+
+    /// power_loss reverts every write which was not synced, as if the machine lost power.
+    /// The trees using the backend are expected to be dropped and reopened.
+    pub fn power_loss(&self) -> Result<(), Error> {
+        for journal in self.state.borrow().journals.values() {
+            let mut journal = journal.borrow_mut();
+            while let Some(undo) = journal.undo.pop() {
+                journal.storage.write_at(undo.offset, &undo.bytes)?;
+                journal.storage.set_len(undo.len)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Backend for FaultyBackend {
+    fn open(&self, path: &Path) -> Result<Box<dyn Storage>, Error> {
+        let storage = self.backend.open(path)?;
+        let mut state = self.state.borrow_mut();
+        let journal = match state.journals.get(path) {
+            Some(journal) => journal.clone(),
+            None => {
+                let journal = Rc::new(RefCell::new(Journal {
+                    storage: self.backend.open(path)?,
+                    undo: vec![],
+                }));
+                state.journals.insert(path.to_path_buf(), journal.clone());
+                journal
+            }
+        };
+        Ok(Box::new(FaultyStorage {
+            storage,
+            journal,
+            state: self.state.clone(),
+        }))
+    }
+}
+
+/// FaultyStorage is a storage opened by a FaultyBackend.
+struct FaultyStorage {
+    storage: Box<dyn Storage>,
+    journal: Rc<RefCell<Journal>>,
+    state: Rc<RefCell<State>>,
+}
+
+fn injected() -> Error {
+    std::io::Error::other("injected fault").into()
+}
+
+impl FaultyStorage {
+    /// record records what it takes to revert a write of len bytes at offset.
+    fn record(&mut self, offset: u64, len: u64) -> Result<(), Error> {
+        let storage_len = self.storage.len()?;
+        let end = std::cmp::min(offset.saturating_add(len), storage_len);
+        let mut bytes = vec![0x00; end.saturating_sub(offset) as usize];
+        self.storage.read_at(offset, &mut bytes)?;
+        self.journal.borrow_mut().undo.push(Undo {
+            len: storage_len,
+            offset,
+            bytes,
+        });
+        Ok(())
+    }
+}
+
+impl Storage for FaultyStorage {
+    fn read_at(&mut self, offset: u64, buf: &mut [u8]) -> Result<(), Error> {
+        if self.state.borrow().fail_reads {
+            return Err(injected());
+        }
+        self.storage.read_at(offset, buf)
+    }
+
+    fn write_at(&mut self, offset: u64, buf: &[u8]) -> Result<(), Error> {
+        let fault = {
+            let mut state = self.state.borrow_mut();
+            let write = state.writes;
+            state.writes += 1;
+            match state.fault {
+                Some((at, fault)) if at == write => state.fault.take().map(|_| fault),
+                _ => None,
+            }
+        };
+        self.record(offset, buf.len() as u64)?;
+        match fault {
+            None => self.storage.write_at(offset, buf),
+            Some(Fault::FailWrite) => Err(injected()),
+            Some(Fault::TearWrite) => {
+                self.storage.write_at(offset, &buf[..buf.len() / 2])?;
+                Err(injected())
+            }
+        }
+    }
+
+    fn sync(&mut self) -> Result<(), Error> {
+        self.storage.sync()?;
+        self.journal.borrow_mut().undo.clear();
+        Ok(())
+    }
+
+    fn len(&mut self) -> Result<u64, Error> {
+        self.storage.len()
+    }
+
+    fn set_len(&mut self, len: u64) -> Result<(), Error> {
+        let storage_len = self.storage.len()?;
+        self.record(len, storage_len.saturating_sub(len))?;
+        self.storage.set_len(len)
+    }
+    fn lock(&mut self, exclusive: bool) -> Result<(), Error> {
+        self.storage.lock(exclusive)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::btree::{BTree, BTreeBuilder};
+    use crate::error::Error;
+    use crate::fault::{Fault, FaultyBackend};
+    use crate::node_type::Key;
+    use crate::storage::MemoryBackend;
+    use std::collections::BTreeMap;
+    use std::path::Path;
+
+    enum Op {
+        Insert(&'static str, &'static str),
+        Delete(&'static str),
+    }
+
+    /// script inserts enough pairs to split nodes, replaces a value and deletes enough pairs to merge nodes.
+    fn script() -> Vec<Op> {
+        let mut script: Vec<Op> = ["a", "b", "c", "d", "e", "f", "g", "h", "i"]
+            .iter()
+            .map(|key| Op::Insert(key, key))
+            .collect();
+        script.push(Op::Insert("c", "cc"));
+        for key in ["d", "a", "h", "e", "b"] {
+            script.push(Op::Delete(key));
+        }
+        script
+    }
+
+    fn apply(
+        btree: &mut BTree,
+        model: &mut BTreeMap<String, String>,
+        op: &Op,
+    ) -> Result<(), Error> {
+        match op {
+            Op::Insert(key, value) => {
+                model.insert(key.to_string(), value.to_string());
+                btree.insert(key.to_string(), value.to_string())
+            }
+            Op::Delete(key) => {
+                model.remove(*key);
+                btree.delete(Key(key.to_string()))
+            }
+        }
+    }
+
+    /// check_faults injects the fault into every write of every operation of the script in turn,
+    /// the tree is reopened after each fault and checked to hold the last committed state.
+    fn check_faults(fault: Fault, power_loss: bool) -> Result<(), Error> {
+        let upper = |value: &str| Some(value.to_uppercase());
+        let script = script();
+        for (i, op) in script.iter().enumerate() {
+            for writes in 0.. {
+                let backend = FaultyBackend::new(MemoryBackend::new());
+                let builder = BTreeBuilder::new()
+                    .path(Path::new("/db"))
+                    .b_parameter(2)
+                    .backend(backend.clone())
+                    .index("upper", upper);
+                let mut btree = builder.build()?;
+                let mut model = BTreeMap::new();
+                for op in script[..i].iter() {
+                    apply(&mut btree, &mut model, op)?;
+                }
+                let before = model.clone();
+                backend.inject(writes, fault);
+                let res = apply(&mut btree, &mut model, op);
+                drop(btree);
+                backend.clear();
+                if power_loss {
+                    backend.power_loss()?;
+                }
+
+                let mut btree = builder.build()?;
+                let root = btree.root()?;
+                let pairs: BTreeMap<String, String> =
+                    btree.sub_tree_pairs(root)?.into_iter().collect();
+                // A torn header fails its checksum, the header of the previous commit is read from the other slot.
+                match res {
+                    Ok(()) => assert_eq!(pairs, model),
+                    Err(_) => assert_eq!(pairs, before),
+                }
+                for value in pairs.values() {
+                    assert_eq!(
+                        &btree.search_by_index("upper", value.to_uppercase())?,
+                        value
+                    );
+                }
+                btree.insert("z".to_string(), "z".to_string())?;
+                assert_eq!(btree.search("z".to_string())?, "z");
+                if res.is_ok() {
+                    break;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn failed_writes_keep_last_commit() -> Result<(), Error> {
+        check_faults(Fault::FailWrite, false)
+    }
+
+    #[test]
+    fn torn_writes_keep_last_commit() -> Result<(), Error> {
+        check_faults(Fault::TearWrite, false)
+    }
+
+    #[test]
+    fn power_loss_keeps_last_commit() -> Result<(), Error> {
+        check_faults(Fault::FailWrite, true)?;
+        check_faults(Fault::TearWrite, true)
+    }
+
+    #[test]
+    fn failed_reads_are_reported() -> Result<(), Error> {
+        let backend = FaultyBackend::new(MemoryBackend::new());
+        let mut btree = BTreeBuilder::new()
+            .path(Path::new("/db"))
+            .b_parameter(2)
+            .backend(backend.clone())
+            .build()?;
+        btree.insert("a".to_string(), "shalom".to_string())?;
+        backend.fail_reads(true);
+        let res = btree.search("a".to_string());
+        assert!(matches!(res, Err(Error::UnexpectedError)));
+        let res = btree.insert("b".to_string(), "hello".to_string());
+        assert!(matches!(res, Err(Error::UnexpectedError)));
+        backend.fail_reads(false);
+        assert_eq!(btree.search("a".to_string())?, "shalom");
+        btree.insert("b".to_string(), "hello".to_string())?;
+        assert_eq!(btree.search("b".to_string())?, "hello");
+        Ok(())
+    }
+
+    #[test]
+    fn failed_reads_fail_the_whole_batch() -> Result<(), Error> {
+        use crate::btree::WriteOp;
+
+        let backend = FaultyBackend::new(MemoryBackend::new());
+        let mut btree = BTreeBuilder::new()
+            .path(Path::new("/db"))
+            .b_parameter(2)
+            .backend(backend.clone())
+            .build()?;
+        btree.insert("a".to_string(), "shalom".to_string())?;
+        backend.fail_reads(true);
+        let res = btree.write_batch(&[
+            WriteOp::Delete(Key("missing".to_string())),
+            WriteOp::Insert("b".to_string(), "hello".to_string()),
+        ]);
+        assert!(matches!(res, Err(Error::UnexpectedError)));
+        backend.fail_reads(false);
+        let res = btree.search("b".to_string());
+        assert!(matches!(res, Err(Error::KeyNotFound)));
+
+        let results = btree.write_batch(&[
+            WriteOp::Delete(Key("missing".to_string())),
+            WriteOp::Insert("b".to_string(), "hello".to_string()),
+        ])?;
+        assert!(matches!(results[0], Err(Error::KeyNotFound)));
+        assert!(results[1].is_ok());
+        assert_eq!(btree.search("b".to_string())?, "hello");
+        Ok(())
+    }
+}
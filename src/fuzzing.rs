@@ -0,0 +1,134 @@
+use crate::change_log::ChangeLog;
+use crate::comparator::{Bytewise, Natural};
+use crate::data_page::DataPage;
+use crate::node::Node;
+use crate::node_view::NodeView;
+use crate::page::Page;
+use crate::page_layout::{FORMAT_VERSION, MIN_FORMAT_VERSION, PAGE_SIZE};
+use crate::storage::{Backend, MemoryBackend, MemoryStorage, Storage};
+use crate::wal::Wal;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+
+// The entry points of the fuzz targets, see the fuzz directory. Each of them decodes arbitrary bytes
+// discarding the result, decoding is expected to return an error rather than panic.
+
+/// page copies the bytes to a page padding them with zeros, bytes past the end of the page are dropped.
+fn page(data: &[u8]) -> Page {
+    let mut raw = [0x00; PAGE_SIZE];
+    let len = std::cmp::min(data.len(), PAGE_SIZE);
+    raw[..len].clone_from_slice(&data[..len]);
+    Page::new(raw)
+}
+
+/// decode_node decodes the bytes as a node, and searches them in place, using every supported format version.
+pub fn decode_node(data: &[u8]) {
+    let page = page(data);
+    for version in MIN_FORMAT_VERSION..=FORMAT_VERSION {
+        if let Ok(view) = NodeView::new(&page, version) {
+            let _ = view.search("key", &Bytewise);
+            let _ = view.search("key", &Natural);
+        }
+        let _ = Node::decode(Page::new(page.get_data()), version);
+    }
+}
+
+/// decode_data_page decodes the bytes as a data page, and reads its first values in place,
+/// using every supported format version.
+pub fn decode_data_page(data: &[u8]) {
+    let page = page(data);
+    for version in MIN_FORMAT_VERSION..=FORMAT_VERSION {
+        for idx in 0..4 {
+            let _ = DataPage::read_value(&page, version, idx);
+        }
+        let _ = DataPage::decode(Page::new(page.get_data()), version);
+    }
+}
+
+/// decode_wal opens a wal holding the bytes and reads its records.
+pub fn decode_wal(data: &[u8]) {
+    let mut storage = MemoryStorage::new();
+    if storage.write_at(0, data).is_err() {
+        return;
+    }
+    if let Ok(mut wal) = Wal::new(Box::new(storage), None) {
+        let _ = wal.records();
+    }
+}
+
+/// decode_change_log opens a change log holding the bytes and reads its records, every record is taken as committed.
+pub fn decode_change_log(data: &[u8]) {
+    let backend = MemoryBackend::new();
+    let path = Path::new("/fuzzing/db.changes");
+    let written = backend
+        .open(path)
+        .and_then(|mut storage| storage.write_at(0, data));
+    if written.is_err() {
+        return;
+    }
+    if let Ok(change_log) =
+        ChangeLog::new(Rc::new(backend), PathBuf::from(path), None, u64::MAX, false)
+    {
+        if let Ok(changes) = change_log.changes_since(0) {
+            changes.for_each(drop);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::data_page::DataPage;
+    use crate::fuzzing::{decode_change_log, decode_data_page, decode_node, decode_wal};
+    use crate::node::Node;
+    use crate::node_type::{Key, KeyValuePair, NodeType, Offset};
+    use crate::page::Page;
+    use std::convert::TryFrom;
+
+    #[test]
+    fn decoders_reject_arbitrary_bytes() -> Result<(), crate::error::Error> {
+        let mut pair = KeyValuePair::new("foo".to_string(), 0);
+        pair.expiry = Some(1);
+        let leaf = Node::new(NodeType::Leaf(Offset(4096), vec![pair]), false);
+        let internal = Node::new(
+            NodeType::Internal(
+                vec![Offset(4096), Offset(8192)],
+                vec![Key("bar".to_string())],
+            ),
+            true,
+        );
+        let mut data_page = DataPage::new();
+        data_page.insert("baz".to_string())?;
+        let pages = [
+            Page::try_from(&leaf)?.get_data(),
+            Page::try_from(&internal)?.get_data(),
+            Page::try_from(&data_page)?.get_data(),
+        ];
+
+        // Valid pages with every byte of their headers replaced, along with pseudo random pages.
+        let mut state: u64 = 0x2545f4914f6cdd1d;
+        for raw in pages.iter() {
+            for idx in 0..64 {
+                for byte in [0x00, 0x01, 0x7f, 0x80, 0xff] {
+                    let mut raw = *raw;
+                    raw[idx] = byte;
+                    decode_node(&raw);
+                    decode_data_page(&raw);
+                    decode_wal(&raw[..idx]);
+                    decode_change_log(&raw[..idx]);
+                }
+            }
+            let mut raw = *raw;
+            for byte in raw.iter_mut() {
+                state ^= state << 13;
+                state ^= state >> 7;
+                state ^= state << 17;
+                *byte = state as u8;
+            }
+            decode_node(&raw);
+            decode_data_page(&raw);
+            decode_wal(&raw);
+            decode_change_log(&raw);
+        }
+        Ok(())
+    }
+}
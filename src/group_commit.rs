@@ -0,0 +1,273 @@
+use crate::btree::{BTree, WriteOp};
+use crate::error::Error;
+use crate::node_type::Key;
+use crate::stats::{Counters, Stats};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError, Sender};
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// GroupCommit shares a tree between threads. The writes of concurrent callers are queued and applied together,
+/// each batch is committed with a single sync of the tree file and a single wal record, see BTree::write_batch.
+/// The tree is owned by a thread applying the batches, a caller is answered once the commit of its write is durable.
+/// Searches are answered from the last commit without waiting for the pending batch.
+#[derive(Clone)]
+pub struct GroupCommit {
+    requests: Sender<Request>,
+    counters: Arc<Counters>,
+}
+
+/// GroupCommitBuilder is a Builder for the GroupCommit struct.
+pub struct GroupCommitBuilder {
+    /// The number of writes above which a batch is committed without waiting for more writes.
+    max_batch_size: usize,
+    /// How long the first write of a batch waits for other writes to join it.
+    max_batch_delay: Duration,
+}
+
+enum Request {
+    Write(WriteOp, Sender<Result<(), Error>>),
+    Search(String, Sender<Result<String, Error>>),
+}
+
+impl GroupCommitBuilder {
+    pub fn new() -> GroupCommitBuilder {
+        GroupCommitBuilder {
+            max_batch_size: 128,
+            max_batch_delay: Duration::from_millis(1),
+        }
+    }
+
+    pub fn max_batch_size(mut self, max_batch_size: usize) -> GroupCommitBuilder {
+        self.max_batch_size = max_batch_size;
+        self
+    }
+
+    pub fn max_batch_delay(mut self, max_batch_delay: Duration) -> GroupCommitBuilder {
+        self.max_batch_delay = max_batch_delay;
+        self
+    }
+
+    /// spawn starts the thread applying the batches, the tree is opened on that thread using the given callback
+    /// as it cannot be moved between threads. The thread exits once every GroupCommit handle is dropped.
+    pub fn spawn(
+        &self,
+        open: impl FnOnce() -> Result<BTree, Error> + Send + 'static,
+    ) -> Result<GroupCommit, Error> {
+        if self.max_batch_size == 0 {
+            return Err(Error::UnexpectedError);
+        }
+        let (requests, receiver) = mpsc::channel();
+        let (opened, open_result) = mpsc::channel();
+        let max_batch_size = self.max_batch_size;
+        let max_batch_delay = self.max_batch_delay;
+        thread::spawn(move || {
+            let btree = match open() {
+                Ok(btree) => btree,
+                Err(e) => {
+                    let _ = opened.send(Err(e));
+                    return;
+                }
+            };
+            if opened.send(Ok(btree.counters())).is_ok() {
+                run(btree, receiver, max_batch_size, max_batch_delay);
+            }
+        });
+        let counters = open_result.recv().map_err(|_| Error::UnexpectedError)??;
+        Ok(GroupCommit { requests, counters })
+    }
+}
+
+impl Default for GroupCommitBuilder {
+    // A default GroupCommitBuilder commits up to 128 writes at once waiting up to a millisecond for them.
+    fn default() -> Self {
+        GroupCommitBuilder::new()
+    }
+}
+
+/// run applies the requests until every GroupCommit handle is dropped.
+fn run(
+    mut btree: BTree,
+    requests: Receiver<Request>,
+    max_batch_size: usize,
+    max_batch_delay: Duration,
+) {
+    loop {
+        // Wait for the first write of the next batch, searches are answered meanwhile.
+        let first = loop {
+            match requests.recv() {
+                Ok(Request::Write(write, reply)) => break (write, reply),
+                Ok(Request::Search(key, reply)) => {
+                    let _ = reply.send(btree.search(key));
+                }
+                Err(_) => return,
+            }
+        };
+        let mut batch = vec![first];
+        let deadline = Instant::now() + max_batch_delay;
+        while batch.len() < max_batch_size {
+            match requests.recv_timeout(deadline.saturating_duration_since(Instant::now())) {
+                Ok(Request::Write(write, reply)) => batch.push((write, reply)),
+                Ok(Request::Search(key, reply)) => {
+                    let _ = reply.send(btree.search(key));
+                }
+                // The handles may have been dropped while the batch was collected, it is committed nonetheless.
+                Err(RecvTimeoutError::Timeout) | Err(RecvTimeoutError::Disconnected) => break,
+            }
+        }
+        let (writes, replies): (Vec<WriteOp>, Vec<Sender<Result<(), Error>>>) =
+            batch.into_iter().unzip();
+        match btree.write_batch(&writes) {
+            Ok(results) => {
+                for (reply, result) in replies.into_iter().zip(results) {
+                    let _ = reply.send(result);
+                }
+            }
+            Err(e) => {
+                for reply in replies {
+                    let _ = reply.send(Err(e.clone()));
+                }
+            }
+        }
+    }
+}
+
+impl GroupCommit {
+    /// insert inserts a key value pair, it returns once the pair is durable.
+    pub fn insert(&self, key: String, value: String) -> Result<(), Error> {
+        self.write(WriteOp::Insert(key, value))
+    }
+
+    /// insert_with_ttl inserts a key value pair which expires once the given duration elapses, see BTree::insert_with_ttl.
+    pub fn insert_with_ttl(&self, key: String, value: String, ttl: Duration) -> Result<(), Error> {
+        self.write(WriteOp::InsertWithTtl(key, value, ttl))
+    }
+
+    /// delete deletes a given key, it returns once the deletion is durable.
+    pub fn delete(&self, key: Key) -> Result<(), Error> {
+        self.write(WriteOp::Delete(key))
+    }
+
+    /// search searches for a specific key in the last commit.
+    pub fn search(&self, key: String) -> Result<String, Error> {
+        let (reply, result) = mpsc::channel();
+        self.requests
+            .send(Request::Search(key, reply))
+            .map_err(|_| Error::UnexpectedError)?;
+        result.recv().map_err(|_| Error::UnexpectedError)?
+    }
+
+    /// stats returns a snapshot of the counters of the tree, see BTree::stats.
+    pub fn stats(&self) -> Stats {
+        self.counters.snapshot()
+    }
+
+    /// write queues a write and waits for the batch it joined to be committed.
+    fn write(&self, write: WriteOp) -> Result<(), Error> {
+        let (reply, result) = mpsc::channel();
+        self.requests
+            .send(Request::Write(write, reply))
+            .map_err(|_| Error::UnexpectedError)?;
+        result.recv().map_err(|_| Error::UnexpectedError)?
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::btree::BTreeBuilder;
+    use crate::error::Error;
+    use crate::group_commit::GroupCommitBuilder;
+    use crate::node_type::Key;
+    use crate::storage::MemoryBackend;
+    use std::path::Path;
+    use std::thread;
+    use std::time::Duration;
+
+    #[test]
+    fn group_commit_batches_concurrent_writes() -> Result<(), Error> {
+        let group_commit = GroupCommitBuilder::new()
+            .max_batch_size(16)
+            .max_batch_delay(Duration::from_millis(20))
+            .spawn(|| {
+                BTreeBuilder::new()
+                    .path(Path::new("/group_commit_batches_concurrent_writes/db"))
+                    .b_parameter(2)
+                    .backend(MemoryBackend::new())
+                    .build()
+            })?;
+        let opened = group_commit.stats();
+
+        let writers: Vec<_> = (0..8)
+            .map(|writer| {
+                let group_commit = group_commit.clone();
+                thread::spawn(move || -> Result<(), Error> {
+                    for idx in 0..10 {
+                        let key = format!("{}/{}", writer, idx);
+                        group_commit.insert(key.clone(), key.to_uppercase())?;
+                        // A write is answered once it is committed, the writer reads it right away.
+                        assert_eq!(group_commit.search(key.clone())?, key.to_uppercase());
+                    }
+                    let res = group_commit.delete(Key(format!("{}/missing", writer)));
+                    assert!(matches!(res, Err(Error::KeyNotFound)));
+                    group_commit.delete(Key(format!("{}/0", writer)))
+                })
+            })
+            .collect();
+        for writer in writers {
+            writer.join().map_err(|_| Error::UnexpectedError)??;
+        }
+
+        for writer in 0..8 {
+            let res = group_commit.search(format!("{}/0", writer));
+            assert!(matches!(res, Err(Error::KeyNotFound)));
+            for idx in 1..10 {
+                let key = format!("{}/{}", writer, idx);
+                assert_eq!(group_commit.search(key.clone())?, key.to_uppercase());
+            }
+        }
+        // 88 writes succeeded, concurrent writers share commits.
+        let stats = group_commit.stats();
+        assert!(stats.wal_records - opened.wal_records < 88);
+        Ok(())
+    }
+
+    #[test]
+    fn group_commit_fails_invalid_writes_alone() -> Result<(), Error> {
+        let group_commit = GroupCommitBuilder::new()
+            .max_batch_size(16)
+            .max_batch_delay(Duration::from_millis(20))
+            .spawn(|| {
+                BTreeBuilder::new()
+                    .path(Path::new("/group_commit_fails_invalid_writes_alone/db"))
+                    .b_parameter(2)
+                    .backend(MemoryBackend::new())
+                    .build()
+            })?;
+
+        let writers: Vec<_> = (0..8)
+            .map(|writer| {
+                let group_commit = group_commit.clone();
+                thread::spawn(move || -> Result<(), Error> {
+                    let key = writer.to_string();
+                    if writer == 0 {
+                        let res = group_commit.insert(key, "y".repeat(5000));
+                        assert!(matches!(res, Err(Error::ValueOverflowError)));
+                        return Ok(());
+                    }
+                    group_commit.insert(key.clone(), key)
+                })
+            })
+            .collect();
+        for writer in writers {
+            writer.join().map_err(|_| Error::UnexpectedError)??;
+        }
+
+        let res = group_commit.search("0".to_string());
+        assert!(matches!(res, Err(Error::KeyNotFound)));
+        for writer in 1..8 {
+            let key = writer.to_string();
+            assert_eq!(group_commit.search(key.clone())?, key);
+        }
+        Ok(())
+    }
+}
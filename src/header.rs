@@ -0,0 +1,214 @@
+use crate::comparator::BYTEWISE;
+use crate::error::Error;
+use crate::node_type::Offset;
+use crate::page::Page;
+use crate::page_layout::{
+    header_slots, FromByte, B_PARAMETER_OFFSET, CATALOG_OFFSET, COMPARATOR_NAME_LEN_SIZE,
+    ENCRYPTED_OFFSET, FORMAT_VERSION, FORMAT_VERSION_OFFSET, FREE_LIST_HEAD_OFFSET,
+    HEADER_CHECKSUM_OFFSET, HEADER_SLOTS_FORMAT_VERSION, HEADER_SLOT_SEQ_OFFSET,
+    INDEX_NAME_LEN_SIZE, LAST_SEQUENCE_OFFSET, MAGIC_NUMBER, MAGIC_NUMBER_OFFSET,
+    MAGIC_NUMBER_SIZE, MIN_FORMAT_VERSION, NUM_INDEXES_OFFSET, PAGE_SIZE, PAGE_SIZE_OFFSET,
+    PTR_SIZE, ROOT_OFFSET,
+};
+use std::convert::TryFrom;
+
+/// Header describes the tree file it is stored in, it occupies the header slots at the start of the file.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Header {
+    pub version: usize,
+    pub page_size: usize,
+    pub b: usize,
+    pub root: Offset,
+    /// The first page of the free list, None when there are no free pages.
+    pub free_list_head: Option<Offset>,
+    /// Whether the pages following the header are encrypted.
+    pub encrypted: bool,
+    /// Whether the root is the root of a catalog mapping tree names to tree roots.
+    pub catalog: bool,
+    /// The sequence number of the last committed change.
+    pub last_seq: u64,
+    /// The name and root of every secondary index, committed together with the root.
+    pub indexes: Vec<(String, Offset)>,
+    /// The name of the comparator the keys are ordered by, see the comparator module.
+    pub comparator: String,
+    /// Incremented every time the header is written, it selects the slot the header is written to.
+    pub slot_seq: u64,
+}
+
+impl Header {
+    pub fn new(b: usize, root: Offset) -> Header {
+        Header {
+            version: FORMAT_VERSION,
+            page_size: PAGE_SIZE,
+            b,
+            root,
+            free_list_head: None,
+            encrypted: false,
+            catalog: false,
+            last_seq: 0,
+            indexes: vec![],
+            comparator: BYTEWISE.to_string(),
+            slot_seq: 0,
+        }
+    }
+
+    /// slot returns the offset of the header slot the header is written to.
+    pub fn slot(&self) -> Offset {
+        Offset(self.slot_seq as usize % header_slots(self.version) * PAGE_SIZE)
+    }
+}
+
+/// checksum returns the CRC-32 (IEEE) of the given bytes.
+pub fn checksum(data: &[u8]) -> u64 {
+    let mut crc = !0u32;
+    for byte in data {
+        crc ^= *byte as u32;
+        for _ in 0..8 {
+            crc = (crc >> 1) ^ (0xedb8_8320 & (crc & 1).wrapping_neg());
+        }
+    }
+    !crc as u64
+}
+
+fn be_u64(bytes: &[u8]) -> u64 {
+    let mut raw = [0x00; PTR_SIZE];
+    raw.clone_from_slice(bytes);
+    u64::from_be_bytes(raw)
+}
+
+/// Implement TryFrom<Page> for Header allowing for easier
+/// deserialization of the header page, the magic number, format version
+/// and page size are validated along the way.
+impl TryFrom<Page> for Header {
+    type Error = Error;
+
+    fn try_from(page: Page) -> Result<Header, Error> {
+        if page.get_ptr_from_offset(MAGIC_NUMBER_OFFSET, MAGIC_NUMBER_SIZE)? != MAGIC_NUMBER {
+            return Err(Error::InvalidFileHeader);
+        }
+        // Older versions are accepted, they are read through the decoder.
+        let version = page.get_value_from_offset(FORMAT_VERSION_OFFSET)?;
+        if !(MIN_FORMAT_VERSION..=FORMAT_VERSION).contains(&version) {
+            return Err(Error::UnsupportedFormatVersion(version));
+        }
+        // A header torn by a crash fails its checksum, the other slot is used instead.
+        let (slot_seq, end) = if version < HEADER_SLOTS_FORMAT_VERSION {
+            (0, PAGE_SIZE)
+        } else {
+            let expected = page.get_ptr_from_offset(HEADER_CHECKSUM_OFFSET, PTR_SIZE)?;
+            if checksum(page.get_ptr_from_offset(0, HEADER_CHECKSUM_OFFSET)?) != be_u64(expected) {
+                return Err(Error::InvalidFileHeader);
+            }
+            let slot_seq = page.get_ptr_from_offset(HEADER_SLOT_SEQ_OFFSET, PTR_SIZE)?;
+            (be_u64(slot_seq), HEADER_SLOT_SEQ_OFFSET)
+        };
+        let page_size = page.get_value_from_offset(PAGE_SIZE_OFFSET)?;
+        if page_size != PAGE_SIZE {
+            return Err(Error::PageSizeMismatch);
+        }
+        // Offset zero is the header itself and thus can never be a free page.
+        let free_list_head = match page.get_value_from_offset(FREE_LIST_HEAD_OFFSET)? {
+            0 => None,
+            offset => Some(Offset(offset)),
+        };
+
+        // Files written before indexes were introduced have zeros here, that is no indexes.
+        let num_indexes = page.get_value_from_offset(NUM_INDEXES_OFFSET)?;
+        let mut indexes = Vec::<(String, Offset)>::new();
+        let mut offset = NUM_INDEXES_OFFSET + PTR_SIZE;
+        for _i in 0..num_indexes {
+            if offset + INDEX_NAME_LEN_SIZE > end {
+                return Err(Error::InvalidFileHeader);
+            }
+            let name_len = page.get_ptr_from_offset(offset, INDEX_NAME_LEN_SIZE)?[0] as usize;
+            offset += INDEX_NAME_LEN_SIZE;
+            if offset + name_len + PTR_SIZE > end {
+                return Err(Error::InvalidFileHeader);
+            }
+            let name = String::from_utf8(page.get_ptr_from_offset(offset, name_len)?.to_vec())
+                .map_err(|_| Error::UTF8Error)?;
+            offset += name_len;
+            indexes.push((name, Offset(page.get_value_from_offset(offset)?)));
+            offset += PTR_SIZE;
+        }
+        if offset + COMPARATOR_NAME_LEN_SIZE > end {
+            return Err(Error::InvalidFileHeader);
+        }
+        let name_len = page.get_ptr_from_offset(offset, COMPARATOR_NAME_LEN_SIZE)?[0] as usize;
+        offset += COMPARATOR_NAME_LEN_SIZE;
+        if offset + name_len > end {
+            return Err(Error::InvalidFileHeader);
+        }
+        let comparator = match name_len {
+            0 => BYTEWISE.to_string(),
+            _ => String::from_utf8(page.get_ptr_from_offset(offset, name_len)?.to_vec())
+                .map_err(|_| Error::UTF8Error)?,
+        };
+
+        Ok(Header {
+            version,
+            page_size,
+            b: page.get_value_from_offset(B_PARAMETER_OFFSET)?,
+            root: Offset(page.get_value_from_offset(ROOT_OFFSET)?),
+            free_list_head,
+            encrypted: page.get_ptr_from_offset(ENCRYPTED_OFFSET, 1)?[0].from_byte(),
+            catalog: page.get_ptr_from_offset(CATALOG_OFFSET, 1)?[0].from_byte(),
+            last_seq: page.get_value_from_offset(LAST_SEQUENCE_OFFSET)? as u64,
+            indexes,
+            comparator,
+            slot_seq,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::error::Error;
+    use crate::header::Header;
+    use crate::node_type::Offset;
+    use crate::page::Page;
+    use crate::page_layout::{MAGIC_NUMBER_OFFSET, MAGIC_NUMBER_SIZE, PAGE_SIZE, ROOT_OFFSET};
+    use std::convert::TryFrom;
+
+    #[test]
+    fn header_to_page_works() -> Result<(), Error> {
+        let mut header = Header::new(2, Offset(PAGE_SIZE * 2));
+        header.indexes = vec![
+            ("email".to_string(), Offset(PAGE_SIZE * 4)),
+            ("name".to_string(), Offset(PAGE_SIZE * 6)),
+        ];
+        header.catalog = true;
+        header.last_seq = 42;
+        header.comparator = "natural".to_string();
+        header.slot_seq = 3;
+        let res = Header::try_from(Page::try_from(&header)?)?;
+        assert_eq!(res, header);
+        Ok(())
+    }
+
+    #[test]
+    fn header_validation_works() -> Result<(), Error> {
+        let header = Header::new(2, Offset(PAGE_SIZE * 2));
+
+        let mut page = Page::try_from(&header)?;
+        page.write_bytes_at_offset(
+            &[0x00; MAGIC_NUMBER_SIZE],
+            MAGIC_NUMBER_OFFSET,
+            MAGIC_NUMBER_SIZE,
+        )?;
+        let res = Header::try_from(page);
+        assert!(matches!(res, Err(Error::InvalidFileHeader)));
+
+        let mut page_size = header.clone();
+        page_size.page_size = PAGE_SIZE * 2;
+        let res = Header::try_from(Page::try_from(&page_size)?);
+        assert!(matches!(res, Err(Error::PageSizeMismatch)));
+
+        // A header torn by a crash fails its checksum.
+        let mut page = Page::try_from(&header)?;
+        page.write_value_at_offset(ROOT_OFFSET, PAGE_SIZE * 4)?;
+        let res = Header::try_from(page);
+        assert!(matches!(res, Err(Error::InvalidFileHeader)));
+        Ok(())
+    }
+}
@@ -1,9 +1,27 @@
+pub mod backup;
 pub mod btree;
+pub mod catalog;
+pub mod change_log;
+mod check;
+pub mod comparator;
+pub mod compression;
 mod data_page;
+mod decoder;
+mod dirty;
+pub mod encryption;
 pub mod error;
+pub mod fault;
+#[cfg(any(test, feature = "fuzzing"))]
+pub mod fuzzing;
+pub mod group_commit;
+mod header;
 pub mod node;
 pub mod node_type;
+mod node_view;
 pub mod page;
 mod page_layout;
 mod pager;
+mod replication;
+pub mod stats;
+pub mod storage;
 mod wal;
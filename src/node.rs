@@ -1,41 +1,41 @@
 use byteorder::{BigEndian, ReadBytesExt};
 
-use crate::data_page::DataPage;
+use crate::comparator::Comparator;
 use crate::error::Error;
 use crate::node_type::{Key, KeyValuePair, NodeType, Offset};
 use crate::page::Page;
 use crate::page_layout::{
-    FromByte, INTERNAL_NODE_HEADER_SIZE, INTERNAL_NODE_NUM_CHILDREN_OFFSET, IS_ROOT_OFFSET,
-    KEY_SIZE, LEAF_NODE_DATA_PAGE_OFFSET, LEAF_NODE_DATA_PAGE_OFFSET_SIZE, LEAF_NODE_HEADER_SIZE,
-    NODE_TYPE_OFFSET, PARENT_POINTER_OFFSET, PTR_SIZE, VALUE_SIZE,
+    min_leaf_pair_size, parent_pointer_size, FromByte, EXPIRY_FORMAT_VERSION, EXPIRY_SIZE,
+    FORMAT_VERSION, INTERNAL_NODE_HEADER_SIZE, INTERNAL_NODE_NUM_CHILDREN_OFFSET, IS_ROOT_OFFSET,
+    KEY_LEN_SIZE, KEY_SIZE, LEAF_NODE_DATA_PAGE_OFFSET, LEAF_NODE_DATA_PAGE_OFFSET_SIZE,
+    LEAF_NODE_HEADER_SIZE, LEAF_PREFIX_COMPRESSION_FORMAT_VERSION, NODE_TYPE_OFFSET, PAGE_SIZE,
+    PREFIX_COMPRESSION_FORMAT_VERSION, PTR_SIZE, VALUE_SIZE,
 };
-use crate::pager::Pager;
+use std::cmp::Ordering;
 use std::convert::TryFrom;
-use std::mem::size_of;
 use std::str;
 
 /// Node represents a node in the BTree occupied by a single page in memory.
+/// Nodes do not point at their parent, every ancestor is copied on write so the parent of a node
+/// is only known along a root to leaf descent.
 #[derive(Clone, Debug)]
 pub struct Node {
     pub node_type: NodeType,
     pub is_root: bool,
-    pub parent_offset: Option<Offset>,
 }
 
 // Node represents a node in the B-Tree.
 impl Node {
-    pub fn new(node_type: NodeType, is_root: bool, parent_offset: Option<Offset>) -> Node {
-        Node {
-            node_type,
-            is_root,
-            parent_offset,
-        }
+    pub fn new(node_type: NodeType, is_root: bool) -> Node {
+        Node { node_type, is_root }
     }
 
     /// split creates a sibling node from a given node by splitting the node in two around a median.
     /// split will split the child at b leaving the [0, b-1] keys
     /// while moving the set of [b, 2b-1] keys to the sibling.
-    pub fn split(&mut self, b: usize, pager: &mut Pager) -> Result<(Key, Node), Error> {
+    /// The pairs of a split leaf keep referring to the values of its data page, which is shared by the two halves.
+    /// The keys are ordered by the given comparator.
+    pub fn split(&mut self, b: usize, comparator: &dyn Comparator) -> Result<(Key, Node), Error> {
         match &mut self.node_type {
             NodeType::Internal(ref mut children, ref mut keys) => {
                 // Populate siblings keys.
@@ -46,46 +46,21 @@ impl Node {
                 let sibling_children = children.split_off(b);
                 Ok((
                     median_key,
-                    Node::new(
-                        NodeType::Internal(sibling_children, sibling_keys),
-                        false,
-                        self.parent_offset.clone(),
-                    ),
+                    Node::new(NodeType::Internal(sibling_children, sibling_keys), false),
                 ))
             }
             NodeType::Leaf(offset, ref mut pairs) => {
                 // Populate siblings pairs.
-                let mut sibling_pairs = pairs.split_off(b);
-                // Pop median key.
+                let sibling_pairs = pairs.split_off(b);
+                // Promote the shortest key separating the two nodes rather than the median key.
                 let median_pair = pairs.get(b - 1).ok_or(Error::UnexpectedError)?.clone();
-                // get data page as node
-                let page = pager.get_page(&offset)?;
-                let mut data_page = DataPage::try_from(page)?;
-                // split data page and reset values for sibling
-                let (left, right) = data_page.split(b);
-                pager.write_page_at_offset(Page::try_from(&left)?, &offset)?;
-                let sibling_offset = pager.write_page(Page::try_from(&right)?)?;
-
-                // find minumum index
-                let mut min = usize::MAX;
-                for pair in sibling_pairs.iter() {
-                    if pair.idx <= min {
-                        min = pair.idx;
-                    }
-                }
-
-                // subtract minimum from indexes in sibling values
-                for pair in sibling_pairs.iter_mut() {
-                    pair.idx -= min;
-                }
+                let sibling_first_pair = sibling_pairs.first().ok_or(Error::UnexpectedError)?;
+                let separator =
+                    shortest_separator(&median_pair.key, &sibling_first_pair.key, comparator);
 
                 Ok((
-                    Key(median_pair.key),
-                    Node::new(
-                        NodeType::Leaf(sibling_offset, sibling_pairs),
-                        false,
-                        self.parent_offset.clone(),
-                    ),
+                    Key(separator),
+                    Node::new(NodeType::Leaf(offset.clone(), sibling_pairs), false),
                 ))
             }
             NodeType::Unexpected => Err(Error::UnexpectedError),
@@ -93,25 +68,92 @@ impl Node {
     }
 }
 
+/// shortest_separator returns the shortest key larger than or equal to left and smaller than right,
+/// which is the shortest prefix of right that is larger than left.
+/// Falls back to left if no such prefix exists, which is bound to happen for some orderings.
+fn shortest_separator(left: &str, right: &str, comparator: &dyn Comparator) -> String {
+    right
+        .char_indices()
+        .skip(1)
+        .map(|(idx, _)| &right[..idx])
+        .find(|prefix| {
+            comparator.compare(prefix, left) == Ordering::Greater
+                && comparator.compare(prefix, right) == Ordering::Less
+        })
+        .unwrap_or(left)
+        .to_string()
+}
+
+/// read_fixed_keys reads num_keys keys each occupying KEY_SIZE bytes padded with zeros,
+/// this is how internal nodes stored their keys before format version 2.
+fn read_fixed_keys(page: &Page, mut offset: usize, num_keys: usize) -> Result<Vec<Key>, Error> {
+    let mut keys = Vec::with_capacity(num_keys);
+    for _i in 0..num_keys {
+        let key_raw = page.get_ptr_from_offset(offset, KEY_SIZE)?;
+        let key = match str::from_utf8(key_raw) {
+            Ok(key) => key,
+            Err(_) => return Err(Error::UTF8Error),
+        };
+        offset += KEY_SIZE;
+        // Trim leading or trailing zeros.
+        keys.push(Key(key.trim_matches(char::from(0)).to_string()));
+    }
+    Ok(keys)
+}
+
+/// read_key_part reads a prefix or the rest of a prefix compressed key stored as a length byte followed by its bytes.
+fn read_key_part<'a>(page: &'a Page, offset: &mut usize) -> Result<&'a [u8], Error> {
+    let len = page.get_ptr_from_offset(*offset, KEY_LEN_SIZE)?[0] as usize;
+    *offset += KEY_LEN_SIZE;
+    let part = page.get_ptr_from_offset(*offset, len)?;
+    *offset += len;
+    Ok(part)
+}
+
+/// read_compressed_keys reads num_keys keys stored as suffixes of a common prefix.
+fn read_compressed_keys(
+    page: &Page,
+    mut offset: usize,
+    num_keys: usize,
+) -> Result<Vec<Key>, Error> {
+    let prefix = read_key_part(page, &mut offset)?.to_vec();
+
+    let mut keys = Vec::with_capacity(num_keys);
+    for _i in 0..num_keys {
+        let mut key_raw = prefix.clone();
+        key_raw.extend_from_slice(read_key_part(page, &mut offset)?);
+        let key = String::from_utf8(key_raw).map_err(|_| Error::UTF8Error)?;
+        keys.push(Key(key));
+    }
+    Ok(keys)
+}
+
 /// Implement TryFrom<Page> for Node allowing for easier
-/// deserialization of data from a Page.
+/// deserialization of data from a Page written using the newest layout.
 impl TryFrom<Page> for Node {
     type Error = Error;
     fn try_from(page: Page) -> Result<Node, Error> {
+        Node::decode(page, FORMAT_VERSION)
+    }
+}
+
+impl Node {
+    /// decode deserializes a page written using the given format version.
+    pub fn decode(page: Page, version: usize) -> Result<Node, Error> {
         let raw = page.get_data();
         let node_type = NodeType::from(raw[NODE_TYPE_OFFSET]);
         let is_root = raw[IS_ROOT_OFFSET].from_byte();
-        let parent_offset: Option<Offset>;
-        if is_root {
-            parent_offset = None;
-        } else {
-            parent_offset = Some(Offset(page.get_value_from_offset(PARENT_POINTER_OFFSET)?));
-        }
+        // The parent pointer of older nodes is skipped, it went stale as soon as the parent was copied.
+        let shift = parent_pointer_size(version);
 
         match node_type {
-            NodeType::Internal(mut children, mut keys) => {
-                let num_children = page.get_value_from_offset(INTERNAL_NODE_NUM_CHILDREN_OFFSET)?;
-                let mut offset = INTERNAL_NODE_HEADER_SIZE;
+            NodeType::Internal(mut children, _) => {
+                let num_children =
+                    page.get_value_from_offset(INTERNAL_NODE_NUM_CHILDREN_OFFSET + shift)?;
+                if num_children > (PAGE_SIZE - INTERNAL_NODE_HEADER_SIZE - shift) / PTR_SIZE {
+                    return Err(Error::CorruptedPage);
+                }
+                let mut offset = INTERNAL_NODE_HEADER_SIZE + shift;
                 for _i in 1..=num_children {
                     let child_offset = page.get_value_from_offset(offset)?;
                     children.push(Offset(child_offset));
@@ -119,56 +161,75 @@ impl TryFrom<Page> for Node {
                 }
 
                 // Number of keys is always one less than the number of children (i.e. branching factor)
-                for _i in 1..num_children {
-                    let key_raw = page.get_ptr_from_offset(offset, KEY_SIZE);
-                    let key = match str::from_utf8(key_raw) {
-                        Ok(key) => key,
-                        Err(_) => return Err(Error::UTF8Error),
-                    };
-                    offset += KEY_SIZE;
-                    // Trim leading or trailing zeros.
-                    keys.push(Key(key.trim_matches(char::from(0)).to_string()));
-                }
-                Ok(Node::new(
-                    NodeType::Internal(children, keys),
-                    is_root,
-                    parent_offset,
-                ))
+                let num_keys = num_children.saturating_sub(1);
+                let keys = if version < PREFIX_COMPRESSION_FORMAT_VERSION {
+                    read_fixed_keys(&page, offset, num_keys)?
+                } else {
+                    read_compressed_keys(&page, offset, num_keys)?
+                };
+                Ok(Node::new(NodeType::Internal(children, keys), is_root))
             }
 
             NodeType::Leaf(_, mut pairs) => {
                 // data page offset
-                let mut offset = LEAF_NODE_DATA_PAGE_OFFSET;
+                let mut offset = LEAF_NODE_DATA_PAGE_OFFSET + shift;
                 let data_offset = Offset(page.get_value_from_offset(offset)?);
 
                 offset += LEAF_NODE_DATA_PAGE_OFFSET_SIZE;
                 // key value pairs
                 let num_keys_val_pairs = page.get_value_from_offset(offset)?;
-                offset = LEAF_NODE_HEADER_SIZE;
+                if num_keys_val_pairs
+                    > (PAGE_SIZE - LEAF_NODE_HEADER_SIZE - shift) / min_leaf_pair_size(version)
+                {
+                    return Err(Error::CorruptedPage);
+                }
+                offset = LEAF_NODE_HEADER_SIZE + shift;
+
+                // Leaf keys were padded with zeros to KEY_SIZE bytes up until format version 8.
+                let prefix = if version < LEAF_PREFIX_COMPRESSION_FORMAT_VERSION {
+                    None
+                } else {
+                    Some(read_key_part(&page, &mut offset)?.to_vec())
+                };
 
                 for _i in 0..num_keys_val_pairs {
-                    let key_raw = page.get_ptr_from_offset(offset, KEY_SIZE);
-                    let key = match str::from_utf8(key_raw) {
-                        Ok(key) => key,
-                        Err(_) => return Err(Error::UTF8Error),
+                    let key = match &prefix {
+                        None => {
+                            let key_raw = page.get_ptr_from_offset(offset, KEY_SIZE)?;
+                            offset += KEY_SIZE;
+                            let key = str::from_utf8(key_raw).map_err(|_| Error::UTF8Error)?;
+                            // Trim leading or trailing zeros.
+                            key.trim_matches(char::from(0)).to_string()
+                        }
+                        Some(prefix) => {
+                            let mut key_raw = prefix.clone();
+                            key_raw.extend_from_slice(read_key_part(&page, &mut offset)?);
+                            String::from_utf8(key_raw).map_err(|_| Error::UTF8Error)?
+                        }
                     };
-                    offset += KEY_SIZE;
 
-                    let mut value_offset_raw = page.get_ptr_from_offset(offset, size_of::<usize>());
-                    let value_offset = value_offset_raw.read_u64::<BigEndian>()? as usize;
+                    let mut value_offset_raw = page.get_ptr_from_offset(offset, VALUE_SIZE)?;
+                    let value_offset = usize::try_from(value_offset_raw.read_u64::<BigEndian>()?)
+                        .map_err(|_| Error::IntegerOverflowError)?;
                     offset += VALUE_SIZE;
 
-                    // Trim leading or trailing zeros.
-                    pairs.push(KeyValuePair::new(
-                        key.trim_matches(char::from(0)).to_string(),
-                        value_offset,
-                    ))
+                    // Pairs never expired up until format version 4.
+                    let expiry = if version < EXPIRY_FORMAT_VERSION {
+                        None
+                    } else {
+                        let mut expiry_raw = page.get_ptr_from_offset(offset, EXPIRY_SIZE)?;
+                        offset += EXPIRY_SIZE;
+                        match expiry_raw.read_u64::<BigEndian>()? {
+                            0 => None,
+                            expiry => Some(expiry),
+                        }
+                    };
+
+                    let mut pair = KeyValuePair::new(key, value_offset);
+                    pair.expiry = expiry;
+                    pairs.push(pair)
                 }
-                Ok(Node::new(
-                    NodeType::Leaf(data_offset, pairs),
-                    is_root,
-                    parent_offset,
-                ))
+                Ok(Node::new(NodeType::Leaf(data_offset, pairs), is_root))
             }
 
             NodeType::Unexpected => Err(Error::UnexpectedError),
@@ -181,10 +242,9 @@ impl TryFrom<Page> for Node {
 ///  Unit Tests. ///
 ///              ///
 ////////////////////
-
 #[cfg(test)]
 mod tests {
-    use crate::data_page::DataPage;
+    use crate::comparator::{Bytewise, Natural};
     use crate::error::Error;
     use crate::node::{
         Node, Page, INTERNAL_NODE_HEADER_SIZE, KEY_SIZE, LEAF_NODE_HEADER_SIZE, PTR_SIZE,
@@ -192,13 +252,11 @@ mod tests {
     };
     use crate::node_type::{Key, NodeType, Offset};
     use crate::page_layout::PAGE_SIZE;
-    use crate::pager::Pager;
-    use std::convert::TryFrom;
-    use std::path::Path;
+    use crate::page_layout::PARENT_POINTER_SIZE;
 
     #[test]
     fn page_to_node_works_for_leaf_node() -> Result<(), Error> {
-        const DATA_LEN: usize = LEAF_NODE_HEADER_SIZE + KEY_SIZE + VALUE_SIZE;
+        const DATA_LEN: usize = LEAF_NODE_HEADER_SIZE + PARENT_POINTER_SIZE + KEY_SIZE + VALUE_SIZE;
         let page_data: [u8; DATA_LEN] = [
             0x01, // Is-Root byte.
             0x02, // Leaf Node type byte.
@@ -216,16 +274,18 @@ mod tests {
             *to = *from
         }
 
-        let node = Node::try_from(Page::new(page))?;
+        // Leaf pairs were stored without an expiry up until format version 4.
+        let node = Node::decode(Page::new(page), 3)?;
 
-        assert_eq!(node.is_root, true);
+        assert!(node.is_root);
         Ok(())
     }
 
     #[test]
     fn page_to_node_works_for_internal_node() -> Result<(), Error> {
         use crate::node_type::Key;
-        const DATA_LEN: usize = INTERNAL_NODE_HEADER_SIZE + 3 * PTR_SIZE + 2 * KEY_SIZE;
+        const DATA_LEN: usize =
+            INTERNAL_NODE_HEADER_SIZE + PARENT_POINTER_SIZE + 3 * PTR_SIZE + 2 * KEY_SIZE;
         let page_data: [u8; DATA_LEN] = [
             0x01, // Is-Root byte.
             0x01, // Internal Node type byte.
@@ -249,12 +309,13 @@ mod tests {
             *to = *from
         }
 
-        let node = Node::try_from(Page::new(page))?;
+        // Internal nodes stored fixed size keys up until format version 2.
+        let node = Node::decode(Page::new(page), 1)?;
 
         if let NodeType::Internal(_, keys) = node.node_type {
             assert_eq!(keys.len(), 2);
 
-            let Key(first_key) = match keys.get(0) {
+            let Key(first_key) = match keys.first() {
                 Some(key) => key,
                 None => return Err(Error::UnexpectedError),
             };
@@ -271,20 +332,77 @@ mod tests {
         Err(Error::UnexpectedError)
     }
 
+    #[test]
+    fn page_to_node_works_for_compressed_internal_node() -> Result<(), Error> {
+        const DATA_LEN: usize = INTERNAL_NODE_HEADER_SIZE + PARENT_POINTER_SIZE + 3 * PTR_SIZE + 14;
+        let page_data: [u8; DATA_LEN] = [
+            0x01, // Is-Root byte.
+            0x01, // Internal Node type byte.
+            0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, // Parent offset.
+            0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x03, // Number of children.
+            0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x10, 0x00, // 4096  (2nd Page)
+            0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x20, 0x00, // 8192  (3rd Page)
+            0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x30, 0x00, // 12288 (4th Page)
+            0x07, 0x74, 0x65, 0x6e, 0x61, 0x6e, 0x74, 0x2f, // "tenant/" prefix
+            0x02, 0x61, 0x62, // "ab"
+            0x02, 0x63, 0x64, // "cd"
+        ];
+        let mut page = [0x00; PAGE_SIZE];
+        page[..DATA_LEN].clone_from_slice(&page_data);
+
+        // Nodes were stored with a parent pointer up until format version 6.
+        let node = Node::decode(Page::new(page), 5)?;
+        assert_eq!(
+            node.node_type,
+            NodeType::Internal(
+                vec![Offset(4096), Offset(8192), Offset(12288)],
+                vec![Key("tenant/ab".to_string()), Key("tenant/cd".to_string())]
+            )
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn page_to_node_works_for_compressed_leaf_node() -> Result<(), Error> {
+        use crate::node_type::KeyValuePair;
+        const DATA_LEN: usize = LEAF_NODE_HEADER_SIZE + 8 + 2 * (3 + 2 * PTR_SIZE);
+        let page_data: [u8; DATA_LEN] = [
+            0x00, // Is-Root byte.
+            0x02, // Leaf Node type byte.
+            0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x20, 0x00, // DataPage offset.
+            0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x02, // Number of Key-Value pairs.
+            0x07, 0x74, 0x65, 0x6e, 0x61, 0x6e, 0x74, 0x2f, // "tenant/" prefix
+            0x02, 0x61, 0x62, // "ab"
+            0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, // Value index.
+            0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, // Never expires.
+            0x02, 0x63, 0x64, // "cd"
+            0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x01, // Value index.
+            0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x2a, // Expiry.
+        ];
+        let mut page = [0x00; PAGE_SIZE];
+        page[..DATA_LEN].clone_from_slice(&page_data);
+
+        // Leaf keys are prefix compressed since format version 8.
+        let node = Node::decode(Page::new(page), 8)?;
+        let mut expiring = KeyValuePair::new("tenant/cd".to_string(), 1);
+        expiring.expiry = Some(42);
+        assert_eq!(
+            node.node_type,
+            NodeType::Leaf(
+                Offset(2 * PAGE_SIZE),
+                vec![KeyValuePair::new("tenant/ab".to_string(), 0), expiring]
+            )
+        );
+        Ok(())
+    }
+
     #[test]
     fn split_leaf_works() -> Result<(), Error> {
         use crate::node::Node;
         use crate::node_type::KeyValuePair;
-        let mut pager = Pager::new(&Path::new("/tmp/pager"))?;
-        let mut data_page = DataPage::new();
-        data_page.insert("bar".to_string());
-        data_page.insert("foo".to_string());
-        data_page.insert("zap".to_string());
-        pager.write_page(Page::try_from(&data_page)?)?;
-
         let mut node = Node::new(
             NodeType::Leaf(
-                Offset(0),
+                Offset(PAGE_SIZE),
                 vec![
                     KeyValuePair::new("foo".to_string(), 0),
                     KeyValuePair::new("lebron".to_string(), 1),
@@ -292,41 +410,40 @@ mod tests {
                 ],
             ),
             true,
-            None,
         );
-        let offset = pager.write_page(Page::try_from(&node)?)?;
-        assert_eq!(offset, Offset(4096));
 
-        let (median, sibling) = node.split(2, &mut pager)?;
+        let (median, sibling) = node.split(2, &Bytewise)?;
         assert_eq!(median, Key("lebron".to_string()));
+        // Both halves keep referring to the values of the original data page.
         assert_eq!(
             node.node_type,
             NodeType::Leaf(
-                Offset(0),
+                Offset(PAGE_SIZE),
                 vec![
                     KeyValuePair {
                         key: "foo".to_string(),
-                        idx: 0
+                        idx: 0,
+                        expiry: None
                     },
                     KeyValuePair {
                         key: "lebron".to_string(),
-                        idx: 1
+                        idx: 1,
+                        expiry: None
                     }
                 ]
             )
         );
 
-        let sibling_key_values = match sibling.node_type {
-            NodeType::Leaf(_, key_values) => key_values,
-            _ => panic!("expected leaf node"),
-        };
-
         assert_eq!(
-            sibling_key_values,
-            vec![KeyValuePair {
-                key: "ariana".to_string(),
-                idx: 0
-            }]
+            sibling.node_type,
+            NodeType::Leaf(
+                Offset(PAGE_SIZE),
+                vec![KeyValuePair {
+                    key: "ariana".to_string(),
+                    idx: 2,
+                    expiry: None
+                }]
+            )
         );
         Ok(())
     }
@@ -337,7 +454,6 @@ mod tests {
         use crate::node_type::NodeType;
         use crate::node_type::{Key, Offset};
         use crate::page_layout::PAGE_SIZE;
-        let mut pager = Pager::new(&Path::new("/tmp/pager")).unwrap();
         let mut node = Node::new(
             NodeType::Internal(
                 vec![
@@ -353,10 +469,9 @@ mod tests {
                 ],
             ),
             true,
-            None,
         );
 
-        let (median, sibling) = node.split(2, &mut pager)?;
+        let (median, sibling) = node.split(2, &Bytewise)?;
         assert_eq!(median, Key("lebron".to_string()));
         assert_eq!(
             node.node_type,
@@ -374,4 +489,40 @@ mod tests {
         );
         Ok(())
     }
+
+    #[test]
+    fn split_leaf_promotes_shortest_separator() -> Result<(), Error> {
+        use crate::node::Node;
+        use crate::node_type::KeyValuePair;
+        let mut node = Node::new(
+            NodeType::Leaf(
+                Offset(PAGE_SIZE),
+                vec![
+                    KeyValuePair::new("tenant/a/user1".to_string(), 0),
+                    KeyValuePair::new("tenant/a/user2".to_string(), 1),
+                    KeyValuePair::new("tenant/b/user1".to_string(), 2),
+                ],
+            ),
+            true,
+        );
+
+        let (median, _) = node.split(2, &Bytewise)?;
+        assert_eq!(median, Key("tenant/b".to_string()));
+
+        // v2 separates v10 from v20 byte-wise but precedes v10 in natural order, the median key is promoted instead.
+        let mut node = Node::new(
+            NodeType::Leaf(
+                Offset(PAGE_SIZE),
+                vec![
+                    KeyValuePair::new("v1".to_string(), 0),
+                    KeyValuePair::new("v10".to_string(), 1),
+                    KeyValuePair::new("v20".to_string(), 2),
+                ],
+            ),
+            true,
+        );
+        let (median, _) = node.split(2, &Natural)?;
+        assert_eq!(median, Key("v10".to_string()));
+        Ok(())
+    }
 }
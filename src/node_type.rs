@@ -9,16 +9,19 @@ pub struct Offset(pub usize);
 
 impl Offset {
     pub fn as_bytes(&self) -> Vec<u8> {
-        self.0.to_be_bytes().to_vec()
+        (self.0 as u64).to_be_bytes().to_vec()
     }
 }
 
-/// Converts an array of length len(usize) to a usize as a BigEndian integer.
+/// Converts an array of length len(u64) to a usize as a BigEndian integer,
+/// this fails if the offset is too large for the host's pointer width.
 impl TryFrom<[u8; PTR_SIZE]> for Offset {
     type Error = Error;
 
     fn try_from(arr: [u8; PTR_SIZE]) -> Result<Self, Self::Error> {
-        Ok(Offset(usize::from_be_bytes(arr)))
+        usize::try_from(u64::from_be_bytes(arr))
+            .map(Offset)
+            .map_err(|_| Error::IntegerOverflowError)
     }
 }
 
@@ -29,6 +32,8 @@ pub struct Key(pub String);
 pub struct KeyValuePair {
     pub key: String,
     pub idx: usize,
+    /// The time the pair expires at in milliseconds since the unix epoch, None if it never expires.
+    pub expiry: Option<u64>,
 }
 
 impl Ord for KeyValuePair {
@@ -45,13 +50,22 @@ impl PartialOrd for KeyValuePair {
 
 impl PartialEq for KeyValuePair {
     fn eq(&self, other: &Self) -> bool {
-        self.key == other.key && self.idx == other.idx
+        self.key == other.key && self.idx == other.idx && self.expiry == other.expiry
     }
 }
 
 impl KeyValuePair {
     pub fn new(key: String, idx: usize) -> KeyValuePair {
-        KeyValuePair { key, idx }
+        KeyValuePair {
+            key,
+            idx,
+            expiry: None,
+        }
+    }
+
+    /// is_expired returns true if the pair expires at or before now, given in milliseconds since the unix epoch.
+    pub fn is_expired(&self, now: u64) -> bool {
+        matches!(self.expiry, Some(expiry) if expiry <= now)
     }
 }
 
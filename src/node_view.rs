@@ -0,0 +1,420 @@
+use crate::comparator::Comparator;
+use crate::error::Error;
+use crate::node_type::{NodeType, Offset};
+use crate::page::Page;
+use crate::page_layout::{
+    min_leaf_pair_size, parent_pointer_size, EXPIRY_FORMAT_VERSION, EXPIRY_SIZE,
+    INTERNAL_NODE_HEADER_SIZE, INTERNAL_NODE_NUM_CHILDREN_OFFSET, KEY_LEN_SIZE, KEY_SIZE,
+    LEAF_NODE_DATA_PAGE_OFFSET, LEAF_NODE_HEADER_SIZE, LEAF_NODE_NUM_PAIRS_OFFSET,
+    LEAF_PREFIX_COMPRESSION_FORMAT_VERSION, NODE_TYPE_OFFSET, PAGE_SIZE,
+    PREFIX_COMPRESSION_FORMAT_VERSION, PTR_SIZE, VALUE_SIZE,
+};
+use std::cmp::Ordering;
+
+/// NodeView is a borrowed view of a node page, it searches the page in place
+/// rather than deserializing every key and child the way Node::decode does.
+/// Searches only ever need a view, nodes are materialized by the write path alone.
+pub struct NodeView<'a> {
+    page: &'a Page,
+    version: usize,
+    /// The size of the parent pointer of nodes written using older versions, see parent_pointer_size.
+    shift: usize,
+    kind: Kind,
+}
+
+enum Kind {
+    Internal { num_children: usize },
+    Leaf { num_pairs: usize },
+}
+
+/// Lookup is the outcome of searching a single node for a key.
+#[derive(Debug, PartialEq, Eq)]
+pub enum Lookup {
+    /// The key may only be found in the sub tree rooted at the given child.
+    Child(Offset),
+    /// The key is stored in the leaf, its value is at the given index of the given data page.
+    Pair {
+        data_page: Offset,
+        idx: usize,
+        expiry: Option<u64>,
+    },
+    /// The key is not stored in the leaf.
+    NotFound,
+}
+
+/// trim_zeros trims the zeros padding a fixed size key, see Node::decode.
+fn trim_zeros(key: &[u8]) -> &[u8] {
+    let start = key.iter().position(|byte| *byte != 0).unwrap_or(key.len());
+    let end = key
+        .iter()
+        .rposition(|byte| *byte != 0)
+        .map_or(start, |end| end + 1);
+    &key[start..end]
+}
+
+/// cmp_prefixed compares the key made of the given prefix followed by the given suffix to the search key,
+/// without concatenating the two.
+fn cmp_prefixed(prefix: &[u8], suffix: &[u8], search: &[u8]) -> Ordering {
+    let split = std::cmp::min(prefix.len(), search.len());
+    match prefix.cmp(&search[..split]) {
+        Ordering::Equal if split == prefix.len() => suffix.cmp(&search[split..]),
+        // The search key is a proper prefix of the prefix.
+        Ordering::Equal => Ordering::Greater,
+        order => order,
+    }
+}
+
+/// compare compares a key read from a page to the search key, keys are only decoded for orderings other than
+/// the byte-wise one.
+fn compare(key: &[u8], search: &str, comparator: &dyn Comparator) -> Result<Ordering, Error> {
+    if comparator.is_bytewise() {
+        return Ok(key.cmp(search.as_bytes()));
+    }
+    let key = std::str::from_utf8(key).map_err(|_| Error::UTF8Error)?;
+    Ok(comparator.compare(key, search))
+}
+
+impl<'a> NodeView<'a> {
+    /// new views a node page written using the given format version,
+    /// the counts of the page are validated so searches never read past the page.
+    pub fn new(page: &'a Page, version: usize) -> Result<NodeView<'a>, Error> {
+        let shift = parent_pointer_size(version);
+        let kind = match NodeType::from(page.get_ptr_from_offset(NODE_TYPE_OFFSET, 1)?[0]) {
+            NodeType::Internal(_, _) => {
+                let num_children =
+                    page.get_value_from_offset(INTERNAL_NODE_NUM_CHILDREN_OFFSET + shift)?;
+                if num_children > (PAGE_SIZE - INTERNAL_NODE_HEADER_SIZE - shift) / PTR_SIZE {
+                    return Err(Error::CorruptedPage);
+                }
+                Kind::Internal { num_children }
+            }
+            NodeType::Leaf(_, _) => {
+                let num_pairs = page.get_value_from_offset(LEAF_NODE_NUM_PAIRS_OFFSET + shift)?;
+                if num_pairs
+                    > (PAGE_SIZE - LEAF_NODE_HEADER_SIZE - shift) / min_leaf_pair_size(version)
+                {
+                    return Err(Error::CorruptedPage);
+                }
+                Kind::Leaf { num_pairs }
+            }
+            NodeType::Unexpected => return Err(Error::UnexpectedError),
+        };
+        Ok(NodeView {
+            page,
+            version,
+            shift,
+            kind,
+        })
+    }
+
+    /// search returns the child to descend to or the pair of the given key.
+    /// Like the search of a materialized node, a key equal to a separator is searched for in the left child.
+    /// The keys of the page are ordered by the given comparator.
+    pub fn search(&self, key: &str, comparator: &dyn Comparator) -> Result<Lookup, Error> {
+        match self.kind {
+            Kind::Internal { num_children } => {
+                let num_keys = num_children.saturating_sub(1);
+                let idx = if self.version < PREFIX_COMPRESSION_FORMAT_VERSION {
+                    self.partition_fixed_keys(num_keys, key, comparator)?
+                } else {
+                    self.partition_compressed_keys(num_keys, key, comparator)?
+                };
+                if idx >= num_children {
+                    return Err(Error::UnexpectedError);
+                }
+                let child = self.page.get_value_from_offset(
+                    INTERNAL_NODE_HEADER_SIZE + self.shift + idx * PTR_SIZE,
+                )?;
+                Ok(Lookup::Child(Offset(child)))
+            }
+            Kind::Leaf { num_pairs } => {
+                if self.version < LEAF_PREFIX_COMPRESSION_FORMAT_VERSION {
+                    self.search_fixed_pairs(num_pairs, key, comparator)
+                } else {
+                    self.search_compressed_pairs(num_pairs, key, comparator)
+                }
+            }
+        }
+    }
+
+    /// partition_fixed_keys binary searches keys each occupying KEY_SIZE bytes, as written before format version 2,
+    /// returning the number of keys smaller than the search key.
+    fn partition_fixed_keys(
+        &self,
+        num_keys: usize,
+        search: &str,
+        comparator: &dyn Comparator,
+    ) -> Result<usize, Error> {
+        let keys_offset = INTERNAL_NODE_HEADER_SIZE + self.shift + (num_keys + 1) * PTR_SIZE;
+        let (mut low, mut high) = (0, num_keys);
+        while low < high {
+            let mid = low + (high - low) / 2;
+            let key = self
+                .page
+                .get_ptr_from_offset(keys_offset + mid * KEY_SIZE, KEY_SIZE)?;
+            if compare(trim_zeros(key), search, comparator)? == Ordering::Less {
+                low = mid + 1;
+            } else {
+                high = mid;
+            }
+        }
+        Ok(low)
+    }
+
+    /// partition_compressed_keys returns the number of prefix compressed keys smaller than the search key.
+    /// The keys vary in length so they are scanned in order, which stops at the first key not smaller than the search key.
+    fn partition_compressed_keys(
+        &self,
+        num_keys: usize,
+        search: &str,
+        comparator: &dyn Comparator,
+    ) -> Result<usize, Error> {
+        let mut offset = INTERNAL_NODE_HEADER_SIZE + self.shift + (num_keys + 1) * PTR_SIZE;
+        let prefix_len = self.page.get_ptr_from_offset(offset, KEY_LEN_SIZE)?[0] as usize;
+        offset += KEY_LEN_SIZE;
+        let prefix = self.page.get_ptr_from_offset(offset, prefix_len)?;
+        offset += prefix_len;
+        let mut key = Vec::new();
+        for idx in 0..num_keys {
+            let suffix_len = self.page.get_ptr_from_offset(offset, KEY_LEN_SIZE)?[0] as usize;
+            offset += KEY_LEN_SIZE;
+            let suffix = self.page.get_ptr_from_offset(offset, suffix_len)?;
+            offset += suffix_len;
+            let order = if comparator.is_bytewise() {
+                cmp_prefixed(prefix, suffix, search.as_bytes())
+            } else {
+                key.clear();
+                key.extend_from_slice(prefix);
+                key.extend_from_slice(suffix);
+                compare(&key, search, comparator)?
+            };
+            if order != Ordering::Less {
+                return Ok(idx);
+            }
+        }
+        Ok(num_keys)
+    }
+
+    /// search_fixed_pairs binary searches the fixed size pairs of a leaf, as written before format version 8.
+    fn search_fixed_pairs(
+        &self,
+        num_pairs: usize,
+        search: &str,
+        comparator: &dyn Comparator,
+    ) -> Result<Lookup, Error> {
+        let pair_size = min_leaf_pair_size(self.version);
+        let (mut low, mut high) = (0, num_pairs);
+        while low < high {
+            let mid = low + (high - low) / 2;
+            let offset = LEAF_NODE_HEADER_SIZE + self.shift + mid * pair_size;
+            let key = self.page.get_ptr_from_offset(offset, KEY_SIZE)?;
+            match compare(trim_zeros(key), search, comparator)? {
+                Ordering::Less => low = mid + 1,
+                Ordering::Greater => high = mid,
+                Ordering::Equal => return self.pair_at(offset + KEY_SIZE),
+            }
+        }
+        Ok(Lookup::NotFound)
+    }
+
+    /// search_compressed_pairs searches the pairs of a leaf whose keys are prefix compressed.
+    /// Like the keys of internal nodes they vary in length so they are scanned in order.
+    fn search_compressed_pairs(
+        &self,
+        num_pairs: usize,
+        search: &str,
+        comparator: &dyn Comparator,
+    ) -> Result<Lookup, Error> {
+        let mut offset = LEAF_NODE_HEADER_SIZE + self.shift;
+        let prefix_len = self.page.get_ptr_from_offset(offset, KEY_LEN_SIZE)?[0] as usize;
+        offset += KEY_LEN_SIZE;
+        let prefix = self.page.get_ptr_from_offset(offset, prefix_len)?;
+        offset += prefix_len;
+        let mut key = Vec::new();
+        for _i in 0..num_pairs {
+            let suffix_len = self.page.get_ptr_from_offset(offset, KEY_LEN_SIZE)?[0] as usize;
+            offset += KEY_LEN_SIZE;
+            let suffix = self.page.get_ptr_from_offset(offset, suffix_len)?;
+            offset += suffix_len;
+            let order = if comparator.is_bytewise() {
+                cmp_prefixed(prefix, suffix, search.as_bytes())
+            } else {
+                key.clear();
+                key.extend_from_slice(prefix);
+                key.extend_from_slice(suffix);
+                compare(&key, search, comparator)?
+            };
+            match order {
+                Ordering::Less => offset += VALUE_SIZE + EXPIRY_SIZE,
+                Ordering::Greater => break,
+                Ordering::Equal => return self.pair_at(offset),
+            }
+        }
+        Ok(Lookup::NotFound)
+    }
+
+    /// pair_at returns the pair whose value index is found at the given offset of a leaf.
+    fn pair_at(&self, offset: usize) -> Result<Lookup, Error> {
+        let idx = self.page.get_value_from_offset(offset)?;
+        // Pairs never expired up until format version 4.
+        let expiry = if self.version < EXPIRY_FORMAT_VERSION {
+            None
+        } else {
+            let mut expiry = [0x00; EXPIRY_SIZE];
+            expiry.clone_from_slice(
+                self.page
+                    .get_ptr_from_offset(offset + VALUE_SIZE, EXPIRY_SIZE)?,
+            );
+            match u64::from_be_bytes(expiry) {
+                0 => None,
+                expiry => Some(expiry),
+            }
+        };
+        Ok(Lookup::Pair {
+            data_page: Offset(
+                self.page
+                    .get_value_from_offset(LEAF_NODE_DATA_PAGE_OFFSET + self.shift)?,
+            ),
+            idx,
+            expiry,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::comparator::{Bytewise, Natural};
+    use crate::error::Error;
+    use crate::node::Node;
+    use crate::node_type::{Key, KeyValuePair, NodeType, Offset};
+    use crate::node_view::{Lookup, NodeView};
+    use crate::page::Page;
+    use crate::page_layout::{
+        FORMAT_VERSION, INTERNAL_NODE_HEADER_SIZE, KEY_SIZE, PAGE_SIZE, PARENT_POINTER_SIZE,
+        PTR_SIZE,
+    };
+    use std::convert::TryFrom;
+
+    #[test]
+    fn view_search_matches_node() -> Result<(), Error> {
+        let keys = ["tenant/b", "tenant/d", "tenant/f"];
+        let internal = Node::new(
+            NodeType::Internal(
+                (1..=4).map(|idx| Offset(idx * PAGE_SIZE)).collect(),
+                keys.iter().map(|key| Key(key.to_string())).collect(),
+            ),
+            true,
+        );
+        let pairs: Vec<KeyValuePair> = keys
+            .iter()
+            .enumerate()
+            .map(|(idx, key)| {
+                let mut pair = KeyValuePair::new(key.to_string(), idx);
+                pair.expiry = if idx == 1 { Some(42) } else { None };
+                pair
+            })
+            .collect();
+        let leaf = Node::new(NodeType::Leaf(Offset(PAGE_SIZE), pairs.clone()), true);
+
+        let internal_page = Page::try_from(&internal)?;
+        let leaf_page = Page::try_from(&leaf)?;
+        let internal_view = NodeView::new(&internal_page, FORMAT_VERSION)?;
+        let leaf_view = NodeView::new(&leaf_page, FORMAT_VERSION)?;
+        for search in [
+            "", "t", "tenant", "tenant/a", "tenant/b", "tenant/c", "tenant/d", "tenant/f", "u",
+        ] {
+            let idx = keys.binary_search(&search).unwrap_or_else(|idx| idx);
+            assert_eq!(
+                internal_view.search(search, &Bytewise)?,
+                Lookup::Child(Offset((idx + 1) * PAGE_SIZE))
+            );
+            let expected = match pairs.binary_search_by(|pair| pair.key.as_str().cmp(search)) {
+                Ok(idx) => Lookup::Pair {
+                    data_page: Offset(PAGE_SIZE),
+                    idx,
+                    expiry: pairs[idx].expiry,
+                },
+                Err(_) => Lookup::NotFound,
+            };
+            assert_eq!(leaf_view.search(search, &Bytewise)?, expected);
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn view_search_works_for_fixed_keys() -> Result<(), Error> {
+        // Internal nodes stored fixed size keys up until format version 2, following a parent pointer.
+        let header_size = INTERNAL_NODE_HEADER_SIZE + PARENT_POINTER_SIZE;
+        let mut raw = [0x00; PAGE_SIZE];
+        raw[0] = 0x01;
+        raw[1] = 0x01;
+        raw[header_size - 1] = 0x03;
+        for idx in 0..3 {
+            let offset = header_size + idx * PTR_SIZE;
+            raw[offset..offset + PTR_SIZE].clone_from_slice(&((idx + 1) * PAGE_SIZE).to_be_bytes());
+        }
+        let keys_offset = header_size + 3 * PTR_SIZE;
+        raw[keys_offset..keys_offset + 5].clone_from_slice(b"hello");
+        raw[keys_offset + KEY_SIZE..keys_offset + KEY_SIZE + 5].clone_from_slice(b"world");
+        let page = Page::new(raw);
+
+        let view = NodeView::new(&page, 1)?;
+        assert_eq!(
+            view.search("apple", &Bytewise)?,
+            Lookup::Child(Offset(PAGE_SIZE))
+        );
+        assert_eq!(
+            view.search("hello", &Bytewise)?,
+            Lookup::Child(Offset(PAGE_SIZE))
+        );
+        assert_eq!(
+            view.search("help", &Bytewise)?,
+            Lookup::Child(Offset(2 * PAGE_SIZE))
+        );
+        assert_eq!(
+            view.search("zebra", &Bytewise)?,
+            Lookup::Child(Offset(3 * PAGE_SIZE))
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn view_search_uses_comparator() -> Result<(), Error> {
+        // The keys share the prefix "v" which the internal node stores once.
+        let internal = Node::new(
+            NodeType::Internal(
+                (1..=3).map(|idx| Offset(idx * PAGE_SIZE)).collect(),
+                vec![Key("v2".to_string()), Key("v10".to_string())],
+            ),
+            true,
+        );
+        let pairs = ["v2", "v9", "v10"]
+            .iter()
+            .enumerate()
+            .map(|(idx, key)| KeyValuePair::new(key.to_string(), idx))
+            .collect();
+        let leaf = Node::new(NodeType::Leaf(Offset(PAGE_SIZE), pairs), true);
+
+        let internal_page = Page::try_from(&internal)?;
+        let leaf_page = Page::try_from(&leaf)?;
+        let internal_view = NodeView::new(&internal_page, FORMAT_VERSION)?;
+        let leaf_view = NodeView::new(&leaf_page, FORMAT_VERSION)?;
+        assert_eq!(
+            internal_view.search("v9", &Natural)?,
+            Lookup::Child(Offset(2 * PAGE_SIZE))
+        );
+        assert_eq!(
+            internal_view.search("v11", &Natural)?,
+            Lookup::Child(Offset(3 * PAGE_SIZE))
+        );
+        assert_eq!(
+            leaf_view.search("v10", &Natural)?,
+            Lookup::Pair {
+                data_page: Offset(PAGE_SIZE),
+                idx: 2,
+                expiry: None,
+            }
+        );
+        Ok(())
+    }
+}
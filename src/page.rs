@@ -1,13 +1,20 @@
 use crate::data_page::DataPage;
 use crate::error::Error;
+use crate::header::{checksum, Header};
 use crate::node::Node;
 use crate::node_type::{Key, NodeType, Offset};
 use crate::page_layout::{
-    ToByte, DATA_PAGE_NUM_VALUES_OFFSET, INTERNAL_NODE_HEADER_SIZE,
-    INTERNAL_NODE_NUM_CHILDREN_OFFSET, INTERNAL_NODE_NUM_CHILDREN_SIZE, IS_ROOT_OFFSET, KEY_SIZE,
+    ToByte, B_PARAMETER_OFFSET, CATALOG_OFFSET, COMPARATOR_NAME_LEN_SIZE,
+    DATA_PAGE_FREE_END_OFFSET, DATA_PAGE_NUM_SLOTS_OFFSET, DATA_PAGE_SLOTTED_COMPRESSION_OFFSET,
+    DATA_PAGE_SLOTTED_HEADER_SIZE, DATA_PAGE_SLOT_FIELD_SIZE, DATA_PAGE_SLOT_SIZE,
+    ENCRYPTED_OFFSET, EXPIRY_SIZE, FORMAT_VERSION_OFFSET, FREE_LIST_HEAD_OFFSET,
+    HEADER_CHECKSUM_OFFSET, HEADER_SLOTS_FORMAT_VERSION, HEADER_SLOT_SEQ_OFFSET,
+    INDEX_NAME_LEN_SIZE, INTERNAL_NODE_HEADER_SIZE, INTERNAL_NODE_NUM_CHILDREN_OFFSET,
+    INTERNAL_NODE_NUM_CHILDREN_SIZE, IS_ROOT_OFFSET, KEY_LEN_SIZE, KEY_SIZE, LAST_SEQUENCE_OFFSET,
     LEAF_NODE_DATA_PAGE_OFFSET, LEAF_NODE_DATA_PAGE_OFFSET_SIZE, LEAF_NODE_HEADER_SIZE,
-    LEAF_NODE_NUM_PAIRS_OFFSET, LEAF_NODE_NUM_PAIRS_SIZE, NODE_TYPE_OFFSET, PAGE_SIZE,
-    PARENT_POINTER_OFFSET, PARENT_POINTER_SIZE, PTR_SIZE, VALUE_SIZE,
+    LEAF_NODE_NUM_PAIRS_OFFSET, LEAF_NODE_NUM_PAIRS_SIZE, MAGIC_NUMBER, MAGIC_NUMBER_OFFSET,
+    MAGIC_NUMBER_SIZE, MAX_KEY_PART_SIZE, NODE_TYPE_OFFSET, NUM_INDEXES_OFFSET, PAGE_SIZE,
+    PAGE_SIZE_OFFSET, PTR_SIZE, ROOT_OFFSET, VALUE_SIZE,
 };
 use std::convert::TryFrom;
 
@@ -33,15 +40,15 @@ impl Page {
         if offset > PAGE_SIZE - PTR_SIZE {
             return Err(Error::UnexpectedError);
         }
-        let bytes = value.to_be_bytes();
+        let bytes = (value as u64).to_be_bytes();
         self.data[offset..offset + PTR_SIZE].clone_from_slice(&bytes);
         Ok(())
     }
 
-    /// get_value_from_offset Fetches a value calculated as BigEndian, sized to usize.
+    /// get_value_from_offset Fetches a value calculated as a BigEndian u64.
     /// This function may error as the value might not fit into a usize.
     pub fn get_value_from_offset(&self, offset: usize) -> Result<usize, Error> {
-        let bytes = &self.data[offset..offset + PTR_SIZE];
+        let bytes = self.get_ptr_from_offset(offset, PTR_SIZE)?;
         let Value(res) = Value::try_from(bytes)?;
         Ok(res)
     }
@@ -77,9 +84,11 @@ impl Page {
         Ok(())
     }
 
-    /// get_ptr_from_offset Fetches a slice of bytes from certain offset and of certain size.
-    pub fn get_ptr_from_offset(&self, offset: usize, size: usize) -> &[u8] {
-        &self.data[offset..offset + size]
+    /// get_ptr_from_offset Fetches a slice of bytes from certain offset and of certain size,
+    /// a slice reaching past the end of the page results in an error.
+    pub fn get_ptr_from_offset(&self, offset: usize, size: usize) -> Result<&[u8], Error> {
+        let end = offset.checked_add(size).ok_or(Error::CorruptedPage)?;
+        self.data.get(offset..end).ok_or(Error::CorruptedPage)
     }
 
     /// get_data returns the underlying array.
@@ -88,6 +97,40 @@ impl Page {
     }
 }
 
+/// common_prefix_len returns the length in bytes of the longest prefix shared by all keys.
+fn common_prefix_len<'a>(mut keys: impl Iterator<Item = &'a str>) -> usize {
+    let first = match keys.next() {
+        Some(key) => key.as_bytes(),
+        None => return 0,
+    };
+    keys.fold(first.len(), |len, key| {
+        first[..len]
+            .iter()
+            .zip(key.as_bytes())
+            .take_while(|(a, b)| a == b)
+            .count()
+    })
+}
+
+/// write_key_part writes a prefix or the rest of a prefix compressed key as a length byte followed by its bytes.
+fn write_key_part(
+    data: &mut [u8; PAGE_SIZE],
+    page_offset: &mut usize,
+    part: &[u8],
+) -> Result<(), Error> {
+    if part.len() > MAX_KEY_PART_SIZE {
+        return Err(Error::KeyOverflowError);
+    }
+    if *page_offset + KEY_LEN_SIZE + part.len() > PAGE_SIZE {
+        return Err(Error::KeyOverflowError);
+    }
+    data[*page_offset] = part.len() as u8;
+    *page_offset += KEY_LEN_SIZE;
+    data[*page_offset..*page_offset + part.len()].clone_from_slice(part);
+    *page_offset += part.len();
+    Ok(())
+}
+
 /// Implement TryFrom<Box<Node>> for Page allowing for easier
 /// serialization of data from a Node to an on-disk formatted page.
 impl TryFrom<&Node> for Page {
@@ -100,70 +143,58 @@ impl TryFrom<&Node> for Page {
         // node_type byte
         data[NODE_TYPE_OFFSET] = u8::from(&node.node_type);
 
-        // parent offest
-        if !node.is_root {
-            match node.parent_offset {
-                Some(Offset(parent_offset)) => data
-                    [PARENT_POINTER_OFFSET..PARENT_POINTER_OFFSET + PARENT_POINTER_SIZE]
-                    .clone_from_slice(&parent_offset.to_be_bytes()),
-                // Expected an offset of an inner / leaf node.
-                None => return Err(Error::UnexpectedError),
-            };
-        }
-
         match &node.node_type {
             NodeType::Internal(child_offsets, keys) => {
                 data[INTERNAL_NODE_NUM_CHILDREN_OFFSET
                     ..INTERNAL_NODE_NUM_CHILDREN_OFFSET + INTERNAL_NODE_NUM_CHILDREN_SIZE]
-                    .clone_from_slice(&child_offsets.len().to_be_bytes());
+                    .clone_from_slice(&(child_offsets.len() as u64).to_be_bytes());
 
                 let mut page_offset = INTERNAL_NODE_HEADER_SIZE;
                 for Offset(child_offset) in child_offsets {
                     data[page_offset..page_offset + PTR_SIZE]
-                        .clone_from_slice(&child_offset.to_be_bytes());
+                        .clone_from_slice(&(*child_offset as u64).to_be_bytes());
                     page_offset += PTR_SIZE;
                 }
 
+                // The common prefix of the keys is written once followed by the rest of each key.
+                let prefix_len = common_prefix_len(keys.iter().map(|Key(key)| key.as_str()));
+                let prefix = keys
+                    .first()
+                    .map_or(&[][..], |Key(key)| &key.as_bytes()[..prefix_len]);
+                write_key_part(&mut data, &mut page_offset, prefix)?;
                 for Key(key) in keys {
-                    let key_bytes = key.as_bytes();
-                    let mut raw_key: [u8; KEY_SIZE] = [0x00; KEY_SIZE];
-                    if key_bytes.len() > KEY_SIZE {
-                        return Err(Error::KeyOverflowError);
-                    } else {
-                        for (i, byte) in key_bytes.iter().enumerate() {
-                            raw_key[i] = *byte;
-                        }
-                    }
-                    data[page_offset..page_offset + KEY_SIZE].clone_from_slice(&raw_key);
-                    page_offset += KEY_SIZE
+                    write_key_part(&mut data, &mut page_offset, &key.as_bytes()[prefix_len..])?;
                 }
             }
             NodeType::Leaf(Offset(offset), kv_pairs) => {
                 // data page offset
                 data[LEAF_NODE_DATA_PAGE_OFFSET
                     ..LEAF_NODE_DATA_PAGE_OFFSET + LEAF_NODE_DATA_PAGE_OFFSET_SIZE]
-                    .clone_from_slice(&offset.to_be_bytes());
+                    .clone_from_slice(&(*offset as u64).to_be_bytes());
 
                 // num of pairs
                 data[LEAF_NODE_NUM_PAIRS_OFFSET
                     ..LEAF_NODE_NUM_PAIRS_OFFSET + LEAF_NODE_NUM_PAIRS_SIZE]
-                    .clone_from_slice(&kv_pairs.len().to_be_bytes());
+                    .clone_from_slice(&(kv_pairs.len() as u64).to_be_bytes());
 
                 let mut page_offset = LEAF_NODE_HEADER_SIZE;
+                // The keys are prefix compressed the way the keys of internal nodes are.
+                let prefix_len = common_prefix_len(kv_pairs.iter().map(|pair| pair.key.as_str()));
+                let prefix = kv_pairs
+                    .first()
+                    .map_or(&[][..], |pair| &pair.key.as_bytes()[..prefix_len]);
+                write_key_part(&mut data, &mut page_offset, prefix)?;
                 for pair in kv_pairs {
-                    let key_bytes = pair.key.as_bytes();
-                    let mut raw_key: [u8; KEY_SIZE] = [0x00; KEY_SIZE];
-                    if key_bytes.len() > KEY_SIZE {
+                    write_key_part(
+                        &mut data,
+                        &mut page_offset,
+                        &pair.key.as_bytes()[prefix_len..],
+                    )?;
+                    if page_offset + VALUE_SIZE > PAGE_SIZE {
                         return Err(Error::KeyOverflowError);
-                    } else {
-                        for (i, byte) in key_bytes.iter().enumerate() {
-                            raw_key[i] = *byte;
-                        }
                     }
-                    data[page_offset..page_offset + KEY_SIZE].clone_from_slice(&raw_key);
-                    page_offset += KEY_SIZE;
 
-                    let value_bytes = pair.idx.to_be_bytes();
+                    let value_bytes = (pair.idx as u64).to_be_bytes();
                     let mut raw_value: [u8; VALUE_SIZE] = [0x00; VALUE_SIZE];
                     if value_bytes.len() > VALUE_SIZE {
                         return Err(Error::ValueOverflowError);
@@ -174,6 +205,13 @@ impl TryFrom<&Node> for Page {
                     }
                     data[page_offset..page_offset + VALUE_SIZE].clone_from_slice(&raw_value);
                     page_offset += VALUE_SIZE;
+
+                    if page_offset + EXPIRY_SIZE > PAGE_SIZE {
+                        return Err(Error::KeyOverflowError);
+                    }
+                    data[page_offset..page_offset + EXPIRY_SIZE]
+                        .clone_from_slice(&pair.expiry.unwrap_or(0).to_be_bytes());
+                    page_offset += EXPIRY_SIZE;
                 }
             }
             NodeType::Unexpected => return Err(Error::UnexpectedError),
@@ -184,7 +222,7 @@ impl TryFrom<&Node> for Page {
 }
 
 /// Attempts to convert a slice to an array of a fixed size (PTR_SIZE),
-/// and then return the BigEndian value of the byte array.
+/// and then return the BigEndian u64 value of the byte array as a usize.
 impl TryFrom<&[u8]> for Value {
     type Error = Error;
 
@@ -198,28 +236,52 @@ impl TryFrom<&[u8]> for Value {
             truncated_arr[i] = *item;
         }
 
-        Ok(Value(usize::from_be_bytes(truncated_arr)))
+        let value = usize::try_from(u64::from_be_bytes(truncated_arr))
+            .map_err(|_| Error::IntegerOverflowError)?;
+        Ok(Value(value))
     }
 }
 
+/// Implement TryFrom<&DataPage> for Page allowing for easier
+/// serialization of a data page to a slotted page, the values are packed against the end of the page.
 impl TryFrom<&DataPage> for Page {
     type Error = Error;
 
     fn try_from(page: &DataPage) -> Result<Self, Self::Error> {
         let mut data: [u8; PAGE_SIZE] = [0x00; PAGE_SIZE];
+        let u16_bytes = |value: usize| {
+            u16::try_from(value)
+                .map(u16::to_be_bytes)
+                .map_err(|_| Error::ValueOverflowError)
+        };
 
-        data[DATA_PAGE_NUM_VALUES_OFFSET] =
-            u8::try_from(page.values.len()).map_err(|_| Error::UnexpectedError)?;
-        let mut offset = 1;
-        for value in page.values.iter() {
-            let len = u8::try_from(value.len())
-                .map_err(|_| Error::ValueOverflowError)?
-                .to_be();
-            data[offset] = len;
-            offset += 1;
-            data[offset..offset + len as usize].clone_from_slice(value.as_bytes());
-            offset += len as usize;
+        let directory_end = DATA_PAGE_SLOTTED_HEADER_SIZE + page.slots.len() * DATA_PAGE_SLOT_SIZE;
+        if directory_end > PAGE_SIZE {
+            return Err(Error::ValueOverflowError);
+        }
+        data[DATA_PAGE_SLOTTED_COMPRESSION_OFFSET] = u8::from(page.compression());
+        data[DATA_PAGE_NUM_SLOTS_OFFSET..DATA_PAGE_NUM_SLOTS_OFFSET + DATA_PAGE_SLOT_FIELD_SIZE]
+            .clone_from_slice(&u16_bytes(page.slots.len())?);
+        let mut free_end = PAGE_SIZE;
+        for (idx, slot) in page.slots.iter().enumerate() {
+            // Tombstones are left zeroed.
+            let value = match slot {
+                Some(value) => page.compression().compress(value.as_bytes()),
+                None => continue,
+            };
+            if free_end - directory_end < value.len() {
+                return Err(Error::ValueOverflowError);
+            }
+            free_end -= value.len();
+            data[free_end..free_end + value.len()].clone_from_slice(&value);
+            let offset = DATA_PAGE_SLOTTED_HEADER_SIZE + idx * DATA_PAGE_SLOT_SIZE;
+            data[offset..offset + DATA_PAGE_SLOT_FIELD_SIZE]
+                .clone_from_slice(&u16_bytes(free_end)?);
+            data[offset + DATA_PAGE_SLOT_FIELD_SIZE..offset + DATA_PAGE_SLOT_SIZE]
+                .clone_from_slice(&u16_bytes(value.len())?);
         }
+        data[DATA_PAGE_FREE_END_OFFSET..DATA_PAGE_FREE_END_OFFSET + DATA_PAGE_SLOT_FIELD_SIZE]
+            .clone_from_slice(&u16_bytes(free_end)?);
 
         Ok(Self {
             data: Box::new(data),
@@ -227,6 +289,66 @@ impl TryFrom<&DataPage> for Page {
     }
 }
 
+/// Implement TryFrom<&Header> for Page allowing for easier
+/// serialization of the file header to the first page of the file.
+impl TryFrom<&Header> for Page {
+    type Error = Error;
+
+    fn try_from(header: &Header) -> Result<Self, Self::Error> {
+        let mut page = Page::new([0x00; PAGE_SIZE]);
+        page.write_bytes_at_offset(&MAGIC_NUMBER, MAGIC_NUMBER_OFFSET, MAGIC_NUMBER_SIZE)?;
+        page.write_value_at_offset(FORMAT_VERSION_OFFSET, header.version)?;
+        page.write_value_at_offset(PAGE_SIZE_OFFSET, header.page_size)?;
+        page.write_value_at_offset(B_PARAMETER_OFFSET, header.b)?;
+        page.write_value_at_offset(ROOT_OFFSET, header.root.0)?;
+        let Offset(free_list_head) = header.free_list_head.clone().unwrap_or(Offset(0));
+        page.write_value_at_offset(FREE_LIST_HEAD_OFFSET, free_list_head)?;
+        page.write_bytes_at_offset(&[header.encrypted.to_byte()], ENCRYPTED_OFFSET, 1)?;
+        page.write_bytes_at_offset(&[header.catalog.to_byte()], CATALOG_OFFSET, 1)?;
+        page.write_bytes_at_offset(
+            &header.last_seq.to_be_bytes(),
+            LAST_SEQUENCE_OFFSET,
+            PTR_SIZE,
+        )?;
+        page.write_value_at_offset(NUM_INDEXES_OFFSET, header.indexes.len())?;
+        let mut offset = NUM_INDEXES_OFFSET + PTR_SIZE;
+        for (name, Offset(root)) in header.indexes.iter() {
+            if name.len() > KEY_SIZE {
+                return Err(Error::KeyOverflowError);
+            }
+            if offset + INDEX_NAME_LEN_SIZE + name.len() + PTR_SIZE > HEADER_SLOT_SEQ_OFFSET {
+                return Err(Error::UnexpectedError);
+            }
+            page.write_bytes_at_offset(&[name.len() as u8], offset, INDEX_NAME_LEN_SIZE)?;
+            offset += INDEX_NAME_LEN_SIZE;
+            page.write_bytes_at_offset(name.as_bytes(), offset, name.len())?;
+            offset += name.len();
+            page.write_value_at_offset(offset, *root)?;
+            offset += PTR_SIZE;
+        }
+        let name = &header.comparator;
+        if name.len() > KEY_SIZE {
+            return Err(Error::KeyOverflowError);
+        }
+        if offset + COMPARATOR_NAME_LEN_SIZE + name.len() > HEADER_SLOT_SEQ_OFFSET {
+            return Err(Error::UnexpectedError);
+        }
+        page.write_bytes_at_offset(&[name.len() as u8], offset, COMPARATOR_NAME_LEN_SIZE)?;
+        offset += COMPARATOR_NAME_LEN_SIZE;
+        page.write_bytes_at_offset(name.as_bytes(), offset, name.len())?;
+        if header.version >= HEADER_SLOTS_FORMAT_VERSION {
+            page.write_bytes_at_offset(
+                &header.slot_seq.to_be_bytes(),
+                HEADER_SLOT_SEQ_OFFSET,
+                PTR_SIZE,
+            )?;
+            let checksum = checksum(page.get_ptr_from_offset(0, HEADER_CHECKSUM_OFFSET)?);
+            page.write_bytes_at_offset(&checksum.to_be_bytes(), HEADER_CHECKSUM_OFFSET, PTR_SIZE)?;
+        }
+        Ok(page)
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use std::convert::TryFrom;
@@ -248,7 +370,7 @@ mod tests {
             KeyValuePair::new("ariana".to_string(), 40),
         ];
 
-        let some_leaf = Node::new(NodeType::Leaf(Offset(0), key_values.clone()), true, None);
+        let some_leaf = Node::new(NodeType::Leaf(Offset(0), key_values.clone()), true);
 
         // Serialize data.
         let page = Page::try_from(&some_leaf)?;
@@ -262,7 +384,6 @@ mod tests {
 
         assert_eq!(res.is_root, some_leaf.is_root);
         assert_eq!(pairs, key_values);
-        assert_eq!(res.parent_offset, some_leaf.parent_offset);
         Ok(())
     }
 
@@ -289,7 +410,6 @@ mod tests {
                 ],
             ),
             true,
-            None,
         );
 
         // Serialize data.
@@ -299,21 +419,141 @@ mod tests {
 
         assert_eq!(res.is_root, internal_node.is_root);
         assert_eq!(res.node_type, internal_node.node_type);
-        assert_eq!(res.parent_offset, internal_node.parent_offset);
         Ok(())
     }
 
     #[test]
     fn data_page_to_page_works() -> Result<(), Error> {
         let mut data_page = DataPage::new();
-        data_page.values.push("foo".into());
-        data_page.values.push("bar".into());
-        data_page.values.push("baz".into());
+        data_page.insert("foo".into())?;
+        data_page.insert("bar".into())?;
+        data_page.insert("baz".into())?;
 
         let page = Page::try_from(&data_page)?;
         let res = DataPage::try_from(page)?;
 
-        assert_eq!(data_page.values, res.values);
+        assert_eq!(data_page.slots, res.slots);
+        Ok(())
+    }
+
+    #[test]
+    fn data_page_slots_are_stable() -> Result<(), Error> {
+        use crate::page_layout::FORMAT_VERSION;
+
+        let mut data_page = DataPage::new();
+        let foo = data_page.insert("foo".into())?;
+        let bar = data_page.insert("bar".into())?;
+        let baz = data_page.insert("baz".into())?;
+
+        // Removing a value leaves a tombstone, the slots following it keep their ids.
+        assert_eq!(data_page.remove(bar), Some("bar".to_string()));
+        let page = Page::try_from(&data_page)?;
+        assert_eq!(DataPage::read_value(&page, FORMAT_VERSION, foo)?, "foo");
+        assert_eq!(DataPage::read_value(&page, FORMAT_VERSION, baz)?, "baz");
+        assert!(DataPage::read_value(&page, FORMAT_VERSION, bar).is_err());
+
+        // The tombstone is reused by the next value.
+        let mut res = DataPage::try_from(page)?;
+        assert_eq!(
+            res.slots,
+            vec![Some("foo".to_string()), None, Some("baz".to_string())]
+        );
+        assert_eq!(res.insert("zap".into())?, bar);
+        assert_eq!(res.remove(baz), Some("baz".to_string()));
+        assert_eq!(res.slots.len(), 2);
+        Ok(())
+    }
+
+    #[test]
+    fn data_page_overflow_is_detected() -> Result<(), Error> {
+        use crate::page_layout::{DATA_PAGE_SLOTTED_HEADER_SIZE, DATA_PAGE_SLOT_SIZE, PAGE_SIZE};
+
+        let mut data_page = DataPage::new();
+        data_page.insert("a".repeat(2048))?;
+        // A value which does not fit is refused when it is inserted, the page is left as it was.
+        let res = data_page.insert("b".repeat(2048));
+        assert!(matches!(res, Err(Error::ValueOverflowError)));
+        assert_eq!(data_page.slots, vec![Some("a".repeat(2048))]);
+        let free_space = data_page.free_space();
+        assert_eq!(
+            free_space,
+            PAGE_SIZE - DATA_PAGE_SLOTTED_HEADER_SIZE - DATA_PAGE_SLOT_SIZE - 2048
+        );
+
+        // The page is filled up to its last byte.
+        data_page.insert("b".repeat(free_space - DATA_PAGE_SLOT_SIZE))?;
+        assert_eq!(data_page.free_space(), 0);
+        let res = DataPage::try_from(Page::try_from(&data_page)?)?;
+        assert_eq!(data_page.slots, res.slots);
+        assert_eq!(res.free_space(), 0);
+
+        // Removing a value frees its bytes along with its slot.
+        data_page.remove(1);
+        assert_eq!(data_page.free_space(), free_space);
+        Ok(())
+    }
+
+    #[test]
+    fn compressed_data_page_to_page_works() -> Result<(), Error> {
+        use crate::compression::Compression;
+
+        let mut data_page = DataPage::new();
+        data_page.set_compression(Compression::Lz);
+        // Values longer than a length byte can hold fit once compressed.
+        data_page.insert(r#"{"status":"active"}"#.repeat(20))?;
+        data_page.insert("bar".into())?;
+
+        let page = Page::try_from(&data_page)?;
+        let res = DataPage::try_from(page)?;
+
+        assert_eq!(res.compression(), Compression::Lz);
+        assert_eq!(data_page.slots, res.slots);
+        Ok(())
+    }
+
+    #[test]
+    fn data_page_decoding_works_for_uncompressed_layout() -> Result<(), Error> {
+        use crate::page_layout::PAGE_SIZE;
+
+        let mut data = [0x00; PAGE_SIZE];
+        // Up until format version 3 the number of values was directly followed by the values.
+        data[..9].clone_from_slice(&[0x02, 0x03, b'f', b'o', b'o', 0x03, b'b', b'a', b'r']);
+        let res = DataPage::decode(Page::new(data), 2)?;
+
+        assert_eq!(
+            res.slots,
+            vec![Some("foo".to_string()), Some("bar".to_string())]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn node_to_page_uses_fixed_width_integers() -> Result<(), Error> {
+        use crate::node::Node;
+        use crate::node_type::{Key, NodeType};
+        use crate::page_layout::{INTERNAL_NODE_HEADER_SIZE, INTERNAL_NODE_NUM_CHILDREN_OFFSET};
+
+        let internal_node = Node::new(
+            NodeType::Internal(
+                vec![Offset(0x1000), Offset(0x0102_0304)],
+                vec![Key("foo".to_string())],
+            ),
+            true,
+        );
+        let page = Page::try_from(&internal_node)?;
+
+        // Counts and offsets are always eight BigEndian bytes.
+        assert_eq!(
+            page.get_ptr_from_offset(INTERNAL_NODE_NUM_CHILDREN_OFFSET, 8)?,
+            [0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x02]
+        );
+        assert_eq!(
+            page.get_ptr_from_offset(INTERNAL_NODE_HEADER_SIZE, 16)?,
+            [
+                0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x10, 0x00, //
+                0x00, 0x00, 0x00, 0x00, 0x01, 0x02, 0x03, 0x04, //
+            ]
+        );
         Ok(())
     }
 }
@@ -1,22 +1,38 @@
 use crate::btree::MAX_BRANCHING_FACTOR;
+use crate::encryption::TAG_SIZE;
 use std::mem::size_of;
 
 /// A single page size.
 /// Each page represents a node in the BTree.
 pub const PAGE_SIZE: usize = 4096;
 
-pub const PTR_SIZE: usize = size_of::<usize>();
+/// Offsets and counts are written to disk as BigEndian u64 regardless of the host's pointer width,
+/// this allows for files to be moved between 32 and 64 bit machines.
+pub const PTR_SIZE: usize = size_of::<u64>();
 
-/// Common Node header layout (Ten bytes in total)
+/// Common Node header layout (Two bytes in total)
 pub const IS_ROOT_SIZE: usize = 1;
 pub const IS_ROOT_OFFSET: usize = 0;
 pub const NODE_TYPE_SIZE: usize = 1;
 pub const NODE_TYPE_OFFSET: usize = 1;
-pub const PARENT_POINTER_OFFSET: usize = 2;
+pub const COMMON_NODE_HEADER_SIZE: usize = NODE_TYPE_SIZE + IS_ROOT_SIZE;
+
+/// Up until format version 6 the common node header was followed by a pointer to the parent of the node,
+/// the fields following it are found PARENT_POINTER_SIZE bytes further in nodes written using older versions.
 pub const PARENT_POINTER_SIZE: usize = PTR_SIZE;
-pub const COMMON_NODE_HEADER_SIZE: usize = NODE_TYPE_SIZE + IS_ROOT_SIZE + PARENT_POINTER_SIZE;
+/// The first format version storing nodes without a parent pointer.
+pub const PARENTLESS_FORMAT_VERSION: usize = 6;
 
-/// Leaf node header layout (26 bytes in total)
+/// parent_pointer_size returns the size of the parent pointer of nodes written using the given format version.
+pub const fn parent_pointer_size(version: usize) -> usize {
+    if version < PARENTLESS_FORMAT_VERSION {
+        PARENT_POINTER_SIZE
+    } else {
+        0
+    }
+}
+
+/// Leaf node header layout (18 bytes in total)
 pub const LEAF_NODE_DATA_PAGE_OFFSET: usize = COMMON_NODE_HEADER_SIZE;
 pub const LEAF_NODE_DATA_PAGE_OFFSET_SIZE: usize = PTR_SIZE;
 pub const LEAF_NODE_NUM_PAIRS_OFFSET: usize =
@@ -25,37 +41,152 @@ pub const LEAF_NODE_NUM_PAIRS_SIZE: usize = PTR_SIZE;
 pub const LEAF_NODE_HEADER_SIZE: usize =
     COMMON_NODE_HEADER_SIZE + LEAF_NODE_DATA_PAGE_OFFSET_SIZE + LEAF_NODE_NUM_PAIRS_SIZE;
 
-/// Data page layout
+/// Data page layout (up until format version 5)
+/// The number of values is followed by the compression codec byte (since format version 3),
+/// each value is then stored as a length byte followed by the (compressed) value.
 pub const DATA_PAGE_NUM_VALUES_OFFSET: usize = 0;
+pub const DATA_PAGE_COMPRESSION_OFFSET: usize = 1;
+pub const DATA_PAGE_HEADER_SIZE: usize = 2;
+/// The first format version recording the compression codec in data pages.
+pub const COMPRESSION_FORMAT_VERSION: usize = 3;
+
+/// Slotted data page layout (since format version 5):
+/// The compression codec byte is followed by the number of slots and the offset of the first value byte,
+/// which is where the free space ends. The slot directory follows the header, each slot holds the offset
+/// and the length of its value, a slot whose offset is zero is a tombstone left by a removed value.
+/// Values are stored from the end of the page backwards so the free space lies between the directory and the values.
+pub const DATA_PAGE_SLOTTED_COMPRESSION_OFFSET: usize = 0;
+pub const DATA_PAGE_NUM_SLOTS_OFFSET: usize = 1;
+pub const DATA_PAGE_FREE_END_OFFSET: usize = 3;
+pub const DATA_PAGE_SLOTTED_HEADER_SIZE: usize = 5;
+/// Slot offsets and lengths, like the number of slots and the free space end, are BigEndian u16.
+pub const DATA_PAGE_SLOT_FIELD_SIZE: usize = 2;
+pub const DATA_PAGE_SLOT_SIZE: usize = 2 * DATA_PAGE_SLOT_FIELD_SIZE;
+/// The first format version storing slotted data pages.
+pub const SLOTTED_DATA_PAGE_FORMAT_VERSION: usize = 5;
 
-/// Internal header layout (Eighteen bytes in total)
+/// Internal header layout (Ten bytes in total)
 ///
-// Space for children and keys: PAGE_SIZE - INTERNAL_NODE_HEADER_SIZE = 4096 - 18 = 4078 bytes.
+// Space for children and keys: PAGE_SIZE - INTERNAL_NODE_HEADER_SIZE = 4096 - 10 = 4086 bytes.
 pub const INTERNAL_NODE_NUM_CHILDREN_OFFSET: usize = COMMON_NODE_HEADER_SIZE;
 pub const INTERNAL_NODE_NUM_CHILDREN_SIZE: usize = PTR_SIZE;
 pub const INTERNAL_NODE_HEADER_SIZE: usize =
     COMMON_NODE_HEADER_SIZE + INTERNAL_NODE_NUM_CHILDREN_SIZE;
 
-/// On a 64 bit machine the maximum space to keep all of the pointer
-/// is 200 * 8 = 1600 bytes.
+/// The maximum space to keep all of the pointers is 200 * 8 = 1600 bytes.
 #[allow(dead_code)]
 pub const MAX_SPACE_FOR_CHILDREN: usize = MAX_BRANCHING_FACTOR * PTR_SIZE;
 
-/// This leaves the keys of an internal node 2478 bytes:
-/// We use 1990 bytes for keys which leaves 488 bytes as junk.
-/// This means each key is limited to 12 bytes. (2476 / keys limit = ~12)
-/// Rounded down to 10 to accomodate the leave node.
+/// This leaves the keys of an internal node 2486 bytes,
+/// keys are prefix compressed so the space taken by each key depends on how much it shares with its neighbours.
 #[allow(dead_code)]
 pub const MAX_SPACE_FOR_KEYS: usize =
     PAGE_SIZE - INTERNAL_NODE_HEADER_SIZE - MAX_SPACE_FOR_CHILDREN;
 
 /// Key, Value sizes.
+/// Leaf keys occupied KEY_SIZE bytes padded with zeros up until format version 8,
+/// the names of the indexes and of the comparator are still limited to KEY_SIZE bytes.
 pub const KEY_SIZE: usize = 32;
 pub const VALUE_SIZE: usize = 8;
 
+/// Leaf node pairs layout (since format version 4), each key and value index is followed by
+/// the expiry of the pair in milliseconds since the unix epoch, zero if the pair never expires.
+pub const EXPIRY_SIZE: usize = PTR_SIZE;
+/// The first format version storing the expiry of leaf pairs.
+pub const EXPIRY_FORMAT_VERSION: usize = 4;
+
+/// Leaf node pairs layout (since format version 8), following the leaf header:
+/// The common prefix of all the keys is stored once as a length byte followed by the prefix bytes,
+/// then each pair is stored as a length byte followed by the bytes of its key following the prefix,
+/// the value index and the expiry.
+/// The first format version storing prefix compressed keys in leaf nodes.
+pub const LEAF_PREFIX_COMPRESSION_FORMAT_VERSION: usize = 8;
+
+/// min_leaf_pair_size returns the least number of bytes taken by a leaf pair written using the given format version.
+pub const fn min_leaf_pair_size(version: usize) -> usize {
+    if version < EXPIRY_FORMAT_VERSION {
+        KEY_SIZE + VALUE_SIZE
+    } else if version < LEAF_PREFIX_COMPRESSION_FORMAT_VERSION {
+        KEY_SIZE + VALUE_SIZE + EXPIRY_SIZE
+    } else {
+        KEY_LEN_SIZE + VALUE_SIZE + EXPIRY_SIZE
+    }
+}
+
+/// Internal node keys layout (since format version 2), following the children:
+/// The common prefix of all the keys is stored once as a length byte followed by the prefix bytes,
+/// then each key is stored as a length byte followed by the bytes following the prefix.
+/// The prefix and the rest of each key are thus at most MAX_KEY_PART_SIZE bytes long.
+pub const KEY_LEN_SIZE: usize = 1;
+pub const MAX_KEY_PART_SIZE: usize = u8::MAX as usize;
+/// The first format version storing prefix compressed keys in internal nodes.
+pub const PREFIX_COMPRESSION_FORMAT_VERSION: usize = 2;
+
+/// Catalog entries layout (since format version 9): the root of each named tree is stored as the value of its name,
+/// spelled out as CATALOG_ROOT_SIZE lowercase hex digits of its offset. Roots were decimal strings of any length before.
+pub const CATALOG_ROOT_SIZE: usize = 2 * PTR_SIZE;
+/// The first format version storing fixed width catalog roots.
+pub const FIXED_CATALOG_ROOT_FORMAT_VERSION: usize = 9;
+
+/// File header layout, the header occupies the first page of the tree file.
+/// Every field is eight bytes wide but the encrypted and catalog bytes (58 bytes in total),
+/// the roots of the secondary indexes follow along with the name of the comparator ordering the keys,
+/// the rest of the page is reserved but for the slot sequence number and the checksum ending it.
+/// Since format version 7 the first two pages of the file are header slots written alternately,
+/// so a header torn by a crash leaves the header of the previous commit intact in the other slot.
+pub const HEADER_PAGE_OFFSET: usize = 0;
+pub const MAGIC_NUMBER_OFFSET: usize = 0;
+pub const MAGIC_NUMBER_SIZE: usize = 8;
+pub const FORMAT_VERSION_OFFSET: usize = MAGIC_NUMBER_OFFSET + MAGIC_NUMBER_SIZE;
+pub const PAGE_SIZE_OFFSET: usize = FORMAT_VERSION_OFFSET + PTR_SIZE;
+pub const B_PARAMETER_OFFSET: usize = PAGE_SIZE_OFFSET + PTR_SIZE;
+pub const ROOT_OFFSET: usize = B_PARAMETER_OFFSET + PTR_SIZE;
+pub const FREE_LIST_HEAD_OFFSET: usize = ROOT_OFFSET + PTR_SIZE;
+/// One if the pages following the header are encrypted; otherwise - zero.
+pub const ENCRYPTED_OFFSET: usize = FREE_LIST_HEAD_OFFSET + PTR_SIZE;
+/// One if the root is the root of a catalog of named trees; otherwise - zero.
+pub const CATALOG_OFFSET: usize = ENCRYPTED_OFFSET + 1;
+/// The sequence number of the last committed change, see the change_log module.
+pub const LAST_SEQUENCE_OFFSET: usize = CATALOG_OFFSET + 1;
+/// The number of secondary indexes followed by each index as a name length byte, the name and its root offset.
+pub const NUM_INDEXES_OFFSET: usize = LAST_SEQUENCE_OFFSET + PTR_SIZE;
+pub const INDEX_NAME_LEN_SIZE: usize = 1;
+/// The comparator name follows the last index as a length byte and the name, files written before
+/// comparators were introduced have a zero length there and are ordered byte-wise.
+pub const COMPARATOR_NAME_LEN_SIZE: usize = 1;
+/// The slot sequence number is incremented by every header written, the valid slot holding the highest one
+/// holds the current header. The checksum is the CRC-32 of the bytes preceding it, both are zeros up until version 7.
+pub const HEADER_SLOT_SEQ_OFFSET: usize = PAGE_SIZE - 2 * PTR_SIZE;
+pub const HEADER_CHECKSUM_OFFSET: usize = PAGE_SIZE - PTR_SIZE;
+/// The first format version writing the header to two slots.
+pub const HEADER_SLOTS_FORMAT_VERSION: usize = 7;
+
+/// header_slots returns the number of header slots of files written using the given format version,
+/// the pages of the tree follow them.
+pub const fn header_slots(version: usize) -> usize {
+    if version < HEADER_SLOTS_FORMAT_VERSION {
+        1
+    } else {
+        2
+    }
+}
+
+/// Identifies a file as a tree file.
+pub const MAGIC_NUMBER: [u8; MAGIC_NUMBER_SIZE] = *b"BTREEDB\0";
+/// The version of the page layout described in this file, new pages are always written using it.
+pub const FORMAT_VERSION: usize = 9;
+/// The oldest page layout that can still be read, see the decoder module.
+pub const MIN_FORMAT_VERSION: usize = 1;
+
+/// Encrypted page trailer layout, when encrypted each page is followed on disk by
+/// the generation of the write (used to derive the nonce) and the authentication tag.
+pub const PAGE_GENERATION_SIZE: usize = 8;
+pub const PAGE_TRAILER_SIZE: usize = PAGE_GENERATION_SIZE + TAG_SIZE;
+
 /// Wrappers for converting byte to bool and back.
 /// The convention used throughout the index file is: one is true; otherwise - false.
 pub trait FromByte {
+    #[allow(clippy::wrong_self_convention)]
     fn from_byte(&self) -> bool;
 }
 
@@ -1,50 +1,194 @@
+use crate::encryption::{initial_generation, nonce, Cipher, TAG_SIZE};
 use crate::error::Error;
+use crate::header::Header;
 use crate::node_type::Offset;
 use crate::page::Page;
-use crate::page_layout::PAGE_SIZE;
-use std::fs::{File, OpenOptions};
-use std::io::Write;
-use std::io::{Read, Seek, SeekFrom};
-use std::path::Path;
+use crate::page_layout::{
+    header_slots, HEADER_PAGE_OFFSET, PAGE_GENERATION_SIZE, PAGE_SIZE, PAGE_TRAILER_SIZE,
+};
+use crate::stats::Counters;
+use crate::storage::Storage;
+use std::cmp;
+use std::convert::TryFrom;
+use std::sync::Arc;
 
 pub struct Pager {
-    file: File,
+    storage: Box<dyn Storage>,
     cursor: usize,
+    /// When set, pages are encrypted and authenticated, see set_cipher.
+    cipher: Option<Arc<dyn Cipher>>,
+    generation: u64,
+    /// Counts the bytes written, see set_counters.
+    counters: Arc<Counters>,
 }
 
 impl Pager {
-    pub fn new(path: &Path) -> Result<Pager, Error> {
-        let fd = OpenOptions::new()
-            .create(true)
-            .read(true)
-            .write(true)
-            .truncate(true)
-            .open(path)?;
+    /// new stores pages on the given storage,
+    /// new pages are appended after the existing ones.
+    pub fn new(mut storage: Box<dyn Storage>) -> Result<Pager, Error> {
+        let len = usize::try_from(storage.len()?).map_err(|_| Error::IntegerOverflowError)?;
+        // A page which was only partially written is overwritten by the next page.
+        let cursor = len / PAGE_SIZE * PAGE_SIZE;
 
         Ok(Pager {
-            file: fd,
-            cursor: 0,
+            storage,
+            cursor,
+            cipher: None,
+            generation: initial_generation(),
+            counters: Arc::new(Counters::default()),
         })
     }
 
+    /// set_cipher encrypts every page but the header using the given cipher.
+    /// Each encrypted page is followed on disk by a trailer holding the generation of the write
+    /// and the authentication tag, the nonce is derived from the page offset and the generation.
+    /// The header is kept in plain text so the file can be identified without the key,
+    /// it is authenticated using a tag stored in its trailer.
+    /// Offsets remain multiples of PAGE_SIZE, they are mapped to the larger slots on disk.
+    pub fn set_cipher(&mut self, cipher: Arc<dyn Cipher>) -> Result<(), Error> {
+        let len = usize::try_from(self.storage.len()?).map_err(|_| Error::IntegerOverflowError)?;
+        let slot_size = PAGE_SIZE + PAGE_TRAILER_SIZE;
+        self.cursor = len / slot_size * PAGE_SIZE;
+        self.cipher = Some(cipher);
+        Ok(())
+    }
+
+    /// set_counters records the bytes written to the given counters.
+    pub fn set_counters(&mut self, counters: Arc<Counters>) {
+        self.counters = counters;
+    }
+
+    /// cipher returns the cipher encrypting the pages, if any.
+    pub fn cipher(&self) -> Option<Arc<dyn Cipher>> {
+        self.cipher.clone()
+    }
+
+    /// is_empty returns true if no page was ever written to the file.
+    pub fn is_empty(&self) -> bool {
+        self.cursor == 0
+    }
+
+    /// len returns the offset following the last page of the file.
+    pub fn len(&self) -> usize {
+        self.cursor
+    }
+
+    /// sync makes the pages written so far durable.
+    pub fn sync(&mut self) -> Result<(), Error> {
+        self.storage.sync()
+    }
+
+    fn physical_offset(&self, offset: &Offset) -> u64 {
+        match self.cipher {
+            Some(_) => (offset.0 / PAGE_SIZE * (PAGE_SIZE + PAGE_TRAILER_SIZE)) as u64,
+            None => offset.0 as u64,
+        }
+    }
+
     pub fn get_page(&mut self, offset: &Offset) -> Result<Page, Error> {
+        self.read_page(offset, false, true)
+    }
+
+    /// read_header returns the newest valid header amongst the header slots, see Header::slot.
+    /// Once a cipher is set a slot is only valid if it is authentic, unless authenticate is false
+    /// as when the header is read to tell whether the file is encrypted in the first place.
+    /// The error of the first slot is returned when no slot is valid.
+    pub fn read_header(&mut self, authenticate: bool) -> Result<Header, Error> {
+        let first = self.read_header_slot(&Offset(HEADER_PAGE_OFFSET), authenticate);
+        // Files written before header slots were introduced have a single one.
+        if let Ok(header) = &first {
+            if header_slots(header.version) == 1 {
+                return first;
+            }
+        }
+        let second = self.read_header_slot(&Offset(PAGE_SIZE), authenticate);
+        match (first, second) {
+            (Ok(first), Ok(second)) if second.slot_seq > first.slot_seq => Ok(second),
+            (Ok(header), _) | (Err(_), Ok(header)) => Ok(header),
+            (Err(e), Err(_)) => Err(e),
+        }
+    }
+
+    fn read_header_slot(&mut self, offset: &Offset, authenticate: bool) -> Result<Header, Error> {
+        let header = Header::try_from(self.read_page(offset, true, authenticate)?)?;
+        if header.slot() != *offset {
+            return Err(Error::InvalidFileHeader);
+        }
+        Ok(header)
+    }
+
+    /// write_header writes the header to its slot, see Header::slot.
+    /// The header is kept in plain text, once a cipher is set it is authenticated.
+    pub fn write_header(&mut self, header: &Header) -> Result<(), Error> {
+        self.write_at(Page::try_from(header)?, &header.slot(), true)
+    }
+
+    /// read_page reads the page at offset, header pages are only authenticated while other pages are decrypted.
+    fn read_page(
+        &mut self,
+        offset: &Offset,
+        header: bool,
+        authenticate: bool,
+    ) -> Result<Page, Error> {
         let mut page: [u8; PAGE_SIZE] = [0x00; PAGE_SIZE];
-        self.file.seek(SeekFrom::Start(offset.0 as u64))?;
-        self.file.read_exact(&mut page)?;
+        let physical_offset = self.physical_offset(offset);
+        self.storage.read_at(physical_offset, &mut page)?;
+        if let (Some(cipher), true) = (&self.cipher, authenticate) {
+            let mut trailer = [0x00; PAGE_TRAILER_SIZE];
+            self.storage
+                .read_at(physical_offset + PAGE_SIZE as u64, &mut trailer)?;
+            let mut generation = [0x00; PAGE_GENERATION_SIZE];
+            generation.clone_from_slice(&trailer[..PAGE_GENERATION_SIZE]);
+            let mut tag = [0x00; TAG_SIZE];
+            tag.clone_from_slice(&trailer[PAGE_GENERATION_SIZE..]);
+            let nonce = nonce(offset.0 as u64, u64::from_be_bytes(generation));
+            if header {
+                cipher.decrypt(&nonce, &page, &mut [], &tag)?;
+            } else {
+                cipher.decrypt(&nonce, &[], &mut page, &tag)?;
+            }
+        }
         Ok(Page::new(page))
     }
 
     pub fn write_page(&mut self, page: Page) -> Result<Offset, Error> {
-        self.file.seek(SeekFrom::Start(self.cursor as u64))?;
-        self.file.write_all(&page.get_data())?;
         let res = Offset(self.cursor);
-        self.cursor += PAGE_SIZE;
+        self.write_page_at_offset(page, &res)?;
         Ok(res)
     }
 
     pub fn write_page_at_offset(&mut self, page: Page, offset: &Offset) -> Result<(), Error> {
-        self.file.seek(SeekFrom::Start(offset.0 as u64))?;
-        self.file.write_all(&page.get_data())?;
+        self.write_at(page, offset, false)
+    }
+
+    fn write_at(&mut self, page: Page, offset: &Offset, header: bool) -> Result<(), Error> {
+        let mut data = page.get_data();
+        let mut trailer = [0x00; PAGE_TRAILER_SIZE];
+        if let Some(cipher) = &self.cipher {
+            self.generation += 1;
+            let nonce = nonce(offset.0 as u64, self.generation);
+            let tag = if header {
+                cipher.encrypt(&nonce, &data, &mut [])?
+            } else {
+                cipher.encrypt(&nonce, &[], &mut data)?
+            };
+            trailer[..PAGE_GENERATION_SIZE].clone_from_slice(&self.generation.to_be_bytes());
+            trailer[PAGE_GENERATION_SIZE..].clone_from_slice(&tag);
+        }
+        let physical_offset = self.physical_offset(offset);
+        self.storage.write_at(physical_offset, &data)?;
+        let mut written = data.len();
+        if self.cipher.is_some() {
+            self.storage
+                .write_at(physical_offset + PAGE_SIZE as u64, &trailer)?;
+            written += trailer.len();
+        }
+        self.counters.written(written);
+        if offset.0 >= self.cursor {
+            self.counters.appended(written);
+        }
+        // Pages may be written past the end of the file when replicated, see BTree::apply_replication.
+        self.cursor = cmp::max(self.cursor, offset.0 + PAGE_SIZE);
         Ok(())
     }
 }
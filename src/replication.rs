@@ -0,0 +1,85 @@
+use crate::error::Error;
+use crate::node_type::Offset;
+use crate::page::Page;
+use crate::page_layout::{HEADER_PAGE_OFFSET, PAGE_SIZE, PTR_SIZE};
+use std::convert::TryFrom;
+use std::io::{ErrorKind, Read, Write};
+
+/// The replication stream is a sequence of batches, one per commit.
+/// A batch starts with the number of pages it holds, each page is written as its offset followed by its bytes,
+/// the batch ends with the header page of the commit which flips the follower to the new root.
+/// Pages are shipped decrypted, the stream should only be sent over a trusted channel.
+pub struct Replica {
+    writer: Box<dyn Write>,
+    /// The offset following the last page shipped to the replica.
+    pub len: usize,
+}
+
+impl Replica {
+    /// new creates a replica which has not been shipped any page but the header,
+    /// the pages of the tree follow the given number of header slots.
+    pub fn new(writer: Box<dyn Write>, header_slots: usize) -> Replica {
+        Replica {
+            writer,
+            len: header_slots * PAGE_SIZE,
+        }
+    }
+
+    /// begin_batch starts a batch of the given number of pages.
+    pub fn begin_batch(&mut self, num_pages: usize) -> Result<(), Error> {
+        self.writer.write_all(&(num_pages as u64).to_be_bytes())?;
+        Ok(())
+    }
+
+    /// write_page ships a single page of the current batch.
+    pub fn write_page(&mut self, offset: &Offset, page: &Page) -> Result<(), Error> {
+        self.writer.write_all(&offset.as_bytes())?;
+        self.writer.write_all(&page.get_data())?;
+        Ok(())
+    }
+
+    /// end_batch ends the current batch by shipping the header page.
+    pub fn end_batch(&mut self, header: &Page) -> Result<(), Error> {
+        self.writer.write_all(&header.get_data())?;
+        self.writer.flush()?;
+        Ok(())
+    }
+}
+
+fn read_u64(reader: &mut impl Read) -> Result<usize, Error> {
+    let mut raw = [0x00; PTR_SIZE];
+    reader.read_exact(&mut raw)?;
+    usize::try_from(u64::from_be_bytes(raw)).map_err(|_| Error::IntegerOverflowError)
+}
+
+fn read_page(reader: &mut impl Read) -> Result<Page, Error> {
+    let mut raw = [0x00; PAGE_SIZE];
+    reader.read_exact(&mut raw)?;
+    Ok(Page::new(raw))
+}
+
+/// Batch is the pages of a commit along with its header page.
+pub type Batch = (Vec<(Offset, Page)>, Page);
+
+/// read_batch reads the next batch of the stream, None is returned once the stream ends.
+pub fn read_batch(reader: &mut impl Read) -> Result<Option<Batch>, Error> {
+    let mut raw = [0x00; PTR_SIZE];
+    match reader.read_exact(&mut raw) {
+        Ok(()) => {}
+        Err(e) if e.kind() == ErrorKind::UnexpectedEof => return Ok(None),
+        Err(e) => return Err(e.into()),
+    }
+    let num_pages =
+        usize::try_from(u64::from_be_bytes(raw)).map_err(|_| Error::IntegerOverflowError)?;
+    let mut pages = vec![];
+    for _i in 0..num_pages {
+        let offset = read_u64(reader)?;
+        // The header is only ever replaced as a whole at the end of a batch, see BTree::apply_replication.
+        if offset == HEADER_PAGE_OFFSET || offset % PAGE_SIZE != 0 {
+            return Err(Error::UnexpectedError);
+        }
+        pages.push((Offset(offset), read_page(reader)?));
+    }
+    let header = read_page(reader)?;
+    Ok(Some((pages, header)))
+}
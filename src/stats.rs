@@ -0,0 +1,130 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+
+/// Counters are the operational counters of a tree, they are updated as the tree is used
+/// and may be read at any time, for example by a thread exporting them to a monitoring system.
+/// Counters only ever grow, rates are derived by comparing two snapshots.
+/// There is no page cache thus every page read is served by the storage, but for the pages of the pending commit
+/// which are read from memory, pages are counted as written once the commit writes them.
+#[derive(Debug, Default)]
+pub struct Counters {
+    node_pages_read: AtomicU64,
+    node_pages_written: AtomicU64,
+    data_pages_read: AtomicU64,
+    data_pages_written: AtomicU64,
+    bytes_written: AtomicU64,
+    bytes_appended: AtomicU64,
+    splits: AtomicU64,
+    merges: AtomicU64,
+    root_flips: AtomicU64,
+    wal_records: AtomicU64,
+    user_bytes: AtomicU64,
+    replicas_dropped: AtomicU64,
+}
+
+/// Stats is a snapshot of the counters of a tree, see BTree::stats.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Stats {
+    pub node_pages_read: u64,
+    pub node_pages_written: u64,
+    pub data_pages_read: u64,
+    pub data_pages_written: u64,
+    /// The bytes written to the tree file, the wal and the change log, encryption trailers included.
+    pub bytes_written: u64,
+    /// The bytes of the pages appended to the tree file.
+    pub bytes_appended: u64,
+    /// Nodes split because they were full, or overflowed once merged with a sibling.
+    pub splits: u64,
+    /// Nodes merged with a sibling because they underflowed.
+    pub merges: u64,
+    /// Roots replaced by a new root, when the root is split, or by their single child.
+    pub root_flips: u64,
+    pub wal_records: u64,
+    /// The bytes of the keys and values inserted to the tree, index entries excluded.
+    pub user_bytes: u64,
+    /// Replicas dropped because shipping a commit to them failed, see BTree::add_replica.
+    pub replicas_dropped: u64,
+}
+
+impl Stats {
+    /// write_amplification returns the bytes written per byte of user data, zero before any data is inserted.
+    pub fn write_amplification(&self) -> f64 {
+        if self.user_bytes == 0 {
+            return 0.0;
+        }
+        self.bytes_written as f64 / self.user_bytes as f64
+    }
+}
+
+impl Counters {
+    /// snapshot reads every counter, counters updated concurrently may be read before or after the update.
+    pub fn snapshot(&self) -> Stats {
+        let get = |counter: &AtomicU64| counter.load(Ordering::Relaxed);
+        Stats {
+            node_pages_read: get(&self.node_pages_read),
+            node_pages_written: get(&self.node_pages_written),
+            data_pages_read: get(&self.data_pages_read),
+            data_pages_written: get(&self.data_pages_written),
+            bytes_written: get(&self.bytes_written),
+            bytes_appended: get(&self.bytes_appended),
+            splits: get(&self.splits),
+            merges: get(&self.merges),
+            root_flips: get(&self.root_flips),
+            wal_records: get(&self.wal_records),
+            user_bytes: get(&self.user_bytes),
+            replicas_dropped: get(&self.replicas_dropped),
+        }
+    }
+
+    pub(crate) fn node_read(&self) {
+        self.node_pages_read.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub(crate) fn node_written(&self) {
+        self.node_pages_written.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub(crate) fn data_page_read(&self) {
+        self.data_pages_read.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub(crate) fn data_page_written(&self) {
+        self.data_pages_written.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// written records bytes written to any of the files of the tree.
+    pub(crate) fn written(&self, bytes: usize) {
+        self.bytes_written
+            .fetch_add(bytes as u64, Ordering::Relaxed);
+    }
+
+    /// appended records bytes appended to the tree file, they are recorded as written separately.
+    pub(crate) fn appended(&self, bytes: usize) {
+        self.bytes_appended
+            .fetch_add(bytes as u64, Ordering::Relaxed);
+    }
+
+    pub(crate) fn split(&self) {
+        self.splits.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub(crate) fn merge(&self) {
+        self.merges.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub(crate) fn root_flip(&self) {
+        self.root_flips.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub(crate) fn wal_record(&self, bytes: usize) {
+        self.wal_records.fetch_add(1, Ordering::Relaxed);
+        self.written(bytes);
+    }
+
+    pub(crate) fn user_data(&self, bytes: usize) {
+        self.user_bytes.fetch_add(bytes as u64, Ordering::Relaxed);
+    }
+
+    pub(crate) fn replica_dropped(&self) {
+        self.replicas_dropped.fetch_add(1, Ordering::Relaxed);
+    }
+}
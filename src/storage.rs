@@ -0,0 +1,350 @@
+use crate::error::Error;
+use std::cell::{Cell, RefCell};
+use std::collections::HashMap;
+use std::convert::TryFrom;
+use std::fs::{File, OpenOptions, TryLockError};
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+
+/// Storage is the medium a single file of a tree is stored on, it is addressed by byte offsets.
+/// The pager stores pages, which are followed by a trailer when encrypted, while the wal and the change log store records.
+pub trait Storage {
+    /// read_at fills buf with the bytes starting at offset, reading past the end is an error.
+    fn read_at(&mut self, offset: u64, buf: &mut [u8]) -> Result<(), Error>;
+
+    /// write_at writes buf at offset, extending the storage if needed.
+    fn write_at(&mut self, offset: u64, buf: &[u8]) -> Result<(), Error>;
+
+    /// append writes buf at the end of the storage and returns the offset it was written at.
+    fn append(&mut self, buf: &[u8]) -> Result<u64, Error> {
+        let offset = self.len()?;
+        self.write_at(offset, buf)?;
+        Ok(offset)
+    }
+
+    /// sync makes the writes made so far durable.
+    fn sync(&mut self) -> Result<(), Error>;
+
+    /// len returns the length of the storage in bytes.
+    fn len(&mut self) -> Result<u64, Error>;
+
+    /// set_len truncates or extends the storage to the given length.
+    fn set_len(&mut self, len: u64) -> Result<(), Error>;
+
+    /// is_empty returns true if nothing was ever written to the storage.
+    fn is_empty(&mut self) -> Result<bool, Error> {
+        Ok(self.len()? == 0)
+    }
+
+    /// lock takes an advisory lock on the storage which is held until the storage is dropped,
+    /// an exclusive lock excludes any other exclusive lock while shared locks exclude no lock,
+    /// so writers exclude each other while readers attach to a file a writer holds.
+    /// Fails with Error::Locked if a conflicting lock is held, storages which cannot be shared are never locked.
+    fn lock(&mut self, _exclusive: bool) -> Result<(), Error> {
+        Ok(())
+    }
+}
+
+/// Backend opens the storage of every file of a tree, the tree file itself
+/// along with its wal and change log which are named after it.
+pub trait Backend {
+    /// open opens the storage at the given path, creating an empty one if needed.
+    fn open(&self, path: &Path) -> Result<Box<dyn Storage>, Error>;
+
+    /// open_read_only opens the existing storage at the given path without write permission,
+    /// backends without permissions open it as usual.
+    fn open_read_only(&self, path: &Path) -> Result<Box<dyn Storage>, Error> {
+        self.open(path)
+    }
+}
+
+/// FileStorage stores a file on disk.
+pub struct FileStorage {
+    file: File,
+    path: PathBuf,
+    /// The lock file held by a writer, see lock.
+    lock_file: Option<File>,
+}
+
+impl FileStorage {
+    pub fn open(path: &Path) -> Result<FileStorage, Error> {
+        let file = OpenOptions::new()
+            .create(true)
+            .read(true)
+            .write(true)
+            .truncate(false)
+            .open(path)?;
+        Ok(FileStorage {
+            file,
+            path: path.to_path_buf(),
+            lock_file: None,
+        })
+    }
+
+    /// open_read_only opens an existing file for reading only.
+    pub fn open_read_only(path: &Path) -> Result<FileStorage, Error> {
+        let file = OpenOptions::new().read(true).open(path)?;
+        Ok(FileStorage {
+            file,
+            path: path.to_path_buf(),
+            lock_file: None,
+        })
+    }
+}
+
+impl Storage for FileStorage {
+    fn read_at(&mut self, offset: u64, buf: &mut [u8]) -> Result<(), Error> {
+        self.file.seek(SeekFrom::Start(offset))?;
+        self.file.read_exact(buf)?;
+        Ok(())
+    }
+
+    fn write_at(&mut self, offset: u64, buf: &[u8]) -> Result<(), Error> {
+        self.file.seek(SeekFrom::Start(offset))?;
+        self.file.write_all(buf)?;
+        Ok(())
+    }
+
+    fn sync(&mut self) -> Result<(), Error> {
+        self.file.sync_data()?;
+        Ok(())
+    }
+
+    fn len(&mut self) -> Result<u64, Error> {
+        Ok(self.file.metadata()?.len())
+    }
+
+    fn set_len(&mut self, len: u64) -> Result<(), Error> {
+        self.file.set_len(len)?;
+        Ok(())
+    }
+
+    /// lock locks the file shared, an exclusive lock is taken on the lock file named after the file instead
+    /// so it does not conflict with the shared locks.
+    fn lock(&mut self, exclusive: bool) -> Result<(), Error> {
+        if exclusive && self.lock_file.is_none() {
+            let mut lock_path = self.path.as_os_str().to_owned();
+            lock_path.push(".lock");
+            let lock_file = OpenOptions::new()
+                .create(true)
+                .write(true)
+                .truncate(false)
+                .open(lock_path)?;
+            try_lock(lock_file.try_lock())?;
+            self.lock_file = Some(lock_file);
+        }
+        try_lock(self.file.try_lock_shared())
+    }
+}
+
+/// try_lock maps the failure to take a lock held elsewhere to Error::Locked.
+fn try_lock(res: Result<(), TryLockError>) -> Result<(), Error> {
+    res.map_err(|e| match e {
+        TryLockError::WouldBlock => Error::Locked,
+        TryLockError::Error(e) => e.into(),
+    })
+}
+
+/// FileBackend stores the files of a tree on disk, it is the default backend.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct FileBackend;
+
+impl Backend for FileBackend {
+    fn open(&self, path: &Path) -> Result<Box<dyn Storage>, Error> {
+        Ok(Box::new(FileStorage::open(path)?))
+    }
+
+    fn open_read_only(&self, path: &Path) -> Result<Box<dyn Storage>, Error> {
+        Ok(Box::new(FileStorage::open_read_only(path)?))
+    }
+}
+
+/// MemoryStorage stores a file in memory, clones share the same bytes.
+#[derive(Clone, Debug, Default)]
+pub struct MemoryStorage {
+    data: Rc<RefCell<Vec<u8>>>,
+    /// Whether an exclusive lock is held on the file, shared by every storage of the file.
+    locked: Rc<Cell<bool>>,
+    /// The exclusive lock held by this storage, released once the storage and its clones are dropped.
+    lock: Option<Rc<MemoryLock>>,
+}
+
+/// MemoryLock is an exclusive lock on a file held in memory, it is released when dropped.
+#[derive(Debug)]
+struct MemoryLock(Rc<Cell<bool>>);
+
+impl Drop for MemoryLock {
+    fn drop(&mut self) {
+        self.0.set(false);
+    }
+}
+
+impl MemoryStorage {
+    pub fn new() -> MemoryStorage {
+        Self::default()
+    }
+}
+
+/// range returns the range of bytes of a buffer of the given length starting at offset.
+fn range(offset: u64, len: usize) -> Result<std::ops::Range<usize>, Error> {
+    let start = usize::try_from(offset).map_err(|_| Error::IntegerOverflowError)?;
+    let end = start.checked_add(len).ok_or(Error::IntegerOverflowError)?;
+    Ok(start..end)
+}
+
+impl Storage for MemoryStorage {
+    fn read_at(&mut self, offset: u64, buf: &mut [u8]) -> Result<(), Error> {
+        let data = self.data.borrow();
+        let bytes = data
+            .get(range(offset, buf.len())?)
+            .ok_or(Error::UnexpectedError)?;
+        buf.clone_from_slice(bytes);
+        Ok(())
+    }
+
+    fn write_at(&mut self, offset: u64, buf: &[u8]) -> Result<(), Error> {
+        let range = range(offset, buf.len())?;
+        let mut data = self.data.borrow_mut();
+        if data.len() < range.end {
+            data.resize(range.end, 0x00);
+        }
+        data[range].clone_from_slice(buf);
+        Ok(())
+    }
+
+    fn sync(&mut self) -> Result<(), Error> {
+        Ok(())
+    }
+
+    fn len(&mut self) -> Result<u64, Error> {
+        Ok(self.data.borrow().len() as u64)
+    }
+
+    fn set_len(&mut self, len: u64) -> Result<(), Error> {
+        let len = usize::try_from(len).map_err(|_| Error::IntegerOverflowError)?;
+        self.data.borrow_mut().resize(len, 0x00);
+        Ok(())
+    }
+
+    /// lock follows the rules of FileStorage::lock, shared locks exclude no lock so only exclusive ones are tracked.
+    fn lock(&mut self, exclusive: bool) -> Result<(), Error> {
+        if !exclusive || self.lock.is_some() {
+            return Ok(());
+        }
+        if self.locked.replace(true) {
+            return Err(Error::Locked);
+        }
+        self.lock = Some(Rc::new(MemoryLock(self.locked.clone())));
+        Ok(())
+    }
+}
+
+/// MemoryBackend keeps the files of trees in memory, clones share the same files and their locks
+/// so a tree can be reopened using a clone of the backend it was built with.
+#[derive(Clone, Debug, Default)]
+pub struct MemoryBackend {
+    files: Rc<RefCell<HashMap<PathBuf, MemoryStorage>>>,
+}
+
+impl MemoryBackend {
+    pub fn new() -> MemoryBackend {
+        Self::default()
+    }
+}
+
+impl Backend for MemoryBackend {
+    fn open(&self, path: &Path) -> Result<Box<dyn Storage>, Error> {
+        let storage = self
+            .files
+            .borrow_mut()
+            .entry(path.to_path_buf())
+            .or_default()
+            .clone();
+        Ok(Box::new(storage))
+    }
+}
+
+/// Reader reads a storage sequentially from its start up until the given length.
+pub(crate) struct Reader {
+    storage: Box<dyn Storage>,
+    position: u64,
+    len: u64,
+}
+
+impl Reader {
+    pub(crate) fn new(storage: Box<dyn Storage>, len: u64) -> Reader {
+        Reader {
+            storage,
+            position: 0,
+            len,
+        }
+    }
+}
+
+impl Read for Reader {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let remaining = self.len.saturating_sub(self.position);
+        let n = std::cmp::min(buf.len() as u64, remaining) as usize;
+        self.storage
+            .read_at(self.position, &mut buf[..n])
+            .map_err(|_| std::io::Error::from(std::io::ErrorKind::UnexpectedEof))?;
+        self.position += n as u64;
+        Ok(n)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::error::Error;
+
+    #[test]
+    fn memory_backend_works() -> Result<(), Error> {
+        use crate::btree::BTreeBuilder;
+        use crate::node_type::Key;
+        use crate::storage::MemoryBackend;
+        use std::path::Path;
+
+        let path = Path::new("/tmp/memory_backend_works/db");
+        let backend = MemoryBackend::new();
+        let builder = BTreeBuilder::new()
+            .path(path)
+            .b_parameter(2)
+            .backend(backend.clone());
+
+        let mut btree = builder.build()?;
+        for key in ["a", "b", "c", "d", "e"] {
+            btree.insert(key.to_string(), format!("value {}", key))?;
+        }
+        btree.delete(Key("b".to_string()))?;
+        // Locks follow the rules of files on disk, a writer excludes other writers but not readers.
+        assert!(matches!(builder.build(), Err(Error::Locked)));
+        let mut reader = BTreeBuilder::new()
+            .path(path)
+            .b_parameter(2)
+            .read_only(true)
+            .backend(backend.clone())
+            .build()?;
+        assert_eq!(reader.search("a".to_string())?, "value a");
+        drop(reader);
+        drop(btree);
+
+        // Clones of the backend share its files.
+        let mut btree = builder.build()?;
+        assert_eq!(btree.search("e".to_string())?, "value e");
+        let res = btree.search("b".to_string());
+        assert!(matches!(res, Err(Error::KeyNotFound)));
+        assert_eq!(btree.changes_since(0)?.count(), 6);
+        assert!(!path.exists());
+
+        // A separate backend holds separate files.
+        let mut btree = BTreeBuilder::new()
+            .path(path)
+            .b_parameter(2)
+            .backend(MemoryBackend::new())
+            .build()?;
+        let res = btree.search("e".to_string());
+        assert!(matches!(res, Err(Error::KeyNotFound)));
+        Ok(())
+    }
+}
@@ -1,42 +1,149 @@
+use crate::encryption::{initial_generation, nonce, Cipher, TAG_SIZE};
 use crate::error::Error;
 use crate::node_type::Offset;
-use crate::page_layout::PTR_SIZE;
+use crate::page_layout::{PAGE_GENERATION_SIZE, PTR_SIZE};
+use crate::storage::{MemoryStorage, Storage};
 use std::convert::TryFrom;
-use std::fs::{File, OpenOptions};
-use std::io::{Read, Seek, SeekFrom, Write};
-use std::path::PathBuf;
+use std::sync::Arc;
 
+/// The wal starts with a magic number, a wal without it was written by an older version
+/// which truncated the wal whenever the tree was opened, it is truncated once more.
+const WAL_MAGIC: [u8; 8] = *b"BTREEWAL";
+/// Each record holds a committed root, the sequence number of the last change committed along with it
+/// and the time of the commit.
+const RECORD_SIZE: usize = 3 * PTR_SIZE;
+/// Encrypted records are followed by the generation of the write and the authentication tag.
+const ENCRYPTED_RECORD_SIZE: usize = RECORD_SIZE + PAGE_GENERATION_SIZE + TAG_SIZE;
+/// Record nonces are derived from the record index with the high bit set,
+/// which keeps them apart from page nonces derived from page offsets.
+const RECORD_POSITION_FLAG: u64 = 1 << 63;
+
+/// Record is a root committed to the wal.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Record {
+    pub root: Offset,
+    /// The sequence number of the last change committed up until the root.
+    pub seq: u64,
+    /// The time of the commit in milliseconds since the unix epoch.
+    pub timestamp: u64,
+}
+
+/// Wal is the log of every root ever committed, the header of the tree holds the current root.
 pub struct Wal {
-    file: File,
+    storage: Box<dyn Storage>,
+    cipher: Option<Arc<dyn Cipher>>,
+    generation: u64,
 }
 
 impl Wal {
-    pub fn new(parent_directoy: PathBuf) -> Result<Self, Error> {
-        let fd = OpenOptions::new()
-            .create(true)
-            .read(true)
-            .write(true)
-            .truncate(true)
-            .open(parent_directoy.join("wal"))?;
-
-        Ok(Self { file: fd })
-    }
-
-    pub fn get_root(&mut self) -> Result<Offset, Error> {
-        let mut buff: [u8; PTR_SIZE] = [0x00; PTR_SIZE];
-        let file_len = self.file.seek(SeekFrom::End(0))? as usize;
-        let mut root_offset: usize = 0;
-        if file_len > 0 {
-            root_offset = (file_len / PTR_SIZE - 1) * PTR_SIZE;
+    /// new opens the wal stored on the given storage, when a cipher is given
+    /// the records are encrypted and authenticated. A record which was only partially written is dropped.
+    pub fn new(
+        mut storage: Box<dyn Storage>,
+        cipher: Option<Arc<dyn Cipher>>,
+    ) -> Result<Self, Error> {
+        let mut magic = [0x00; WAL_MAGIC.len()];
+        if storage.read_at(0, &mut magic).is_err() || magic != WAL_MAGIC {
+            storage.set_len(0)?;
+            storage.write_at(0, &WAL_MAGIC)?;
+        }
+        let mut wal = Self {
+            storage,
+            cipher,
+            generation: initial_generation(),
+        };
+        let len = wal.num_records()?;
+        wal.truncate(len)?;
+        Ok(wal)
+    }
+
+    /// read_only opens the wal stored on the given storage without ever modifying it,
+    /// the wal is copied to memory where partially written records are dropped.
+    pub fn read_only(
+        mut storage: Box<dyn Storage>,
+        cipher: Option<Arc<dyn Cipher>>,
+    ) -> Result<Self, Error> {
+        let len = usize::try_from(storage.len()?).map_err(|_| Error::IntegerOverflowError)?;
+        let mut bytes = vec![0x00; len];
+        storage.read_at(0, &mut bytes)?;
+        let mut copy = MemoryStorage::new();
+        copy.write_at(0, &bytes)?;
+        Self::new(Box::new(copy), cipher)
+    }
+
+    fn record_size(&self) -> usize {
+        match self.cipher {
+            Some(_) => ENCRYPTED_RECORD_SIZE,
+            None => RECORD_SIZE,
         }
-        self.file.seek(SeekFrom::Start(root_offset as u64))?;
-        self.file.read_exact(&mut buff)?;
-        Offset::try_from(buff)
     }
 
-    pub fn set_root(&mut self, offset: Offset) -> Result<(), Error> {
-        self.file.seek(SeekFrom::End(0))?;
-        self.file.write_all(&offset.0.to_be_bytes())?;
+    /// num_records returns the number of complete records.
+    pub fn num_records(&mut self) -> Result<usize, Error> {
+        let file_len = self.storage.len()?;
+        let file_len = usize::try_from(file_len).map_err(|_| Error::IntegerOverflowError)?;
+        Ok(file_len.saturating_sub(WAL_MAGIC.len()) / self.record_size())
+    }
+
+    /// truncate keeps the first num_records records dropping the ones following them.
+    pub fn truncate(&mut self, num_records: usize) -> Result<(), Error> {
+        let len = WAL_MAGIC.len() + num_records * self.record_size();
+        self.storage.set_len(len as u64)?;
         Ok(())
     }
+
+    fn read_record(&mut self, idx: usize) -> Result<Record, Error> {
+        let mut buff = [0x00; ENCRYPTED_RECORD_SIZE];
+        let record = &mut buff[..self.record_size()];
+        let position = WAL_MAGIC.len() + idx * record.len();
+        self.storage.read_at(position as u64, record)?;
+
+        let mut raw = [0x00; RECORD_SIZE];
+        raw.clone_from_slice(&record[..RECORD_SIZE]);
+        if let Some(cipher) = &self.cipher {
+            let mut generation = [0x00; PAGE_GENERATION_SIZE];
+            generation.clone_from_slice(&record[RECORD_SIZE..RECORD_SIZE + PAGE_GENERATION_SIZE]);
+            let mut tag = [0x00; TAG_SIZE];
+            tag.clone_from_slice(&record[RECORD_SIZE + PAGE_GENERATION_SIZE..]);
+            let position = RECORD_POSITION_FLAG | idx as u64;
+            let nonce = nonce(position, u64::from_be_bytes(generation));
+            cipher.decrypt(&nonce, &[], &mut raw, &tag)?;
+        }
+        let field = |idx: usize| {
+            let mut field = [0x00; PTR_SIZE];
+            field.clone_from_slice(&raw[idx * PTR_SIZE..(idx + 1) * PTR_SIZE]);
+            field
+        };
+        Ok(Record {
+            root: Offset::try_from(field(0))?,
+            seq: u64::from_be_bytes(field(1)),
+            timestamp: u64::from_be_bytes(field(2)),
+        })
+    }
+
+    /// records returns every record of the wal in the order they were committed.
+    pub fn records(&mut self) -> Result<Vec<Record>, Error> {
+        (0..self.num_records()?)
+            .map(|idx| self.read_record(idx))
+            .collect()
+    }
+
+    /// append appends the record returning the number of bytes written.
+    pub fn append(&mut self, record: &Record) -> Result<usize, Error> {
+        let idx = self.num_records()?;
+        let mut raw = record.root.as_bytes();
+        raw.extend_from_slice(&record.seq.to_be_bytes());
+        raw.extend_from_slice(&record.timestamp.to_be_bytes());
+        if let Some(cipher) = &self.cipher {
+            self.generation += 1;
+            let position = RECORD_POSITION_FLAG | idx as u64;
+            let tag = cipher.encrypt(&nonce(position, self.generation), &[], &mut raw)?;
+            raw.extend_from_slice(&self.generation.to_be_bytes());
+            raw.extend_from_slice(&tag);
+        }
+        // Records are written at their position, overwriting a record which was only partially written.
+        let position = WAL_MAGIC.len() + idx * self.record_size();
+        self.storage.write_at(position as u64, &raw)?;
+        Ok(raw.len())
+    }
 }